@@ -18,6 +18,7 @@ extern crate cbindgen;
 extern crate proc_macro_error;
 
 mod pclone;
+mod trace;
 mod root;
 mod cbinding;
 
@@ -27,6 +28,12 @@ pub fn derive_pclone(input: TokenStream) -> TokenStream {
     pclone::derive_pclone(input)
 }
 
+#[proc_macro_error]
+#[proc_macro_derive(Trace, attributes(pools))]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    trace::derive_trace(input)
+}
+
 #[proc_macro_error]
 #[proc_macro_derive(Root, attributes(pools))]
 pub fn derive_root(input: TokenStream) -> TokenStream {
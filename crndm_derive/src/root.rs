@@ -23,6 +23,9 @@ pub fn derive_root(input: TokenStream) -> TokenStream {
         // Generate an expression to sum up the heap size of each field.
         let sum = root_all_fields(&name, &input.data);
 
+        // Generate an expression to describe the structure of the type.
+        let schema = schema_all_fields(&input.data, &p);
+
         expanded.push(quote! {
             #[automatically_derived]
             #[allow(unused_qualifications)]
@@ -31,6 +34,14 @@ pub fn derive_root(input: TokenStream) -> TokenStream {
                 fn init(j: &corundum::stm::Journal<#p>) -> Self {
                     #sum
                 }
+
+                #[allow(unused_mut)]
+                fn schema() -> String {
+                    let mut s = format!("{} ({})", std::any::type_name::<Self>(),
+                        std::mem::size_of::<Self>());
+                    #schema
+                    s
+                }
             }
         });
     }
@@ -141,4 +152,58 @@ fn root_all_fields(ident: &Ident, data: &Data) -> TokenStream2 {
         }
         Data::Union(_) => panic!("Union types cannot derive RootObj"),
     }
-}
\ No newline at end of file
+}
+
+// Generate statements to append the name, offset, and schema of each field to
+// `s`. The offsets of enum fields are not known, so only their schemas are
+// included.
+fn schema_all_fields(data: &Data, p: &TokenStream2) -> TokenStream2 {
+    match *data {
+        Data::Struct(ref data) => {
+            let recurse = data.fields.iter().enumerate().map(|(i, f)| {
+                let ty = &f.ty;
+                let (member, label) = match &f.ident {
+                    Some(name) => (quote!(#name), name.to_string()),
+                    None => {
+                        let index = Index::from(i);
+                        (quote!(#index), i.to_string())
+                    }
+                };
+                quote_spanned! {f.span()=>
+                    let off = unsafe {
+                        let u = std::mem::MaybeUninit::<Self>::uninit();
+                        let base = u.as_ptr();
+                        std::ptr::addr_of!((*base).#member) as usize - base as usize
+                    };
+                    s += &format!(" {}@{}: {};", #label, off,
+                        <#ty as corundum::RootObj<#p>>::schema());
+                }
+            });
+            quote! {
+                s += " {";
+                #(#recurse)*
+                s += " }";
+            }
+        }
+        Data::Enum(DataEnum { ref variants, .. }) => {
+            let res = variants.iter().map(|v| {
+                let variant = v.ident.to_string();
+                let fields = v.fields.iter().map(|f| {
+                    let ty = &f.ty;
+                    quote_spanned! {f.span()=>
+                        s += &format!(" {};", <#ty as corundum::RootObj<#p>>::schema());
+                    }
+                });
+                quote! {
+                    s += &format!(" {} {{", #variant);
+                    #(#fields)*
+                    s += " }";
+                }
+            });
+            quote! {
+                #(#res)*
+            }
+        }
+        Data::Union(_) => panic!("Union types cannot derive RootObj"),
+    }
+}
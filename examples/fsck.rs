@@ -0,0 +1,34 @@
+use corundum::default::*;
+
+type P = Allocator;
+
+fn main() {
+    use std::env;
+    use std::vec::Vec as StdVec;
+
+    let args: StdVec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        println!("usage: {} [--repair] file-name", args[0]);
+        return;
+    }
+
+    let report = if args[1] == "--repair" && args.len() > 2 {
+        corundum::repair::<P>(&args[2])
+    } else {
+        corundum::check::<P>(&args[1])
+    };
+
+    match report {
+        Ok(report) => {
+            println!("{}", report);
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
@@ -10,10 +10,15 @@ fn main() {
     let args: StdVec<String> = env::args().collect();
 
     if args.len() < 2 {
-        println!("usage: {} file-name", args[0]);
+        println!("usage: {} [--json] file-name", args[0]);
         return;
     }
 
-    let _pool = P::open_no_root(&args[1], O_READINFO).unwrap();
-    P::print_info();
+    if args[1] == "--json" && args.len() > 2 {
+        let _pool = P::open_no_root(&args[2], O_READINFO).unwrap();
+        println!("{}", P::allocator_stats().unwrap().to_json());
+    } else {
+        let _pool = P::open_no_root(&args[1], O_READINFO).unwrap();
+        P::print_info();
+    }
 }
@@ -92,6 +92,9 @@ pub struct BuddyAlg<A: MemPool> {
     /// Log of available space
     available_log: usize,
 
+    /// The end offset of the region managed by this allocator
+    end: u64,
+
     #[cfg(feature = "stat_footprint")]
     /// The stat_footprint of memory usage in bytes
     foot_print: usize,
@@ -135,6 +138,7 @@ impl<A: MemPool> BuddyAlg<A> {
         self.available = self.size;
         self.buddies[idx] = base;
         self.last_idx = idx;
+        self.end = base + self.size as u64;
         self.log64.clear();
         self.drop_log.clear();
         self.aux.clear();
@@ -167,7 +171,7 @@ impl<A: MemPool> BuddyAlg<A> {
     }
 
     #[inline]
-    fn lock(&mut self) {
+    pub(crate) fn lock(&mut self) {
         unsafe { 
             // debug_assert!(self.aux.empty(), "locked before: aux is not empty");
 
@@ -182,7 +186,7 @@ impl<A: MemPool> BuddyAlg<A> {
     }
 
     #[inline]
-    fn unlock(&mut self) {
+    pub(crate) fn unlock(&mut self) {
         unsafe { 
             #[cfg(not(any(feature = "no_pthread", windows)))]
             libc::pthread_mutex_unlock(&mut self.mutex.0); 
@@ -200,8 +204,17 @@ impl<A: MemPool> BuddyAlg<A> {
 
     #[inline]
     /// Adds a new low-level `DropOnFailure` log entry
+    /// 
+    /// The caller may write into the new block before the changes are
+    /// performed, which overwrites the free list link of the block. Therefore,
+    /// the pending changes become valid here, so that the recovery procedure
+    /// redoes the allocation and then drops it, rather than discarding it.
     pub unsafe fn drop_on_failure(&mut self, off: u64, len: usize) {
         self.drop_log.push((off, len));
+        persist_obj(&self.drop_log, false);
+        persist_obj(&self.available_log, true);
+        self.aux_valid = true;
+        persist_obj(&self.aux_valid, true);
     }
 
     #[inline]
@@ -222,20 +235,29 @@ impl<A: MemPool> BuddyAlg<A> {
     /// [`alloc_impl`]: #method.alloc_impl
     /// [`dealloc_impl`]: #method.dealloc_impl
     pub fn drain_aux(&mut self) {
-        sfence();
+        // The changes should be durable before they become valid, so that the
+        // recovery procedure can redo them
+        persist_obj(&self.aux, false);
+        persist_obj(&self.log64, false);
+        persist_obj(&self.drop_log, false);
+        persist_obj(&self.available_log, true);
 
         self.aux_valid = true;
+        persist_obj(&self.aux_valid, true);
         self.aux.foreach(|(off, next)| {
             let n = Self::buddy(off);
             n.next = next;
+            persist_obj(&n.next, false);
         });
         self.aux.clear();
         self.log64.foreach(|(off, data)| unsafe {
             let n = Self::buddy(off);
             std::intrinsics::atomic_store_rel(&mut n.next, data);
+            persist_obj(&n.next, false);
         });
         self.log64.clear();
         self.available = self.available_log;
+        persist_obj(&self.available, true);
     }
 
     #[inline(always)]
@@ -250,9 +272,12 @@ impl<A: MemPool> BuddyAlg<A> {
     /// Materializes the changes in the auxiliary list and clears the drop log
     /// records
     pub unsafe fn perform(&mut self) {
-        self.drain_aux();
+        // Once the changes are durable, the recovery procedure completes them
+        // rather than dropping the new allocations
         self.drop_log.clear();
+        self.drain_aux();
         self.aux_valid = false;
+        persist_obj(&self.aux_valid, true);
         self.unlock();
     }
 
@@ -319,12 +344,42 @@ impl<A: MemPool> BuddyAlg<A> {
     /// If successful, it returns the offset of the available free block.
     /// Otherwise, `u64::MAX` is returned.
     pub unsafe fn alloc_impl(&mut self, len: usize, perform: bool) -> u64 {
+        self.begin();
+        let off = self.alloc_locked(len);
+        if off == u64::MAX {
+            self.discard();
+        } else {
+            self.finish(perform);
+        }
+        off
+    }
+
+    #[inline]
+    /// Locks the allocator and starts a new list of changes
+    pub(crate) unsafe fn begin(&mut self) {
         self.lock();
+        self.available_log = self.available;
+    }
+
+    #[inline]
+    /// Persists the list of changes, and materializes them if `perform` is set
+    pub(crate) unsafe fn finish(&mut self, perform: bool) {
+        self.aux.sync_all();
+        if perform {
+            self.perform();
+        }
+    }
+
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len` without locking the allocator or
+    /// materializing the changes. It should be called after [`begin`].
+    ///
+    /// [`begin`]: #method.begin
+    pub(crate) unsafe fn alloc_locked(&mut self, len: usize) -> u64 {
         let idx = get_idx(len);
         let len = 1 << idx;
 
-        if len > self.available {
-            self.discard();
+        if len > self.available_log {
             u64::MAX
         } else {
             match self.find_free_memory(idx, false) {
@@ -332,12 +387,7 @@ impl<A: MemPool> BuddyAlg<A> {
                     #[cfg(feature = "verbose")]
                     debug_alloc::<A>(off, len, self.used(), self.used() + (1 << idx));
 
-                    self.available_log = self.available - len;
-
-                    self.aux.sync_all();
-                    if perform {
-                        self.perform();
-                    }
+                    self.available_log -= len;
 
                     #[cfg(feature = "stat_footprint")]
                     {
@@ -355,33 +405,94 @@ impl<A: MemPool> BuddyAlg<A> {
                         len,
                         self.available()
                     );
-                    self.discard();
                     u64::MAX
                 }
             }
         }
     }
 
+    #[inline]
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len` at an address aligned to `align`, and
+    /// materialize them according to the `perform` argument. If successful, it
+    /// returns the offset of the available free block. Otherwise, `u64::MAX`
+    /// is returned.
+    ///
+    /// The allocated block can be reclaimed by [`dealloc_impl`] with the same
+    /// `len`, regardless of the alignment.
+    ///
+    /// [`dealloc_impl`]: #method.dealloc_impl
+    pub unsafe fn alloc_aligned_impl(&mut self, len: usize, align: usize, perform: bool) -> u64 {
+        self.begin();
+        let off = self.alloc_aligned_locked(len, align);
+        if off == u64::MAX {
+            self.discard();
+        } else {
+            self.finish(perform);
+        }
+        off
+    }
+
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len` aligned to `align` without locking the
+    /// allocator or materializing the changes. It should be called after
+    /// [`begin`].
+    ///
+    /// A block large enough to be aligned is taken, and the unused parts of it
+    /// are returned to the free lists right away. So, the remaining block is
+    /// the same as a block of size `len`, and it is reclaimed in the same way.
+    ///
+    /// The offsets of the blocks of at least `align` bytes are multiples of
+    /// `align`, so either all of them are aligned or none is, depending on
+    /// the start address of the pool. In the latter case, a smaller `len`
+    /// block at an aligned address is carved out of the taken block instead.
+    ///
+    /// [`begin`]: #method.begin
+    pub(crate) unsafe fn alloc_aligned_locked(&mut self, len: usize, align: usize) -> u64 {
+        let idx = get_idx(len);
+        let aidx = get_idx(len.max(align));
+        let off = self.alloc_locked(1 << aidx);
+        if off == u64::MAX {
+            return u64::MAX;
+        }
+        let align = align as u64;
+        let blk = off + (align - (A::start() + off) % align) % align;
+        if blk != off && (idx == aidx || blk % (1 << idx) != 0) {
+            return u64::MAX;
+        }
+        for i in idx..aidx {
+            // The buddy of the block of size `1 << i` which contains `blk`
+            self.free_impl((blk & !((1 << i) - 1)) ^ (1 << i), 1 << i);
+        }
+        blk
+    }
+
     #[inline]
     /// Generates required changes to the metadata for reclaiming the memory
     /// block at offset `off` with the size of `len`, and materialize them by
     /// calling [`drain_aux`](#methods.drain_aux) according to the `perform`
     /// argument.
     pub unsafe fn dealloc_impl(&mut self, off: u64, len: usize, perform: bool) {
-        self.lock();
+        self.begin();
+        self.dealloc_locked(off, len);
+        self.finish(perform);
+    }
+
+    #[inline]
+    /// Generates required changes to the metadata for reclaiming the memory
+    /// block at offset `off` with the size of `len` without locking the
+    /// allocator or materializing the changes. It should be called after
+    /// [`begin`].
+    ///
+    /// [`begin`]: #method.begin
+    pub(crate) unsafe fn dealloc_locked(&mut self, off: u64, len: usize) {
         let idx = get_idx(len);
         let len = 1 << idx;
 
         #[cfg(feature = "verbose")]
         debug_dealloc::<A>(off, len, self.used(), self.used() - len);
 
-        self.available_log = self.available;
         self.free_impl(off, len);
-
-        self.aux.sync_all();
-        if perform {
-            self.perform();
-        }
     }
 
     #[inline]
@@ -428,6 +539,45 @@ impl<A: MemPool> BuddyAlg<A> {
         self.available_log += len;
     }
 
+    /// Adds the free region `off..off+len` to the free lists
+    ///
+    /// The region is split into the largest aligned buddy blocks, and every
+    /// block is added in a separate failure-atomic section together with the
+    /// new `size` and `end` of the allocator. Therefore, a crash in the middle
+    /// leaves the allocator consistent, and the remaining region can be added
+    /// later by calling `extend(self.end(), ..)` again.
+    pub unsafe fn extend(&mut self, off: u64, len: usize) {
+        let end = off + len as u64;
+        let mut off = off;
+        while off < end {
+            let mut idx = if off == 0 { 63 } else { off.trailing_zeros() as usize };
+            while idx > 0 && (end - off) < (1 << idx) {
+                idx -= 1;
+            }
+            if (1 << idx) < mem::size_of::<Buddy>() {
+                break;
+            }
+            let blk = 1 << idx;
+
+            self.lock();
+            if idx > self.last_idx {
+                // Lists above `last_idx` are always empty, so it is safe to
+                // update it before the block is added
+                self.last_idx = idx;
+                persist_obj(&self.last_idx, true);
+            }
+            self.available_log = self.available;
+            self.free_impl(off, blk);
+            self.log(A::off_unchecked(&self.size), (self.size + blk) as u64);
+            self.log(A::off_unchecked(&self.end), off + blk as u64);
+            self.aux.sync_all();
+            self.log64.sync_all();
+            self.perform();
+
+            off += blk as u64;
+        }
+    }
+
     #[inline]
     /// Determines if the given address range is allocated
     pub fn is_allocated(&mut self, off: u64, _len: usize) -> bool {
@@ -484,6 +634,15 @@ impl<A: MemPool> BuddyAlg<A> {
     /// 
     /// [`DropOnFailure`]: ../alloc/trait.MemPool.html#method.drop_on_failure
     pub fn recover(&mut self) {
+        let self_mut = self as *mut Self;
+        self.recover_with(|off, len| unsafe {
+            (*self_mut).dealloc_impl(off, len, false);
+        });
+    }
+
+    /// Runs the recovery procedure using `dealloc` to reclaim the
+    /// allocations in the `DropOnFailure` logs
+    pub(crate) fn recover_with<F: FnMut(u64, usize)>(&mut self, mut dealloc: F) {
         #[cfg(not(any(feature = "no_pthread", windows)))] unsafe {
         crate::sync::init_lock(&mut self.mutex.0, &mut self.mutex.1);
         }
@@ -502,7 +661,7 @@ impl<A: MemPool> BuddyAlg<A> {
             eprintln!("Crashed while the allocator was operating");
 
             #[cfg(feature = "verbose")] {
-                if *crate::utils::VERBOSE {
+                if crate::utils::verbose() {
                     self.aux.foreach(|(off, next)| {
                         let n = Self::buddy(off);
                         println!("aux @({:x}) {:x} -> {:x}", off, n.next, next);
@@ -519,6 +678,12 @@ impl<A: MemPool> BuddyAlg<A> {
                 }
             }
 
+            // The allocations in the drop log were not performed, so the
+            // low-level logs of the same section are not valid
+            if !self.drop_log.is_empty() {
+                self.log64.clear();
+            }
+
             // continue draining
             self.drain_aux();
 
@@ -528,7 +693,7 @@ impl<A: MemPool> BuddyAlg<A> {
                 unsafe {
                     let self_mut = self as *mut Self;
                     self.drop_log.drain_atomic(|(off, len)| {
-                        (*self_mut).dealloc_impl(off, len, false);
+                        dealloc(off, len);
                     }, || {
                         (*self_mut).drain_aux();
                         (*self_mut).discard();
@@ -537,6 +702,9 @@ impl<A: MemPool> BuddyAlg<A> {
                 self.drop_log.clear();
             }
 
+            self.aux_valid = false;
+            persist_obj(&self.aux_valid, true);
+
             #[cfg(debug_assertions)]
             self.check(module_path!());
         } else {
@@ -592,6 +760,12 @@ impl<A: MemPool> BuddyAlg<A> {
         self.size
     }
 
+    #[inline]
+    /// Returns the end offset of the region managed by this allocator
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
     #[inline]
     /// Returns the total available space in the pool
     pub fn available(&self) -> usize {
@@ -611,6 +785,39 @@ impl<A: MemPool> BuddyAlg<A> {
         self.foot_print
     }
 
+    /// Returns the statistics of the free lists
+    pub fn stats(&mut self) -> ZoneStats {
+        self.lock();
+        let stats = self.read_stats();
+        self.unlock();
+        stats
+    }
+
+    /// Returns the statistics of the free lists without taking the lock
+    ///
+    /// It is for the pools which are not modified by this process, e.g. the
+    /// ones opened only to read their information, in which the lock may have
+    /// been left held by a crashed process.
+    pub fn read_stats(&self) -> ZoneStats {
+        let mut free_blocks = vec![0; self.last_idx + 1];
+        let mut largest_free_block = 0;
+        for (idx, count) in free_blocks.iter_mut().enumerate() {
+            let mut curr = self.buddies[idx];
+            while let Some(b) = off_to_option(curr) {
+                *count += 1;
+                largest_free_block = 1 << idx;
+                curr = Self::buddy(b).next;
+            }
+        }
+        ZoneStats {
+            size: self.size,
+            used: self.used(),
+            available: self.available,
+            largest_free_block,
+            free_blocks,
+        }
+    }
+
     pub fn check(&self, f: &str) {
         for idx in 3..self.last_idx + 1 {
             let mut curr = self.buddies[idx];
@@ -648,6 +855,140 @@ impl<A: MemPool> BuddyAlg<A> {
         true
     }
 
+    /// Validates the free lists of the zone which starts at offset `start`
+    ///
+    /// It checks that the free blocks are in the zone boundaries, the lists
+    /// are sorted and acyclic, the blocks do not overlap, and the available
+    /// space matches the free blocks. A description of every problem is
+    /// appended to `errors`. It returns the valid free blocks as
+    /// `(offset, order)` pairs, sorted by offset.
+    pub fn validate(&self, start: u64, errors: &mut Vec<String>) -> Vec<(u64, usize)> {
+        let mut blocks = vec![];
+        let mut visited = std::collections::HashSet::new();
+        for idx in 0..64 {
+            let len = 1u64 << idx;
+            let mut curr = self.buddies[idx];
+            if idx > self.last_idx && !is_none(curr) {
+                errors.push(format!("free list {} is above the last list {}", idx, self.last_idx));
+            }
+            let mut prev = None;
+            while let Some(b) = off_to_option(curr) {
+                if b < start || b.checked_add(len).map_or(true, |e| e > self.end)
+                    || !Self::in_range(b) {
+                    errors.push(format!("free block 0x{:x} in list {} is out of the zone \
+                        boundaries (0x{:x}..0x{:x})", b, idx, start, self.end));
+                    break;
+                }
+                if !visited.insert(b) {
+                    errors.push(format!("a cyclic link to block 0x{:x} detected in list {}", b, idx));
+                    break;
+                }
+                if prev.map_or(false, |p| p > b) {
+                    errors.push(format!("free list {} is not sorted at block 0x{:x}", idx, b));
+                }
+                blocks.push((b, idx));
+                prev = Some(b);
+                curr = Self::buddy(b).next;
+            }
+        }
+
+        blocks.sort_unstable();
+        let mut valid: Vec<(u64, usize)> = Vec::with_capacity(blocks.len());
+        for (b, idx) in blocks {
+            if let Some(&(p, pidx)) = valid.last() {
+                if p + (1u64 << pidx) > b {
+                    errors.push(format!("free blocks 0x{:x} ({} bytes) and 0x{:x} ({} bytes) overlap",
+                        p, 1u64 << pidx, b, 1u64 << idx));
+                    continue;
+                }
+            }
+            valid.push((b, idx));
+        }
+
+        let free: usize = valid.iter().map(|(_, idx)| 1usize << idx).sum();
+        if free != self.available {
+            errors.push(format!("available space is {} bytes, but free blocks have {} bytes",
+                self.available, free));
+        }
+        valid
+    }
+
+    /// Rebuilds the free lists of the zone which starts at offset `start` from
+    /// the given free blocks
+    ///
+    /// `blocks` contains `(offset, order)` pairs sorted by offset, such as
+    /// the ones returned by [`validate()`](#method.validate). Everything else
+    /// in the zone is considered allocated. The pending low-level logs are
+    /// discarded. It is not failure-atomic, and should be used only to repair
+    /// a closed pool.
+    pub unsafe fn rebuild(&mut self, _start: u64, blocks: &[(u64, usize)]) {
+        let mut tails = [u64::MAX; 64];
+        self.buddies = [u64::MAX; 64];
+        self.available = 0;
+        for &(b, idx) in blocks {
+            let e = Self::buddy(b);
+            e.next = u64::MAX;
+            persist_obj(e, false);
+            if let Some(t) = off_to_option(tails[idx]) {
+                let t = Self::buddy(t);
+                t.next = b;
+                persist_obj(t, false);
+            } else {
+                self.buddies[idx] = b;
+            }
+            tails[idx] = b;
+            self.available += 1 << idx;
+            self.last_idx = self.last_idx.max(idx);
+        }
+        self.available_log = self.available;
+        self.aux.clear();
+        self.log64.clear();
+        self.drop_log.clear();
+        self.aux_valid = false;
+        persist_obj(self, true);
+    }
+
+    /// Finds the allocated space of the zone which is not covered by the
+    /// reachable blocks
+    ///
+    /// `reachable` contains the `(offset, len)` pairs of the reachable
+    /// allocations. The allocated space is the region managed by the zone
+    /// minus the free blocks. The uncovered parts are split into the largest
+    /// aligned buddy blocks, and returned as `(offset, len)` pairs which can
+    /// be deallocated, sorted by offset.
+    pub fn leaks(&self, reachable: &[(u64, usize)]) -> Vec<(u64, usize)> {
+        let base = self.end - self.size as u64;
+        let mut used = vec![];
+        for idx in 0..self.last_idx + 1 {
+            let mut curr = self.buddies[idx];
+            while let Some(b) = off_to_option(curr) {
+                used.push((b, b + (1 << idx)));
+                curr = Self::buddy(b).next;
+            }
+        }
+        for &(off, len) in reachable {
+            if off >= base && off < self.end {
+                used.push((off, off + (1 << get_idx(len))));
+            }
+        }
+        used.sort_unstable();
+
+        let mut leaks = vec![];
+        let mut off = base;
+        for (start, end) in used.into_iter().chain(std::iter::once((self.end, self.end))) {
+            while off < start {
+                let mut idx = if off == 0 { 63 } else { off.trailing_zeros() as usize };
+                while (start - off) < (1 << idx) {
+                    idx -= 1;
+                }
+                leaks.push((off, 1 << idx));
+                off += 1 << idx;
+            }
+            off = off.max(end);
+        }
+        leaks
+    }
+
     /// Prints the free lists
     pub fn print(&self) {
         println!();
@@ -725,11 +1066,19 @@ impl<T, A: MemPool> Zones<T, A> {
     /// Returns a mutable reference to the zone object associated with the
     /// given offset
     pub fn from_off(&self, off: u64) -> (&mut T, usize) {
-        let i = off as usize / self.quota;
+        let i = self.index(off);
         let off = self.base + i * mem::size_of::<T>();
         (Self::read(off as u64), i)
     }
 
+    #[inline]
+    /// Returns the index of the zone that owns the given offset
+    ///
+    /// The last zone also owns the region added by growing the pool.
+    pub fn index(&self, off: u64) -> usize {
+        (off as usize / self.quota).min(self.count - 1)
+    }
+
     #[inline]
     fn read<'a>(off: u64) -> &'a mut T {
         unsafe { read_addr(A::start() + off) }
@@ -827,6 +1176,100 @@ mod test {
 
         println!("{} -> {}", u, P::used());
     }
+
+    #[test]
+    fn grow_test() {
+        let _pool = P::open_no_root("grow.pool", O_CF | O_GROW).unwrap();
+        let size = P::size();
+        let available = P::available();
+
+        P::grow(2 * size).unwrap();
+        assert_eq!(P::size(), 2 * size);
+        assert!(P::available() >= available + size);
+
+        unsafe {
+            // Fits only in the new region
+            let (p, _, len) = P::alloc(size);
+            assert!(!p.is_null());
+            P::dealloc(p, len);
+
+            // Does not fit in the pool; it grows automatically
+            let (p, _, len) = P::alloc(2 * size);
+            assert!(!p.is_null());
+            P::dealloc(p, len);
+        }
+        assert!(P::size() > 2 * size);
+    }
+
+    #[test]
+    fn reserve_test() {
+        crate::pool!(reserve, Allocator);
+        type R = reserve::Allocator;
+
+        let size = 8 << 20;
+        let _pool = R::open_no_root_with("reserve.pool", crate::PoolOptions::new()
+            .create(true).format(true).size(size as u64).reserve(2 * size)).unwrap();
+        let start = R::start();
+
+        // It grows in place within the reservation
+        R::grow(2 * size).unwrap();
+        assert_eq!(R::size(), 2 * size);
+
+        // The address range after the reservation is taken
+        let end = (start + 2 * size as u64) as *mut libc::c_void;
+        let blocker = unsafe {
+            libc::mmap(end, size, libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE, -1, 0)
+        };
+        assert_ne!(blocker, libc::MAP_FAILED);
+        assert!(matches!(R::grow(3 * size), Err(crate::Error::InvalidSize(_))));
+        assert_eq!(R::size(), 2 * size);
+        assert_eq!(R::start(), start);
+        unsafe {
+            let (p, _, len) = R::alloc(size);
+            assert!(!p.is_null());
+            R::dealloc(p, len);
+            libc::munmap(blocker, size);
+        }
+
+        // The reservation is extended once the address range is free
+        if blocker == end {
+            R::grow(3 * size).unwrap();
+            assert_eq!(R::size(), 3 * size);
+            assert_eq!(R::start(), start);
+        }
+    }
+
+    #[test]
+    fn aligned_alloc_test() {
+        use std::alloc::Layout;
+
+        let _pool = P::open_no_root("aligned.pool", O_CF).unwrap();
+        let used = P::used();
+
+        unsafe {
+            for &align in &[8, 64, 256, 4096] {
+                let (p, _, len) = P::alloc_layout(Layout::from_size_align(24, align).unwrap());
+                assert!(!p.is_null());
+                assert_eq!(p as usize % align, 0);
+
+                // Only a 32-byte block is kept; the rest is freed
+                assert_eq!(P::used(), used + 32);
+                P::dealloc(p, len);
+                assert_eq!(P::used(), used);
+            }
+        }
+
+        #[repr(align(128))]
+        struct Line([u8; 128]);
+
+        P::transaction(|j| {
+            let b = Pbox::new(Line([1; 128]), j);
+            assert_eq!(&*b as *const _ as usize % 128, 0);
+            let b = Pbox::new_aligned(1u64, 512, j);
+            assert_eq!(&*b as *const _ as usize % 512, 0);
+        }).unwrap();
+    }
 }
 
 #[cfg(feature = "verbose")]
@@ -988,7 +1431,41 @@ macro_rules! __cfg_delete_history {
 /// }).unwrap();
 /// ```
 /// 
+/// The allocation algorithm can be chosen by passing it as the third argument.
+/// The default algorithm is [`BuddyAlg`]. [`SlabAlg`] packs small objects in
+/// size classes to reduce the internal fragmentation.
+///
+/// ```
+/// corundum::pool!(small, Allocator, SlabAlg);
+///
+/// type P = small::Allocator;
+///
+/// let _pool = P::open_no_root("small.pool", O_CF).unwrap();
+/// ```
+///
+/// The fourth argument is the number of instances of the pool type that can
+/// be open at the same time. The pool type is `Instance<I>`, and [`Allocator`]
+/// is an alias to `Instance<0>`. Each instance opens a separate file, and since
+/// instances are different types, a pointer cannot cross the instance it is
+/// allocated in.
+///
+/// ```
+/// corundum::pool!(shards, Allocator, BuddyAlg, 4);
+///
+/// type S0 = shards::Instance<0>;
+/// type S1 = shards::Instance<1>;
+///
+/// let _s0 = S0::open_no_root("s0.pool", O_CF).unwrap();
+/// let _s1 = S1::open_no_root("s1.pool", O_CF).unwrap();
+///
+/// S1::transaction(|j| {
+///     let b = corundum::Pbox::<i32, S1>::new(10, j);
+/// }).unwrap();
+/// ```
+///
 /// [`Allocator`]: ./alloc/default/struct.Allocator.html
+/// [`BuddyAlg`]: ./struct.BuddyAlg.html
+/// [`SlabAlg`]: ./struct.SlabAlg.html
 /// [`corundum::boxed::Pbox`]: ./boxed/struct.Pbox.html
 /// [`corundum::prc::Prc`]: ./prc/struct.Prc.html
 /// [`corundum::sync::Parc`]: ./sync/struct.Parc.html
@@ -1001,6 +1478,12 @@ macro_rules! __cfg_delete_history {
 /// [`corundum::str::String`]: ./str/struct.String.html
 macro_rules! pool {
     ($mod:ident, $name:ident) => {
+        $crate::pool!($mod, $name, BuddyAlg);
+    };
+    ($mod:ident, $name:ident, $alg:ident) => {
+        $crate::pool!($mod, $name, $alg, 1);
+    };
+    ($mod:ident, $name:ident, $alg:ident, $n:expr) => {
         /// The default allocator module
         pub mod $mod {
             use memmap::*;
@@ -1028,6 +1511,7 @@ macro_rules! pool {
                 transaction, 
                 open_flags, 
                 PClone, 
+                Trace,
                 Root,
                 RootObj,
                 ToPString,
@@ -1036,32 +1520,188 @@ macro_rules! pool {
                 MemPool
             };
     
-            static mut BUDDY_START: u64 = 0;
-            static mut BUDDY_VALID_START: u64 = 0;
-            static mut BUDDY_END: u64 = 0;
+            /// Maximum number of simultaneously open instances
+            pub const INSTANCES: usize = $n;
+
+            // The constants only initialize the arrays of the instances
+            const NO_FILE: Option<std::fs::File> = None;
+            #[allow(clippy::declare_interior_mutable_const)]
+            const CLOSED: AtomicBool = AtomicBool::new(false);
+            #[allow(clippy::declare_interior_mutable_const)]
+            const NO_VDATA: LazyCell<Arc<Mutex<Option<VData>>>> =
+                LazyCell::new(|| Arc::new(Mutex::new(None)));
+            #[allow(clippy::declare_interior_mutable_const)]
+            const NO_GROUP: LazyCell<$crate::stm::CommitGroup> =
+                LazyCell::new(Default::default);
+            const NO_TYPES: Option<TypeTable> = None;
+
+            static mut BUDDY_START: [u64; INSTANCES] = [0; INSTANCES];
+            static mut BUDDY_VALID_START: [u64; INSTANCES] = [0; INSTANCES];
+            static mut BUDDY_END: [u64; INSTANCES] = [0; INSTANCES];
+            static mut BUDDY_MAPPED: [usize; INSTANCES] = [0; INSTANCES];
+            static mut BUDDY_RESERVED: [usize; INSTANCES] = [0; INSTANCES];
+            static mut BUDDY_FILE: [Option<std::fs::File>; INSTANCES] = [NO_FILE; INSTANCES];
+            static mut AUTO_GROW: [bool; INSTANCES] = [false; INSTANCES];
+            static mut GROW_LOCK: [u8; INSTANCES] = [0; INSTANCES];
+            static mut RECOVERY_INFO: [u32; INSTANCES] = [0; INSTANCES];
+            static mut SCRATCHPAD_SIZE: [usize; INSTANCES] = [DEFAULT_SCRATCHPAD_SIZE; INSTANCES];
+            static mut READ_ONLY: [bool; INSTANCES] = [false; INSTANCES];
+            static mut REDO_LOG: [bool; INSTANCES] = [false; INSTANCES];
+            static mut GROUP_COMMIT: [bool; INSTANCES] = [false; INSTANCES];
+            static mut PAGE_SLOTS: [usize; INSTANCES] = [DEFAULT_JOURNAL_PAGE_SLOTS; INSTANCES];
+            static mut JOURNAL_CACHE: [usize; INSTANCES] = [0; INSTANCES];
+            static mut DEADLOCK_DETECTION: [bool; INSTANCES] = [false; INSTANCES];
+            static mut TYPES: [Option<TypeTable>; INSTANCES] = [NO_TYPES; INSTANCES];
     
             #[repr(C)]
-            struct BuddyAllocInner {
+            struct BuddyAllocInner<const I: usize> {
                 magic_number: u64,
+                version: u32,
                 flags: u64,
                 gen: u32,
                 tx_gen: u32,
                 root_obj: u64,
                 root_type_id: u64,
                 journals: u64,
+                type_pages: u64,
+                type_names: u64,
                 size: usize,
-                zone: Zones<BuddyAlg<$name>, $name>
+                zone: Zones<$alg<Instance<I>>, Instance<I>>
             }
     
             struct VData {
                 filename: String,
                 journals: HashMap<ThreadId, (u64, i32)>,
                 check_double_free: HashSet<u64>,
-                mmap: MmapMut,
+                mmap: Mapping,
+            }
+
+            /// Memory mapping of the pool file
+            enum Mapping {
+                #[cfg(not(target_os = "linux"))]
+                ReadWrite(MmapMut),
+
+                /// A private copy-on-write mapping of a pool opened in the
+                /// read-only mode or only to read its information. The
+                /// changes, such as the volatile state of the locks and the
+                /// borrow flags, are never written to the file.
+                ReadOnly(MmapMut),
+
+                /// A writable mapping at the beginning of a reserved address
+                /// range, so that it can be extended in place when the pool
+                /// grows
+                #[cfg(target_os = "linux")]
+                Growable { addr: u64, len: usize, reserved: usize },
+            }
+
+            impl Mapping {
+                /// Maps the pool file for writing
+                #[cfg(not(target_os = "linux"))]
+                fn writable(file: &std::fs::File, _reserve: Option<usize>) -> std::io::Result<Self> {
+                    Ok(Mapping::ReadWrite(unsafe { MmapOptions::new().map_mut(file)? }))
+                }
+
+                /// Reserves `reserve` bytes of address space, or
+                /// `DEFAULT_RESERVE_FACTOR` times the file length if it is not
+                /// given, and maps the pool file at the beginning of it for
+                /// writing
+                #[cfg(target_os = "linux")]
+                fn writable(file: &std::fs::File, reserve: Option<usize>) -> std::io::Result<Self> {
+                    use std::os::unix::io::AsRawFd;
+
+                    let len = file.metadata()?.len() as usize;
+                    let reserved = reserve.unwrap_or(len * DEFAULT_RESERVE_FACTOR).max(len);
+                    unsafe {
+                        let addr = libc::mmap(std::ptr::null_mut(), reserved, libc::PROT_NONE,
+                            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE, -1, 0);
+                        if addr == libc::MAP_FAILED {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        if libc::mmap(addr, len, libc::PROT_READ | libc::PROT_WRITE,
+                            libc::MAP_SHARED | libc::MAP_FIXED, file.as_raw_fd(), 0) == libc::MAP_FAILED {
+                            let err = std::io::Error::last_os_error();
+                            libc::munmap(addr, reserved);
+                            return Err(err);
+                        }
+                        Ok(Mapping::Growable { addr: addr as u64, len, reserved })
+                    }
+                }
+
+                fn len(&self) -> usize {
+                    match self {
+                        #[cfg(not(target_os = "linux"))]
+                        Mapping::ReadWrite(m) => m.len(),
+                        Mapping::ReadOnly(m) => m.len(),
+                        #[cfg(target_os = "linux")]
+                        Mapping::Growable { len, .. } => *len,
+                    }
+                }
+
+                fn as_mut_ptr(&mut self) -> *mut u8 {
+                    match self {
+                        #[cfg(not(target_os = "linux"))]
+                        Mapping::ReadWrite(m) => m.as_mut_ptr(),
+                        Mapping::ReadOnly(m) => m.as_mut_ptr(),
+                        #[cfg(target_os = "linux")]
+                        Mapping::Growable { addr, .. } => *addr as *mut u8,
+                    }
+                }
+
+                /// Returns the size of the address range which the mapping
+                /// can be extended to
+                fn capacity(&self) -> usize {
+                    match self {
+                        #[cfg(target_os = "linux")]
+                        Mapping::Growable { reserved, .. } => *reserved,
+                        _ => self.len(),
+                    }
+                }
+
+                /// Extends the reserved address range to `size` bytes if the
+                /// range right after it is free, and returns `true` if
+                /// successful. The mapping itself never moves.
+                fn reserve(&mut self, size: usize) -> bool {
+                    match self {
+                        #[cfg(target_os = "linux")]
+                        Mapping::Growable { addr, reserved, .. } => {
+                            if size <= *reserved {
+                                return true;
+                            }
+                            let end = (*addr + *reserved as u64) as *mut libc::c_void;
+                            let extra = size - *reserved;
+                            unsafe {
+                                // The end of the reservation is only a hint,
+                                // so that no other mapping is replaced
+                                let ext = libc::mmap(end, extra, libc::PROT_NONE,
+                                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE, -1, 0);
+                                if ext == libc::MAP_FAILED {
+                                    return false;
+                                }
+                                if ext != end {
+                                    libc::munmap(ext, extra);
+                                    return false;
+                                }
+                            }
+                            *reserved = size;
+                            true
+                        }
+                        _ => size <= self.capacity(),
+                    }
+                }
+            }
+
+            #[cfg(target_os = "linux")]
+            impl Drop for Mapping {
+                fn drop(&mut self) {
+                    if let Mapping::Growable { addr, reserved, .. } = self {
+                        // Unmaps the pool file together with the extensions
+                        unsafe { libc::munmap(*addr as *mut libc::c_void, *reserved); }
+                    }
+                }
             }
     
             impl VData {
-                fn new(mmap: MmapMut, filename: &str) -> Self {
+                fn new(mmap: Mapping, filename: &str) -> Self {
                     Self {
                         filename: filename.to_string(),
                         journals: HashMap::new(),
@@ -1071,27 +1711,55 @@ macro_rules! pool {
                 }
             }
     
-            impl BuddyAllocInner {
-                fn init(&mut self, size: usize) {
-                    let id = std::any::type_name::<Self>();
+            impl<const I: usize> BuddyAllocInner<I> {
+                /// Returns the magic number of the pool images, which does
+                /// not change with the format version. All instances share
+                /// the same layout.
+                fn magic() -> u64 {
                     let mut s = DefaultHasher::new();
-                    id.hash(&mut s);
+                    std::any::type_name::<BuddyAllocInner<0>>().hash(&mut s);
+                    "format".hash(&mut s);
+                    s.finish()
+                }
+
+                /// Checks the magic number and the format version of the image
+                fn check_format(magic: u64, version: u32) -> Result<()> {
+                    if magic == Self::magic() {
+                        if version == FORMAT_VERSION {
+                            Ok(())
+                        } else {
+                            Err(Error::UnsupportedVersion(version))
+                        }
+                    } else {
+                        // The images without a version were identified only
+                        // by the name of the header type
+                        let id = std::any::type_name::<BuddyAllocInner<0>>();
+                        for id in &[id, id.trim_end_matches("<0>")] {
+                            let mut s = DefaultHasher::new();
+                            id.hash(&mut s);
+                            if magic == s.finish() {
+                                return Err(Error::UnsupportedVersion(0));
+                            }
+                        }
+                        Err(Error::InvalidMagic)
+                    }
+                }
+
+                fn init(&mut self, size: usize, cpus: usize) {
+                    self.version = FORMAT_VERSION;
                     self.flags = 0;
                     self.gen = 1;
                     self.tx_gen = 0;
                     self.root_obj = u64::MAX;
                     self.root_type_id = 0;
                     self.journals = u64::MAX;
+                    self.type_pages = u64::MAX;
+                    self.type_names = u64::MAX;
                     self.size = size;
     
-                    type T = BuddyAlg<$name>;
-                    let cpus = if let Some(val) = std::env::var_os("CPUS") {
-                        val.into_string().unwrap().parse::<usize>().unwrap()
-                    } else {
-                        num_cpus::get()
-                    };
                     assert_ne!(cpus, 0);
-                    let quota = size / cpus;
+                    // Zones start at page boundaries to keep the blocks aligned
+                    let quota = (size / cpus) & !(MAX_ALIGN - 1);
                     self.zone = Zones::new(cpus, mem::size_of::<Self>(), quota);
                     for i in 0..cpus {
                         self.zone[i].init((quota * i) as u64, quota);
@@ -1099,11 +1767,11 @@ macro_rules! pool {
                     self.magic_number = u64::MAX;
                     unsafe {
                         self.zone[0].alloc_impl(
-                            mem::size_of::<Self>() + mem::size_of::<T>() * cpus,
+                            mem::size_of::<Self>() + mem::size_of::<$alg<Instance<I>>>() * cpus,
                             true,
                         );
                     }
-                    self.magic_number = s.finish();
+                    self.magic_number = Self::magic();
                 }
     
                 fn as_bytes(&self) -> &[u8] {
@@ -1120,13 +1788,18 @@ macro_rules! pool {
             /// A memory allocator with buddy allocation mechanism
             ///
             /// To define a new buddy allocator type as a memory pool, you may
-            /// use [`pool!()`] macro. 
+            /// use [`pool!()`] macro. Each instance `I` can open a separate
+            /// pool file at the same time. Instances are distinct pool types,
+            /// so that the pointers of one cannot point to another.
             /// 
             /// [`pool!()`]: ../macro.pool.html
             #[derive(Clone,Copy,Default)]
-            pub struct $name {}
+            pub struct Instance<const I: usize> {}
+
+            /// The first instance of the pool
+            pub type $name = Instance<0>;
 
-            unsafe impl MemPool for $name {}
+            unsafe impl<const I: usize> MemPool for Instance<I> {}
     
             pub mod dummy {
                 #[repr(C)]
@@ -1135,15 +1808,134 @@ macro_rules! pool {
                 }
             }
     
-            static mut BUDDY_INNER: Option<*mut BuddyAllocInner> = None;
-            static mut OPEN: AtomicBool = AtomicBool::new(false);
+            static mut BUDDY_INNER: [Option<*mut u8>; INSTANCES] = [None; INSTANCES];
+            static mut OPEN: [AtomicBool; INSTANCES] = [CLOSED; INSTANCES];
             static mut MAX_GEN: u32 = 0;
-            static mut VDATA: LazyCell<Arc<Mutex<Option<VData>>>> = 
-                LazyCell::new(|| Arc::new(Mutex::new(None)));
+            static mut VDATA: [LazyCell<Arc<Mutex<Option<VData>>>>; INSTANCES] = [NO_VDATA; INSTANCES];
+            static mut GROUPS: [LazyCell<$crate::stm::CommitGroup>; INSTANCES] = [NO_GROUP; INSTANCES];
     
-            impl $name {
+            impl<const I: usize> Instance<I> {
+                #[inline]
+                fn inner() -> Option<*mut BuddyAllocInner<I>> {
+                    unsafe { BUDDY_INNER[I].map(|p| p as *mut BuddyAllocInner<I>) }
+                }
+
+                /// Replaces the root object with the upgraded one. The new
+                /// root object is created, the header fields are updated, and
+                /// the old root object is dropped in a single transaction, in
+                /// which the header fields are logged like any other data. A
+                /// crash leaves either the old or the new root object in the
+                /// pool, and none of them leaks.
+                unsafe fn upgrade_root<'a, U: 'a + PSafe + RootObj<Self>>(
+                    inner: &mut BuddyAllocInner<I>,
+                    id: u64,
+                    migrations: &Migrations<U, Self>,
+                ) -> Result<&'a U> {
+                    let old_off = inner.root_obj;
+                    let old_id = inner.root_type_id;
+                    // `root_obj` and `root_type_id` are adjacent in the header
+                    let header = &mut inner.root_obj as *mut u64;
+                    let root_off = Self::transaction(AssertTxInSafe(|j| {
+                        let root = migrations.migrate(old_id, old_off, j).unwrap();
+                        let root_off = Self::off_unchecked(Self::new(root, j));
+                        let fields = std::slice::from_raw_parts_mut(header, 2);
+                        $crate::stm::Logger::create_log(&*fields, j, $crate::stm::Notifier::None);
+                        fields.copy_from_slice(&[root_off, id]);
+                        migrations.drop_old(old_id, old_off, j);
+                        root_off
+                    }))?;
+                    Ok(Self::get_unchecked(root_off))
+                }
+
+                /// Runs `f` when no transaction is running. Holding the lock
+                /// of `VDATA` prevents new transactions from obtaining a
+                /// journal while `f` is running.
+                fn quiescent<T, F: FnOnce() -> Result<T>>(f: F) -> Result<T> {
+                    loop {
+                        let vdata = match unsafe { VDATA[I].lock() } {
+                            Ok(g) => g,
+                            Err(p) => p.into_inner()
+                        };
+                        let quiescent = if let Some(vdata) = &*vdata {
+                            $crate::__cfg_pin_journals!({
+                                vdata.journals.values().all(|(_, c)| *c <= 0)
+                            }, {
+                                if unsafe { JOURNAL_CACHE[I] } > 0 {
+                                    vdata.journals.values().all(|(_, c)| *c <= 0)
+                                } else {
+                                    vdata.journals.is_empty()
+                                }
+                            })
+                        } else {
+                            return Err(Error::PoolNotOpen);
+                        };
+                        if quiescent {
+                            return f();
+                        }
+                        drop(vdata);
+                        std::thread::yield_now();
+                    }
+                }
+
+                /// Copies the pool to `dest` when no transaction is running
+                fn snapshot_impl(dest: &str, skip_unchanged: bool) -> Result<usize> {
+                    if !Self::is_open() {
+                        return Err(Error::PoolNotOpen);
+                    }
+                    if $crate::stm::Journal::<Self>::is_running() {
+                        return Err(Error::InTransaction);
+                    }
+                    Self::quiescent(|| {
+                        let src = unsafe { std::slice::from_raw_parts(
+                            BUDDY_START[I] as *const u8, Self::size()) };
+                        write_snapshot(dest, src, skip_unchanged)
+                    })
+                }
+
+                /// Traces the object graph from the root object of type `U`,
+                /// and returns the unreachable blocks with the number of the
+                /// reachable ones. The allocator metadata and the journals
+                /// are reachable.
+                fn find_leaks<U: PSafe + RootObj<Self> + Trace<Self>>()
+                    -> Result<(Vec<(u64, usize)>, usize)> {
+                    static_inner!(Self::inner(), inner, {
+                        if !inner.has_root() {
+                            return Err(Error::NoRoot);
+                        }
+                        if inner.root_type_id != fingerprint::<U, Self>()
+                            && inner.root_type_id != legacy_fingerprint::<U, Self>() {
+                            return Err(Error::IncompatibleRoot);
+                        }
+                        let mut tracer = Tracer::<Self>::new();
+                        tracer.mark(0, mem::size_of::<BuddyAllocInner<I>>() +
+                            mem::size_of::<$alg<Self>>() * inner.zone.count());
+                        if tracer.mark(inner.root_obj, mem::size_of::<U>()) {
+                            Self::get_unchecked::<U>(inner.root_obj).trace(&mut tracer);
+                        }
+                        if let Some(types) = &TYPES[I] {
+                            for (off, len) in types.blocks::<Self>() {
+                                tracer.mark(off, len);
+                            }
+                        }
+                        let mut curr = inner.journals;
+                        while let Ok(j) = Self::deref::<$crate::stm::Journal<Self>>(curr) {
+                            tracer.mark(curr, mem::size_of::<$crate::stm::Journal<Self>>());
+                            for (off, len) in j.page_blocks() {
+                                tracer.mark(off, len);
+                            }
+                            curr = j.next_off();
+                        }
+                        let reachable = tracer.blocks();
+                        let mut leaks = vec![];
+                        for i in 0..inner.zone.count() {
+                            leaks.extend(inner.zone[i].leaks(&reachable));
+                        }
+                        Ok((leaks, reachable.len()))
+                    })
+                }
+
                 fn running_transaction() -> bool {
-                    let vdata = match unsafe { VDATA.lock() } {
+                    let vdata = match unsafe { VDATA[I].lock() } {
                         Ok(g) => g,
                         Err(p) => p.into_inner()
                     };
@@ -1157,119 +1949,185 @@ macro_rules! pool {
                 /// Opens a memory pool file and returns an instance of
                 /// [`Allocator`](#) if success. The pool remains open as long
                 /// as the instance lives.
+                ///
+                /// A pool opened for writing locks the file exclusively until
+                /// it is closed, so that other processes cannot open it for
+                /// writing at the same time. A pool opened in the read-only
+                /// mode or only to read its info does not lock the file, so
+                /// that it does not conflict with the writer.
                 #[track_caller]
-                pub fn open_impl(filename: &str, no_check: bool) -> Result<PoolGuard<Self>> {
-                    let metadata = std::fs::metadata(filename);
-                    if let Err(e) = &metadata {
-                        Err(format!("{}", e))
+                pub fn open_impl(filename: &str, opts: &PoolOptions) -> Result<PoolGuard<Self>> {
+                    let metadata = std::fs::metadata(filename)?;
+                    if !metadata.is_file() {
+                        return Err(Error::InvalidMagic);
+                    }
+                    let writable = !(opts.get_read_info() || opts.get_read_only());
+                    let path = PathBuf::from(filename);
+                    let file = OpenOptions::new()
+                        .read(true)
+                        .write(writable)
+                        .create(writable)
+                        .open(&path)?;
+
+                    if writable {
+                        lock_file(&file, true, opts.get_wait_lock())?;
+                    }
+                    Self::open_file(file, filename, opts)
+                }
+
+                /// Maps the pool from an open file. The file is kept open
+                /// until the pool is closed to hold the lock, if any.
+                #[track_caller]
+                fn open_file(file: std::fs::File, filename: &str, opts: &PoolOptions) -> Result<PoolGuard<Self>> {
+                    let no_check = opts.get_read_info();
+                    if file.metadata()?.len() < 8 {
+                        Err(Error::InvalidMagic)
                     } else {
-                        let metadata = metadata.unwrap();
-                        assert!(metadata.is_file());
-                        if metadata.len() < 8 {
-                            Err("Invalid pool file".to_string())
+                        let mut mmap = if opts.get_read_only() || opts.get_read_info() {
+                            Mapping::ReadOnly(unsafe {
+                                memmap::MmapOptions::new().map_copy(&file)?
+                            })
                         } else {
-                            let path = PathBuf::from(filename);
-                            let file = OpenOptions::new()
-                                .read(true)
-                                .write(true)
-                                .create(true)
-                                .open(&path)
-                                .unwrap();
-    
-                            let mut mmap =
-                                unsafe { memmap::MmapOptions::new().map_mut(&file).unwrap() };
-    
-                            let raw_offset = mmap.get_mut(0).unwrap();
-    
-                            let id = std::any::type_name::<BuddyAllocInner>();
-                            let mut s = DefaultHasher::new();
-                            id.hash(&mut s);
-                            let id = s.finish();
-    
-                            let inner = unsafe {
-                                read::<BuddyAllocInner>(raw_offset)
-                            };
-                            if !no_check {
-                                assert_eq!(
-                                    inner.magic_number, id,
-                                    "Invalid magic number for the pool image file"
-                                );
-                            }
-    
-                            let base = raw_offset as *mut _ as u64;
-                            unsafe {
-                                inner.gen = MAX_GEN.max(inner.gen + 1);
-                                inner.tx_gen = 0;
-                                MAX_GEN = inner.gen;
-                                BUDDY_START = base;
-                                BUDDY_VALID_START = base
-                                    + mem::size_of::<BuddyAllocInner>() as u64
-                                    + mem::size_of::<BuddyAlg<Self>>() as u64;
-                                BUDDY_END = BUDDY_START + inner.size as u64 + 1;
-                                BUDDY_INNER = Some(inner);
-                                let mut vdata = match VDATA.lock() {
-                                    Ok(g) => g,
-                                    Err(p) => p.into_inner()
-                                };
-                                *vdata = Some(VData::new(mmap, filename));
+                            Mapping::writable(&file, opts.get_reserve())?
+                        };
+
+                        let raw_offset = mmap.as_mut_ptr();
+
+                        let inner = unsafe {
+                            read::<BuddyAllocInner<I>>(raw_offset)
+                        };
+                        if !no_check {
+                            if mmap.len() < mem::size_of::<BuddyAllocInner<I>>() {
+                                return Err(Error::InvalidMagic);
                             }
-    
-                            Ok(PoolGuard::<Self>::new())
+                            BuddyAllocInner::<I>::check_format(inner.magic_number, inner.version)?;
+                        }
+
+                        let base = raw_offset as u64;
+                        unsafe {
+                            // The new generation of a read-only or info
+                            // pool only goes to its private mapping
+                            inner.gen = MAX_GEN.max(inner.gen + 1);
+                            inner.tx_gen = 0;
+                            MAX_GEN = inner.gen;
+                            // A pool opened only to read its information
+                            // is not modified either
+                            READ_ONLY[I] = opts.get_read_only() || opts.get_read_info();
+                            BUDDY_START[I] = base;
+                            BUDDY_VALID_START[I] = base
+                                + mem::size_of::<BuddyAllocInner<I>>() as u64
+                                + mem::size_of::<$alg<Self>>() as u64;
+                            BUDDY_END[I] = BUDDY_START[I] + inner.size as u64 + 1;
+                            BUDDY_MAPPED[I] = mmap.len();
+                            BUDDY_RESERVED[I] = mmap.capacity();
+                            BUDDY_INNER[I] = Some(inner as *mut _ as *mut u8);
+                            let mut vdata = match VDATA[I].lock() {
+                                Ok(g) => g,
+                                Err(p) => p.into_inner()
+                            };
+                            *vdata = Some(VData::new(mmap, filename));
+                            BUDDY_FILE[I] = Some(file);
+                        }
+
+                        Ok(PoolGuard::<Self>::new())
+                    }
+                }
+
+                /// Sets the options of the open pool. The options which update
+                /// the pool are disabled if it is opened in the read-only mode
+                /// or only to read its information.
+                unsafe fn set_options(opts: &PoolOptions) {
+                    let writable = !(opts.get_read_only() || opts.get_read_info());
+                    AUTO_GROW[I] = writable && opts.get_grow();
+                    RECOVERY_INFO[I] = opts.get_recovery_info();
+                    REDO_LOG[I] = writable && opts.get_redo_log();
+                    if writable && opts.get_group_commit() {
+                        LazyCell::force(&GROUPS[I]);
+                    }
+                    GROUP_COMMIT[I] = writable && opts.get_group_commit();
+                    PAGE_SLOTS[I] = opts.get_page_slots();
+                    JOURNAL_CACHE[I] = if writable { opts.get_journal_cache() } else { 0 };
+                    DEADLOCK_DETECTION[I] = opts.get_deadlock_detection();
+                    opts.apply_process_settings();
+                }
+
+                /// Loads the type records if the pool keeps the type
+                /// information. The stale records are cleared if `repair` is
+                /// set.
+                #[allow(unused_unsafe)]
+                unsafe fn load_types(repair: bool) {
+                    static_inner!(Self::inner(), inner, {
+                        if inner.flags & FLAG_TYPE_INFO != 0 {
+                            TYPES[I] = Some(TypeTable::load::<Self>(
+                                Self::off_unchecked(&inner.type_pages),
+                                Self::off_unchecked(&inner.type_names),
+                                repair));
                         }
+                    })
+                }
+
+                /// Adds the region between the end of the last zone and the
+                /// end of the pool to the last zone
+                fn resume_growth(inner: &mut BuddyAllocInner<I>) {
+                    let last = inner.zone.count() - 1;
+                    let zone = &mut inner.zone[last];
+                    let end = zone.end();
+                    if end < inner.size as u64 {
+                        unsafe { zone.extend(end, inner.size - end as usize); }
                     }
+                    inner.flags &= !FLAG_GROWING;
+                    persist_obj(&inner.flags, true);
                 }
             }
     
-            unsafe impl MemPoolTraits for $name {
+            unsafe impl<const I: usize> MemPoolTraits for Instance<I> {
                 #[inline]
                 fn name() -> &'static str {
                     stringify!($mod)
                 }
     
                 /// Formats the image file
-                unsafe fn format(filename: &str) -> Result<()> {
+                unsafe fn format_with(filename: &str, opts: &PoolOptions) -> Result<()> {
                     if Path::new(filename).exists() {
                         let file = OpenOptions::new()
                             .read(true)
                             .write(true)
                             .create(true)
-                            .open(filename);
-                        if let Err(e) = &file {
-                            Err(format!("{}", e))
-                        } else {
-                            let file = file.unwrap();
-                            let mut len = file.metadata().unwrap().len() as usize;
-                            if len < 8 {
-                                len = 10 * 1024 * 1024;
-                                file.set_len(len as u64).unwrap();
-                            }
-    
-                            let mut mmap = memmap::MmapOptions::new().map_mut(&file).unwrap();
-                            let begin = mmap.get_mut(0).unwrap();
-                            std::ptr::write_bytes(begin, 0xff, 8);
-                            BUDDY_START = begin as *const _ as u64;
-                            BUDDY_END = u64::MAX;
-    
-                            let inner = read::<BuddyAllocInner>(begin);
-                            inner.init(len);
-                            mmap.flush().unwrap();
-                            Ok(())
+                            .open(filename)?;
+                        let mut len = file.metadata()?.len() as usize;
+                        if len < 8 {
+                            len = 10 * 1024 * 1024;
+                            file.set_len(len as u64)?;
+                        }
+
+                        let mut mmap = memmap::MmapOptions::new().map_mut(&file)?;
+                        let begin = mmap.as_mut_ptr();
+                        std::ptr::write_bytes(begin, 0xff, 8);
+                        BUDDY_START[I] = begin as *const _ as u64;
+                        BUDDY_END[I] = u64::MAX;
+
+                        let inner = read::<BuddyAllocInner<I>>(begin);
+                        inner.init(len, opts.zone_count());
+                        if opts.get_type_info() {
+                            inner.flags |= FLAG_TYPE_INFO;
                         }
+                        mmap.flush()?;
+                        Ok(())
                     } else {
-                        Err("Image file does not exist".to_string())
+                        Err(Error::Io(std::io::ErrorKind::NotFound.into()))
                     }
                 }
     
                 #[inline]
                 #[track_caller]
                 fn gen() -> u32 {
-                    static_inner!(BUDDY_INNER, inner, { inner.gen })
+                    static_inner!(Self::inner(), inner, { inner.gen })
                 }
     
                 #[inline]
                 #[track_caller]
                 fn tx_gen() -> u32 {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         inner.tx_gen += 1;
                         inner.tx_gen
                     })
@@ -1277,13 +2135,13 @@ macro_rules! pool {
     
                 #[track_caller]
                 fn size() -> usize {
-                    static_inner!(BUDDY_INNER, inner, { inner.size })
+                    static_inner!(Self::inner(), inner, { inner.size })
                 }
     
                 #[inline]
                 #[track_caller]
                 fn available() -> usize {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         let mut sum = 0;
                         for i in 0..inner.zone.count() {
                             sum += inner.zone[i].available();
@@ -1294,7 +2152,7 @@ macro_rules! pool {
     
                 #[track_caller]
                 fn used() -> usize {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         let mut sum = 0;
                         for i in 0..inner.zone.count() {
                             sum += inner.zone[i].used();
@@ -1305,34 +2163,56 @@ macro_rules! pool {
     
                 #[inline]
                 fn rng() -> Range<u64> {
-                    unsafe { BUDDY_VALID_START..BUDDY_END }
+                    unsafe { BUDDY_VALID_START[I]..BUDDY_END[I] }
                 }
     
                 #[inline]
                 fn start() -> u64 {
-                    unsafe { BUDDY_START }
+                    unsafe { BUDDY_START[I] }
                 }
     
                 #[inline]
                 fn end() -> u64 {
-                    unsafe { BUDDY_END }
+                    unsafe { BUDDY_END[I] }
                 }
     
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn pre_alloc(size: usize) -> (*mut u8, u64, usize, usize) {
+                    Self::pre_alloc_aligned(size, 1)
+                }
+
+                #[allow(unused_unsafe)]
+                #[track_caller]
+                unsafe fn pre_alloc_aligned(size: usize, align: usize) -> (*mut u8, u64, usize, usize) {
                     let _perf = $crate::__cfg_stat_perf!($crate::stat::Measure::<Self>::Alloc(std::time::Instant::now()));
+
+                    if READ_ONLY[I] {
+                        std::panic::panic_any(Error::ReadOnly);
+                    }
+                    assert!(align.is_power_of_two() && align <= MAX_ALIGN,
+                        "Invalid alignment {} (maximum = {})", align, MAX_ALIGN);
     
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         let cpu = cpu();
                         let cnt = inner.zone.count();
                         for i in 0..cnt {
                             let z = (cpu+i)%cnt;
-                            let a = inner.zone[z].alloc_impl(size, false);
+                            let a = inner.zone[z].alloc_aligned_impl(size, align, false);
                             if a != u64::MAX {
                                 return (Self::get_mut_unchecked(a), a, size, z);
                             }
                         }
+                        if AUTO_GROW[I] {
+                            let ext = $crate::utils::nearest_pow2(size.max(align) as u64) as usize * 2;
+                            if Self::grow(inner.size + inner.size.max(ext)).is_ok() {
+                                let z = cnt - 1;
+                                let a = inner.zone[z].alloc_aligned_impl(size, align, false);
+                                if a != u64::MAX {
+                                    return (Self::get_mut_unchecked(a), a, size, z);
+                                }
+                            }
+                        }
                         eprintln!(
                             "No space left (requested = {}, available= {})",
                             size, Self::available()
@@ -1341,12 +2221,34 @@ macro_rules! pool {
                     })
                 }
     
+                #[allow(unused_unsafe)]
+                #[track_caller]
+                unsafe fn pre_alloc_typed(size: usize, align: usize, type_name: &'static str) -> (*mut u8, u64, usize, usize) {
+                    if let Some(types) = &TYPES[I] {
+                        // The record is reserved before taking the zone lock
+                        let slot = types.reserve::<Self>(type_name);
+                        let (p, off, len, z) = Self::pre_alloc_aligned(size, align);
+                        if p.is_null() {
+                            types.release(slot);
+                        } else {
+                            types.tag::<Self>(slot, off, size, type_name, z);
+                        }
+                        (p, off, len, z)
+                    } else {
+                        Self::pre_alloc_aligned(size, align)
+                    }
+                }
+
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn pre_dealloc(ptr: *mut u8, size: usize) -> usize {
                     let _perf = $crate::__cfg_stat_perf!($crate::stat::Measure::<Self>::Dealloc(std::time::Instant::now()));
+
+                    if READ_ONLY[I] {
+                        std::panic::panic_any(Error::ReadOnly);
+                    }
     
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         let off = Self::off(ptr).expect("invalid pointer");
                         let (zone,zidx) = inner.zone.from_off(off);
                         $crate::__cfg_check_access_violation!({
@@ -1358,15 +2260,84 @@ macro_rules! pool {
                         }, {
                             zone.dealloc_impl(off, size, false);
                         });
+                        if let Some(types) = &TYPES[I] {
+                            types.untag::<Self>(off, zidx);
+                        }
                         zidx
                     })
                 }
     
+                #[allow(unused_unsafe)]
+                #[track_caller]
+                fn grow(new_size: usize) -> Result<()> {
+                    unsafe {
+                        if READ_ONLY[I] {
+                            return Err(Error::ReadOnly);
+                        }
+                        let _lock = $crate::utils::SpinLock::acquire(&mut GROW_LOCK[I]);
+                        static_inner!(Self::inner(), inner, {
+                            if new_size <= inner.size {
+                                return Err(Error::InvalidSize(new_size));
+                            }
+                            let file = if let Some(file) = &BUDDY_FILE[I] {
+                                file
+                            } else {
+                                return Err(Error::PoolNotOpen);
+                            };
+                            if new_size > BUDDY_RESERVED[I] {
+                                // The reservation is extended in place, or
+                                // the pool cannot grow
+                                let mut vdata = match VDATA[I].lock() {
+                                    Ok(g) => g,
+                                    Err(p) => p.into_inner()
+                                };
+                                let mmap = match &mut *vdata {
+                                    Some(vdata) => &mut vdata.mmap,
+                                    None => return Err(Error::PoolNotOpen),
+                                };
+                                if !mmap.reserve(new_size) {
+                                    return Err(Error::InvalidSize(new_size));
+                                }
+                                BUDDY_RESERVED[I] = mmap.capacity();
+                            }
+                            file.set_len(new_size as u64)?;
+                            if new_size > BUDDY_MAPPED[I] {
+                                // The mapping should not move because there
+                                // are references to the persistent objects
+                                #[cfg(target_os = "linux")] {
+                                    use std::os::unix::io::AsRawFd;
+
+                                    // The last page of the current mapping is
+                                    // mapped again, as the offset should be
+                                    // page-aligned
+                                    let off = BUDDY_MAPPED[I] & !(MAX_ALIGN - 1);
+                                    let addr = libc::mmap((BUDDY_START[I] + off as u64) as *mut libc::c_void,
+                                        new_size - off, libc::PROT_READ | libc::PROT_WRITE,
+                                        libc::MAP_SHARED | libc::MAP_FIXED, file.as_raw_fd(), off as libc::off_t);
+                                    if addr == libc::MAP_FAILED {
+                                        return Err(Error::Io(std::io::Error::last_os_error()));
+                                    }
+                                }
+                                #[cfg(not(target_os = "linux"))] {
+                                    return Err(Error::Unsupported);
+                                }
+                                BUDDY_MAPPED[I] = new_size;
+                            }
+                            inner.size = new_size;
+                            inner.flags |= FLAG_GROWING;
+                            persist_obj(inner, true);
+                            BUDDY_END[I] = BUDDY_START[I] + new_size as u64 + 1;
+                            Self::resume_growth(inner);
+                            Ok(())
+                        })
+                    }
+                }
+
                 #[inline]
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn log64(off: u64, val: u64, z: usize) {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         inner.zone[z].log(off, val);
                     })
                 }
@@ -1375,7 +2346,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn drop_on_failure(off: u64, len: usize, z: usize) {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         inner.zone[z].drop_on_failure(off, len);
                     })
                 }
@@ -1383,8 +2354,8 @@ macro_rules! pool {
                 #[inline]
                 #[track_caller]
                 fn zone(off: u64) -> usize {
-                    static_inner!(BUDDY_INNER, inner, {
-                        off as usize / inner.zone.quota()
+                    static_inner!(Self::inner(), inner, {
+                        inner.zone.index(off)
                     })
                 }
     
@@ -1392,7 +2363,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn prepare(z: usize) {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         inner.zone[z].prepare();
                     })
                 }
@@ -1401,7 +2372,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn perform(z: usize) {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         inner.zone[z].perform();
                     })
                 }
@@ -1410,7 +2381,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn discard(z: usize) {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         inner.zone[z].discard();
                     })
                 }
@@ -1419,7 +2390,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 fn allocated(off: u64, _len: usize) -> bool {
-                    static_inner!(BUDDY_INNER, _inner, {
+                    static_inner!(Self::inner(), _inner, {
                         if off >= Self::end() {
                             false
                         } else if Self::contains(off + Self::start()) {
@@ -1427,7 +2398,9 @@ macro_rules! pool {
                                 { _inner.zone.from_off(off).0.is_allocated(off, _len) },
                                 { true })
                         } else {
-                            false
+                            // The header is allocated in zone 0, so that its
+                            // fields can be logged in transactions
+                            off + _len as u64 <= mem::size_of::<BuddyAllocInner<I>>() as u64
                         }
                     })
                 }
@@ -1436,7 +2409,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 fn verify() -> bool {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         for i in 0..inner.zone.count() {
                             if !inner.zone[i].verify() {
                                 return false;
@@ -1450,33 +2423,34 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn journals_head() -> &'static u64 {
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         &inner.journals
                     })
                 }
     
                 #[allow(unused_unsafe)]
                 #[track_caller]
-                unsafe fn drop_journal(journal: &mut Journal) {
-                    let _vdata = match VDATA.lock() {
+                unsafe fn drop_journal(journal: &mut $crate::stm::Journal<Self>) {
+                    let _vdata = match VDATA[I].lock() {
                         Ok(g) => g,
                         Err(p) => p.into_inner()
                     };
-                    static_inner!(BUDDY_INNER, inner, {
+                    static_inner!(Self::inner(), inner, {
                         let off = Self::off(journal).unwrap();
                     
                         $crate::__cfg_pin_journals!({
                             journal.drop_pages();
                         });
+                        journal.drop_cache();
     
-                        let z = Self::pre_dealloc(journal as *mut _ as *mut u8, mem::size_of::<Journal>());
+                        let z = Self::pre_dealloc(journal as *mut _ as *mut u8, mem::size_of::<$crate::stm::Journal<Self>>());
                         if inner.journals == off {
                             Self::log64(Self::off_unchecked(&inner.journals), journal.next_off(), z);
                         }
-                        if let Ok(prev) = Self::deref_mut::<Journal>(journal.prev_off()) {
+                        if let Ok(prev) = Self::deref_mut::<$crate::stm::Journal<Self>>(journal.prev_off()) {
                             Self::log64(Self::off_unchecked(prev.next_off_ref()), journal.next_off(), z);
                         }
-                        if let Ok(next) = Self::deref_mut::<Journal>(journal.next_off()) {
+                        if let Ok(next) = Self::deref_mut::<$crate::stm::Journal<Self>>(journal.next_off()) {
                             Self::log64(Self::off_unchecked(next.prev_off_ref()), journal.prev_off(), z);
                         }
                         Self::perform(z);
@@ -1486,7 +2460,7 @@ macro_rules! pool {
                 #[allow(unused_unsafe)]
                 #[track_caller]
                 unsafe fn journals<T, F: Fn(&mut HashMap<ThreadId, (u64, i32)>)->T>(f: F)->T{
-                    let mut vdata = match VDATA.lock() {
+                    let mut vdata = match VDATA[I].lock() {
                         Ok(g) => g,
                         Err(p) => p.into_inner()
                     };
@@ -1498,7 +2472,7 @@ macro_rules! pool {
                 }
 
                 unsafe fn dealloc_history() -> *mut HashSet<u64> {
-                    let mut vdata = match VDATA.lock() {
+                    let mut vdata = match VDATA[I].lock() {
                         Ok(g) => g,
                         Err(p) => p.into_inner()
                     };
@@ -1511,12 +2485,9 @@ macro_rules! pool {
     
                 #[allow(unused_unsafe,unused_braces)]
                 unsafe fn recover() {
-                    static_inner!(BUDDY_INNER, inner, {
-                        let info_level = std::env::var("RECOVERY_INFO")
-                            .unwrap_or("0".to_string())
-                            .parse::<u32>()
-                            .expect("RECOVERY_INFO should be an unsigned integer");
-                        
+                    static_inner!(Self::inner(), inner, {
+                        let info_level = RECOVERY_INFO[I];
+
                         if info_level > 0 {
                             for i in 0..inner.zone.count() {
                                 eprintln!("{:=^60}", format!(" Restore Allocator (Zone {}) ", i));
@@ -1524,7 +2495,7 @@ macro_rules! pool {
                             }
     
                             let mut curr = inner.journals;
-                            while let Ok(j) = Self::deref_mut::<Journal>(curr) {
+                            while let Ok(j) = Self::deref_mut::<$crate::stm::Journal<Self>>(curr) {
                                 eprintln!("{:-^60}\n{}", format!(" Journal @({}) ", curr), j.recovery_info(info_level));
                                 curr = j.next_off();
                             }
@@ -1533,6 +2504,10 @@ macro_rules! pool {
                         for i in 0..inner.zone.count() {
                             inner.zone[i].recover();
                         }
+
+                        if inner.flags & FLAG_GROWING != 0 {
+                            Self::resume_growth(inner);
+                        }
     
                         $crate::__cfg_check_allocator_cyclic_links!({
                             debug_assert!(Self::verify());
@@ -1544,10 +2519,10 @@ macro_rules! pool {
                         }, { () });
                         
     
-                        while let Ok(logs) = Self::deref_mut::<Journal>(inner.journals) {
+                        while let Ok(logs) = Self::deref_mut::<$crate::stm::Journal<Self>>(inner.journals) {
     
                             $crate::__cfg_verbose!({
-                                if *utils::VERBOSE {
+                                if $crate::utils::verbose() {
                                     println!("{:?}", logs);
                                 }
                             });
@@ -1585,28 +2560,33 @@ macro_rules! pool {
     
                 #[allow(unused_unsafe)]
                 #[track_caller]
-                fn open<'a, U: 'a + PSafe + RootObj<Self>>(
+                fn open_with<'a, U: 'a + PSafe + RootObj<Self>>(
+                    path: &str,
+                    opts: &PoolOptions,
+                ) -> Result<RootCell<'a, U, Self>> {
+                    Self::open_with_migrations(path, opts, &Migrations::new())
+                }
+
+                #[allow(unused_unsafe)]
+                #[track_caller]
+                fn open_with_migrations<'a, U: 'a + PSafe + RootObj<Self>>(
                     path: &str,
-                    flags: u32,
+                    opts: &PoolOptions,
+                    migrations: &Migrations<U, Self>,
                 ) -> Result<RootCell<'a, U, Self>> {
-                    let slf = Self::open_no_root(path, flags)?;
-                    static_inner!(BUDDY_INNER, inner, {
-                        // Replace it with std::any::TypeId::of::<U>() when it
-                        // is available in the future for non-'static types
-                        let id = format!("{} ({})", std::any::type_name::<U>(),
-                            mem::size_of::<U>());
-                        let mut s = DefaultHasher::new();
-                        id.hash(&mut s);
-                        let id = s.finish();
+                    let slf = Self::open_no_root_with(path, opts)?;
+                    static_inner!(Self::inner(), inner, {
+                        let id = fingerprint::<U, Self>();
                         if !inner.has_root() {
                             if mem::size_of::<U>() == 0 {
-                                Err("root type cannot be a ZST".to_string())
+                                Err(Error::ZeroSizedRoot)
+                            } else if opts.get_read_only() {
+                                Err(Error::NoRoot)
                             } else {
                                 let root_off = Self::transaction(move |j| {
                                     let ptr = Self::new(U::init(j), j);
                                     Self::off_unchecked(ptr)
-                                })
-                                .unwrap();
+                                })?;
                                 let ptr = Self::get_unchecked(root_off);
                                 inner.flags |= FLAG_HAS_ROOT;
                                 inner.root_obj = root_off;
@@ -1615,13 +2595,32 @@ macro_rules! pool {
                                 Ok(RootCell::new(ptr, Arc::new(slf)))
                             }
                         } else {
-                            if inner.root_type_id == id {
+                            if inner.root_type_id == legacy_fingerprint::<U, Self>() {
+                                // The root objects which were stored before
+                                // the schemas are taken as they are, and their
+                                // fingerprints are updated if possible
+                                if !opts.get_read_only() {
+                                    inner.root_type_id = id;
+                                    persist_obj(&inner.root_type_id, true);
+                                }
                                 Ok(RootCell::new(
                                     Self::deref::<U>(inner.root_obj)?,
                                     Arc::new(slf),
                                 ))
+                            } else if inner.root_type_id == id {
+                                Ok(RootCell::new(
+                                    Self::deref::<U>(inner.root_obj)?,
+                                    Arc::new(slf),
+                                ))
+                            } else if migrations.supports(inner.root_type_id) {
+                                if opts.get_read_only() {
+                                    Err(Error::ReadOnly)
+                                } else {
+                                    Self::upgrade_root(inner, id, migrations)
+                                        .map(|root| RootCell::new(root, Arc::new(slf)))
+                                }
                             } else {
-                                Err("Incompatible root type".to_string())
+                                Err(Error::IncompatibleRoot)
                             }
                         }
                     })
@@ -1629,66 +2628,343 @@ macro_rules! pool {
     
                 #[inline]
                 fn is_open() -> bool {
-                    unsafe { BUDDY_INNER.is_some() }
+                    unsafe { BUDDY_INNER[I].is_some() }
+                }
+
+                #[inline]
+                fn is_read_only() -> bool {
+                    unsafe { READ_ONLY[I] }
+                }
+
+                #[inline]
+                fn redo_logging() -> bool {
+                    unsafe { REDO_LOG[I] }
+                }
+
+                #[inline]
+                fn journal_page_slots() -> usize {
+                    unsafe { PAGE_SLOTS[I] }
+                }
+
+                #[inline]
+                fn journal_cache() -> usize {
+                    unsafe { JOURNAL_CACHE[I] }
+                }
+
+                #[inline]
+                fn deadlock_detection() -> bool {
+                    unsafe { DEADLOCK_DETECTION[I] }
+                }
+
+                #[inline]
+                fn commit_group() -> Option<&'static $crate::stm::CommitGroup> {
+                    unsafe {
+                        if GROUP_COMMIT[I] {
+                            Some(&*GROUPS[I])
+                        } else {
+                            None
+                        }
+                    }
                 }
     
                 #[allow(unused_unsafe)]
                 #[track_caller]
-                fn open_no_root(path: &str, flags: u32) -> Result<PoolGuard<Self>> {
+                fn open_no_root_with(path: &str, opts: &PoolOptions) -> Result<PoolGuard<Self>> {
                     unsafe {
-                        while OPEN.compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed).is_err() {}
+                        while OPEN[I].compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed).is_err() {}
                         if !Self::running_transaction() {
-                            if flags == open_flags::O_READINFO {
-                                Self::open_impl(path, true)
-                            } else if let Ok(_) = Self::apply_flags(path, flags) {
-                                let res = Self::open_impl(path, false);
+                            SCRATCHPAD_SIZE[I] = opts.get_scratchpad_size();
+                            let res = if opts.get_read_info() {
+                                let res = Self::open_impl(path, opts);
                                 if res.is_ok() {
-                                    Self::recover();
+                                    Self::set_options(opts);
+                                }
+                                res
+                            } else if opts.get_read_only() {
+                                // Read-only pools are not recovered
+                                let res = opts.validate().and_then(|_| Self::open_impl(path, opts));
+                                if res.is_ok() {
+                                    Self::set_options(opts);
+                                    Self::load_types(false);
                                 }
                                 res
                             } else {
-                                OPEN.store(false, Ordering::Release);
-                                Err("Could not open file".to_string())
+                                // The file is locked once before it is created
+                                // or formatted, and it stays locked while the
+                                // pool is open
+                                let res = open_locked(path, opts).and_then(|file| {
+                                    Self::apply_options(path, opts)?;
+                                    Self::open_file(file, path, opts)
+                                });
+                                if res.is_ok() {
+                                    Self::set_options(opts);
+                                    if opts.get_recover() {
+                                        Self::recover();
+                                    }
+                                    Self::load_types(opts.get_recover());
+                                }
+                                res
+                            };
+                            if res.is_err() {
+                                OPEN[I].store(false, Ordering::Release);
                             }
+                            res
                         } else {
-                            OPEN.store(false, Ordering::Release);
-                            Err("An uncommitted transaction exists in the pool"
-                                .to_string())
+                            OPEN[I].store(false, Ordering::Release);
+                            Err(Error::InTransaction)
                         }
                     }
                 }
     
                 #[allow(unused_unsafe)]
                 unsafe fn close() -> Result<()> {
-                    if OPEN.load(Ordering::Acquire) {
-                        let mut vdata = match VDATA.lock() {
+                    if OPEN[I].load(Ordering::Acquire) {
+                        let mut vdata = match VDATA[I].lock() {
                             Ok(g) => g,
                             Err(p) => p.into_inner()
                         };
+                        // The region added by growing the pool is unmapped
+                        // together with the mapping
                         *vdata = None;
-                        BUDDY_INNER = None;
-                        OPEN.store(false, Ordering::Release);
+                        BUDDY_INNER[I] = None;
+                        BUDDY_FILE[I] = None;
+                        BUDDY_MAPPED[I] = 0;
+                        BUDDY_RESERVED[I] = 0;
+                        AUTO_GROW[I] = false;
+                        RECOVERY_INFO[I] = 0;
+                        SCRATCHPAD_SIZE[I] = DEFAULT_SCRATCHPAD_SIZE;
+                        READ_ONLY[I] = false;
+                        REDO_LOG[I] = false;
+                        GROUP_COMMIT[I] = false;
+                        PAGE_SLOTS[I] = DEFAULT_JOURNAL_PAGE_SLOTS;
+                        JOURNAL_CACHE[I] = 0;
+                        DEADLOCK_DETECTION[I] = false;
+                        TYPES[I] = None;
+                        OPEN[I].store(false, Ordering::Release);
                         Ok(())
                     } else {
-                        Err("Pool was already closed".to_string())
+                        Err(Error::PoolNotOpen)
                     }
                 }
     
+                #[inline]
+                fn scratchpad_size() -> usize {
+                    unsafe { SCRATCHPAD_SIZE[I] }
+                }
+
+                fn snapshot(dest: &str) -> Result<()> {
+                    Self::snapshot_impl(dest, false).map(|_| ())
+                }
+
+                fn snapshot_incremental(dest: &str) -> Result<usize> {
+                    Self::snapshot_impl(dest, true)
+                }
+
+                fn leaks<U: PSafe + RootObj<Self> + Trace<Self>>(collect: bool) -> Result<LeakReport> {
+                    if !Self::is_open() {
+                        return Err(Error::PoolNotOpen);
+                    }
+                    if $crate::stm::Journal::<Self>::is_running() {
+                        return Err(Error::InTransaction);
+                    }
+                    if collect && Self::is_read_only() {
+                        return Err(Error::ReadOnly);
+                    }
+                    let (leaks, reachable) = Self::quiescent(|| Self::find_leaks::<U>())?;
+
+                    // The leaked blocks are unreachable, so they remain
+                    // allocated after releasing the lock
+                    let collected = collect && !leaks.is_empty();
+                    if collected {
+                        Self::transaction(|j| {
+                            for &(off, len) in &leaks {
+                                $crate::stm::Log::drop_on_commit(off, len, j);
+                            }
+                        })?;
+                    }
+                    Ok(LeakReport { leaks, reachable, collected })
+                }
+
+                fn heap_iter() -> Result<HeapIter> {
+                    if !Self::is_open() {
+                        return Err(Error::PoolNotOpen);
+                    }
+                    if let Some(types) = unsafe { &TYPES[I] } {
+                        Ok(HeapIter::new(types.entries::<Self>()))
+                    } else {
+                        Err(Error::NoTypeInfo)
+                    }
+                }
+
+                #[allow(unused_unsafe)]
+                fn fsck(path: &str, repair: bool) -> Result<CheckReport> {
+                    use std::convert::TryInto;
+                    use std::io::Read;
+
+                    if Self::is_open() {
+                        return Err(Error::PoolOpen);
+                    }
+                    let mut report = CheckReport::default();
+
+                    // The magic number and the format version are checked
+                    // before mapping the file
+                    let mut header = [0u8; 12];
+                    let mut file = std::fs::File::open(path)?;
+                    if file.read_exact(&mut header).is_err()
+                        || file.metadata()?.len()
+                            < mem::size_of::<BuddyAllocInner<I>>() as u64 {
+                        report.errors.push("the pool file is too small".to_string());
+                        return Ok(report);
+                    }
+                    let magic = u64::from_ne_bytes(header[..8].try_into().unwrap());
+                    let version = u32::from_ne_bytes(header[8..].try_into().unwrap());
+                    match BuddyAllocInner::<I>::check_format(magic, version) {
+                        Ok(()) => {}
+                        Err(Error::UnsupportedVersion(v)) => {
+                            report.errors.push(format!("unsupported format version {}", v));
+                            return Ok(report);
+                        }
+                        Err(_) => {
+                            report.errors.push("invalid magic number".to_string());
+                            return Ok(report);
+                        }
+                    }
+                    drop(file);
+
+                    let mut opts = PoolOptions::new();
+                    if repair {
+                        opts.recover(false);
+                    } else {
+                        opts.read_only(true);
+                    }
+                    let _pool = Self::open_no_root_with(path, &opts)?;
+                    static_inner!(Self::inner(), inner, {
+                        let len = BUDDY_MAPPED[I] as u64;
+                        let zones = &inner.zone;
+                        report.zones = zones.count();
+                        if zones.count() == 0 || zones.quota() == 0 {
+                            report.errors.push("invalid zones".to_string());
+                            return Ok(report);
+                        }
+                        if inner.size as u64 > len {
+                            report.errors.push(format!("pool size {} exceeds the file size {}",
+                                inner.size, len));
+                            return Ok(report);
+                        }
+                        if inner.flags & FLAG_GROWING != 0 {
+                            report.warnings.push("the pool is growing".to_string());
+                        }
+
+                        let mut free = vec![];
+                        for i in 0..zones.count() {
+                            let start = (zones.quota() * i) as u64;
+                            let end = zones[i].end();
+                            if end < start || end > inner.size as u64 {
+                                report.errors.push(format!("zone {} has invalid boundaries \
+                                    0x{:x}..0x{:x}", i, start, end));
+                                free.push(None);
+                                continue;
+                            }
+                            let mut errors = vec![];
+                            let blocks = zones[i].validate(start, &mut errors);
+                            report.free_blocks += blocks.len();
+                            report.errors.extend(errors.into_iter()
+                                .map(|e| format!("zone {}: {}", i, e)));
+                            free.push(Some((start, blocks)));
+                        }
+
+                        if inner.has_root() && inner.root_obj >= inner.size as u64 {
+                            report.errors.push(format!("root object 0x{:x} is out of the pool",
+                                inner.root_obj));
+                        }
+
+                        let mut visited = HashSet::new();
+                        let mut prev = u64::MAX;
+                        let mut curr = inner.journals;
+                        while curr != u64::MAX {
+                            let valid = curr.checked_add(mem::size_of::<$crate::stm::Journal<Self>>() as u64)
+                                .map_or(false, |e| e <= inner.size as u64);
+                            if !valid {
+                                report.errors.push(format!("journal 0x{:x} is out of the pool", curr));
+                                break;
+                            }
+                            if !visited.insert(curr) {
+                                report.errors.push(format!("a cyclic link to journal 0x{:x} detected", curr));
+                                break;
+                            }
+                            let j = Self::get_unchecked::<$crate::stm::Journal<Self>>(curr);
+                            if prev != u64::MAX && j.prev_off() != prev {
+                                report.errors.push(format!("journal 0x{:x} links back to 0x{:x} \
+                                    instead of 0x{:x}", curr, j.prev_off(), prev));
+                            }
+                            if !j.is_committed() {
+                                report.warnings.push(format!("journal 0x{:x} is not committed", curr));
+                            }
+                            report.errors.extend(j.check().into_iter()
+                                .map(|e| format!("journal 0x{:x}: {}", curr, e)));
+                            report.journals += 1;
+                            prev = curr;
+                            curr = j.next_off();
+                        }
+
+                        if repair {
+                            for (i, blocks) in free.iter().enumerate() {
+                                if let Some((start, blocks)) = blocks {
+                                    zones.at(i).rebuild(*start, blocks);
+                                }
+                            }
+                            report.repaired = true;
+                        }
+
+                        Ok(report)
+                    })
+                }
+
                 fn stat_footprint() -> usize {
                     $crate::__cfg_stat_footprint!({
-                        static_inner!(BUDDY_INNER, inner, { inner.zone.stat_footprint() })
+                        static_inner!(Self::inner(), inner, { inner.zone.stat_footprint() })
                     }, {
                         unimplemented!()
                     })
                 }
     
+                fn allocator_stats() -> Result<AllocatorStats> {
+                    if !Self::is_open() {
+                        return Err(Error::PoolNotOpen);
+                    }
+                    static_inner!(Self::inner(), inner, {
+                        let mut zones = vec![];
+                        for i in 0..inner.zone.count() {
+                            // The zones of a pool which this process does
+                            // not modify are read without locking them
+                            zones.push(if READ_ONLY[I] {
+                                inner.zone[i].read_stats()
+                            } else {
+                                inner.zone[i].stats()
+                            });
+                        }
+                        let mut journals = 0;
+                        let mut curr = inner.journals;
+                        while let Ok(j) = Self::deref::<$crate::stm::Journal<Self>>(curr) {
+                            journals += 1;
+                            curr = j.next_off();
+                        }
+                        #[allow(unused_mut)]
+                        let mut stats = AllocatorStats::from_zones(zones, journals);
+                        $crate::__cfg_stat_footprint!({
+                            stats.footprint = Some(inner.zone.stat_footprint());
+                        });
+                        Ok(stats)
+                    })
+                }
+
                 fn print_info() {
                     println!("{:=^80}", " All Zones ");
                     println!("      Total: {} bytes", Self::size());
                     println!("       Used: {} bytes", Self::used());
                     println!("  Available: {} bytes", Self::available());
     
-                    static_inner!(BUDDY_INNER, inner, { 
+                    static_inner!(Self::inner(), inner, { 
                         for i in 0..inner.zone.count() {
                             println!("{:=^80}", format!(" Persistent Memory Zone #{} ", i));
                             println!("       Total      {}", inner.zone[i].size());
@@ -1756,6 +3032,10 @@ macro_rules! pool {
             /// `<`[`Allocator`](./struct.Allocator.html)`>`.
             pub type Journal = $crate::stm::Journal<$name>;
     
+            /// Compact form of [`ReadJournal`](../../stm/struct.ReadJournal.html)
+            /// `<`[`Allocator`](./struct.Allocator.html)`>`.
+            pub type ReadJournal = $crate::stm::ReadJournal<$name>;
+    
             pub mod prc {
                 /// Compact form of [`prc::Weak`](../../../prc/struct.Weak.html)
                 /// `<`[`Allocator`](./struct.Allocator.html)`>`.
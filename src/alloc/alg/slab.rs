@@ -0,0 +1,610 @@
+use crate::alloc::*;
+use crate::utils::*;
+use std::marker::PhantomData;
+use std::mem;
+
+/// The size of a slab in bytes
+pub const SLAB_SIZE: usize = 4096;
+
+/// The size classes of the slab allocator
+///
+/// Allocations larger than the last class are directly served by the
+/// underlying buddy allocator.
+pub const SIZE_CLASSES: [usize; 22] = [
+    8, 16, 24, 32, 48, 64, 80, 96, 112, 128, 160,
+    192, 224, 256, 320, 384, 448, 512, 640, 768, 896, 1024
+];
+
+const NUM_CLASSES: usize = SIZE_CLASSES.len();
+const SLAB_MAGIC: u32 = 0x51AB_51AB;
+const HEADER_SIZE: usize = mem::size_of::<Slab>();
+const HEADER_OFF: u64 = (SLAB_SIZE - HEADER_SIZE) as u64;
+
+#[repr(C)]
+/// Slab header
+///
+/// Each slab is a buddy block of size [`SLAB_SIZE`] which starts with the
+/// objects of its size class followed by this header. The bitmap shows which
+/// objects are in use. Having the header at the end keeps the objects aligned
+/// to the largest power of two that divides their size.
+///
+/// [`SLAB_SIZE`]: ./constant.SLAB_SIZE.html
+struct Slab {
+    /// Next partially-free slab of the same class
+    next: u64,
+    magic: u32,
+    class: u32,
+    bitmap: [u64; 8],
+}
+
+#[inline]
+fn is_full(bitmap: &[u64; 8], count: usize) -> bool {
+    let mut c = count;
+    for w in bitmap {
+        if c == 0 {
+            break;
+        }
+        let mask = if c >= 64 { u64::MAX } else { (1 << c) - 1 };
+        if *w & mask != mask {
+            return false;
+        }
+        c -= c.min(64);
+    }
+    true
+}
+
+impl Slab {
+    #[inline]
+    fn first_free(&self, count: usize) -> Option<usize> {
+        for (i, w) in self.bitmap.iter().enumerate() {
+            if *w != u64::MAX {
+                let b = i * 64 + (!*w).trailing_zeros() as usize;
+                return if b < count { Some(b) } else { None };
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn is_set(&self, b: usize) -> bool {
+        self.bitmap[b / 64] & (1 << (b % 64)) != 0
+    }
+}
+
+#[inline]
+/// Returns the size class index of the given size, if it is small enough
+pub fn get_class(len: usize) -> Option<usize> {
+    SIZE_CLASSES.iter().position(|c| *c >= len)
+}
+
+#[inline]
+/// Returns the smallest size class of objects larger than `len` which are
+/// aligned to `align` in a slab
+pub fn get_aligned_class(len: usize, align: usize) -> Option<usize> {
+    SIZE_CLASSES.iter().position(|c| *c >= len && *c % align == 0)
+}
+
+#[inline]
+const fn objects_per_slab(class: usize) -> usize {
+    (SLAB_SIZE - HEADER_SIZE) / SIZE_CLASSES[class]
+}
+
+#[repr(C)]
+/// Slab Allocation Algorithm
+///
+/// Small objects are grouped into slabs of [`SIZE_CLASSES`] so that they do
+/// not pay the internal fragmentation of rounding up to a power of two. For
+/// instance, a 72-byte object occupies 80 bytes instead of 128 bytes. Each
+/// class keeps a list of its partially-free slabs, and every slab keeps a
+/// bitmap of the used objects.
+///
+/// The slabs themselves, as well as the objects larger than the largest class,
+/// are allocated from an internal [`BuddyAlg`]. All changes to the slab
+/// metadata go through the same auxiliary ring of the buddy allocator, so they
+/// are materialized and recovered together with the buddy free lists.
+///
+/// To use it in a pool, pass it to [`pool!()`] as the algorithm:
+///
+/// ```
+/// corundum::pool!(small, Allocator, SlabAlg);
+///
+/// type P = small::Allocator;
+///
+/// let _pool = P::open_no_root("small.pool", corundum::open_flags::O_CF).unwrap();
+/// P::transaction(|j| {
+///     let b = small::Pbox::new([0u8; 72], j);
+/// }).unwrap();
+/// ```
+///
+/// [`SIZE_CLASSES`]: ./constant.SIZE_CLASSES.html
+/// [`BuddyAlg`]: ./struct.BuddyAlg.html
+/// [`pool!()`]: ./macro.pool.html
+pub struct SlabAlg<A: MemPool> {
+    /// The underlying buddy allocator
+    buddy: BuddyAlg<A>,
+
+    /// Lists of partially-free slabs per size class
+    slabs: [u64; NUM_CLASSES],
+
+    // Marker
+    phantom: PhantomData<A>,
+}
+
+impl<A: MemPool> SlabAlg<A> {
+    /// Pool Initialization with a given device size
+    pub fn init(&mut self, base: u64, size: usize) {
+        // Slabs should be aligned to `SLAB_SIZE` to find their headers
+        let mask = SLAB_SIZE as u64 - 1;
+        let aligned = (base + mask) & !mask;
+        self.buddy.init(aligned, size - (aligned - base) as usize);
+        self.slabs = [u64::MAX; NUM_CLASSES];
+    }
+
+    #[inline]
+    fn slab<'a>(off: u64) -> &'a mut Slab {
+        unsafe { read_addr(A::start() + off + HEADER_OFF) }
+    }
+
+    #[inline]
+    fn slab_of(off: u64) -> u64 {
+        off & !(SLAB_SIZE as u64 - 1)
+    }
+
+    #[inline]
+    fn get_off(b: &u64) -> u64 {
+        let off = b as *const _ as u64;
+        off - A::start()
+    }
+
+    #[inline]
+    fn next_off(slab: u64) -> u64 {
+        slab + HEADER_OFF
+    }
+
+    #[inline]
+    fn bitmap_off(slab: u64, w: usize) -> u64 {
+        slab + HEADER_OFF + 16 + (w * 8) as u64
+    }
+
+    #[inline]
+    /// Adds a new low-level 64-bit log entry
+    pub unsafe fn log(&mut self, off: u64, data: u64) {
+        self.buddy.log(off, data);
+    }
+
+    #[inline]
+    /// Adds a new low-level `DropOnFailure` log entry
+    pub unsafe fn drop_on_failure(&mut self, off: u64, len: usize) {
+        self.buddy.drop_on_failure(off, len);
+    }
+
+    #[inline(always)]
+    /// Begins a failure-atomic section
+    pub unsafe fn prepare(&mut self) {
+        self.buddy.prepare();
+    }
+
+    #[inline]
+    /// Materializes the changes in the auxiliary list and clears the drop log
+    /// records
+    pub unsafe fn perform(&mut self) {
+        self.buddy.perform();
+    }
+
+    #[inline]
+    /// Discards the changes in the auxiliary buffer
+    pub fn discard(&mut self) {
+        self.buddy.discard();
+    }
+
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len`, and materialize them according to the
+    /// `perform` argument. If successful, it returns the offset of the
+    /// allocated object. Otherwise, `u64::MAX` is returned.
+    pub unsafe fn alloc_impl(&mut self, len: usize, perform: bool) -> u64 {
+        if let Some(class) = get_class(len) {
+            self.alloc_class(class, perform)
+        } else {
+            self.buddy.alloc_impl(len, perform)
+        }
+    }
+
+    /// Generates required changes to the metadata for allocating a new memory
+    /// block with the size `len` at an address aligned to `align`, and
+    /// materialize them according to the `perform` argument.
+    ///
+    /// Small objects are placed in the smallest size class whose objects are
+    /// aligned. Small objects cannot be aligned to more than the largest size
+    /// class, in which case `u64::MAX` is returned.
+    pub unsafe fn alloc_aligned_impl(&mut self, len: usize, align: usize, perform: bool) -> u64 {
+        if get_class(len).is_none() {
+            self.buddy.alloc_aligned_impl(len, align, perform)
+        } else if let Some(class) = get_aligned_class(len, align) {
+            self.alloc_class(class, perform)
+        } else {
+            u64::MAX
+        }
+    }
+
+    unsafe fn alloc_class(&mut self, class: usize, perform: bool) -> u64 {
+        let count = objects_per_slab(class);
+        let head = Self::get_off(&self.slabs[class]);
+
+        self.buddy.begin();
+        let s = self.slabs[class];
+        let off = if s == u64::MAX {
+            let s = self.buddy.alloc_locked(SLAB_SIZE);
+            if s == u64::MAX {
+                self.buddy.discard();
+                return u64::MAX;
+            }
+
+            // The slab block is still free until the changes are performed,
+            // so its header is written through the auxiliary list
+            self.buddy.aux_push(Self::next_off(s), u64::MAX);
+            self.buddy.aux_push(Self::next_off(s) + 8, (class as u64) << 32 | SLAB_MAGIC as u64);
+            for w in 0..8 {
+                self.buddy.aux_push(Self::bitmap_off(s, w), if w == 0 { 1 } else { 0 });
+            }
+            self.buddy.aux_push(head, s);
+            s
+        } else {
+            let slab = Self::slab(s);
+            let b = slab.first_free(count)
+                .expect("A full slab exists in the list of partially-free slabs");
+            let w = b / 64;
+            self.buddy.aux_push(Self::bitmap_off(s, w), slab.bitmap[w] | (1 << (b % 64)));
+
+            let mut bitmap = slab.bitmap;
+            bitmap[w] |= 1 << (b % 64);
+            if is_full(&bitmap, count) {
+                self.buddy.aux_push(head, slab.next);
+            }
+            s + (b * SIZE_CLASSES[class]) as u64
+        };
+        self.buddy.finish(perform);
+        off
+    }
+
+    /// Generates required changes to the metadata for reclaiming the object
+    /// at offset `off` with the size of `len`, and materialize them according
+    /// to the `perform` argument.
+    ///
+    /// The size class is taken from the slab header, because an aligned
+    /// object may be in a larger size class than `len`.
+    pub unsafe fn dealloc_impl(&mut self, off: u64, len: usize, perform: bool) {
+        if get_class(len).is_none() {
+            return self.buddy.dealloc_impl(off, len, perform);
+        }
+        let s = Self::slab_of(off);
+        let slab = Self::slab(s);
+        debug_assert_eq!(slab.magic, SLAB_MAGIC, "offset @{} is not in a slab", off);
+        debug_assert!(SIZE_CLASSES[slab.class as usize] >= len,
+            "offset @{} ({}) is in a smaller size class", off, len);
+
+        let class = slab.class as usize;
+        let count = objects_per_slab(class);
+        let head = Self::get_off(&self.slabs[class]);
+
+        self.buddy.begin();
+        let b = (off - s) as usize;
+        debug_assert_eq!(b % SIZE_CLASSES[class], 0, "offset @{} is not aligned to its object", off);
+        let b = b / SIZE_CLASSES[class];
+        let w = b / 64;
+
+        let was_full = is_full(&slab.bitmap, count);
+        let mut bitmap = slab.bitmap;
+        bitmap[w] &= !(1 << (b % 64));
+        self.buddy.aux_push(Self::bitmap_off(s, w), bitmap[w]);
+
+        if was_full {
+            // Bring it back to the list of partially-free slabs
+            self.buddy.aux_push(Self::next_off(s), self.slabs[class]);
+            self.buddy.aux_push(head, s);
+        } else if bitmap.iter().all(|w| *w == 0) &&
+            !(self.slabs[class] == s && slab.next == u64::MAX) {
+            // Return the empty slab to the buddy allocator, unless it is the
+            // only slab of its class
+            let mut prev = head;
+            let mut curr = self.slabs[class];
+            while curr != s {
+                debug_assert_ne!(curr, u64::MAX, "slab @{} is not in its list", s);
+                prev = Self::next_off(curr);
+                curr = Self::slab(curr).next;
+            }
+            self.buddy.aux_push(prev, slab.next);
+            self.buddy.dealloc_locked(s, SLAB_SIZE);
+        }
+        self.buddy.finish(perform);
+    }
+
+    #[inline]
+    /// Determines if the given address range is allocated
+    pub fn is_allocated(&mut self, off: u64, len: usize) -> bool {
+        if !self.buddy.is_allocated(off, len) {
+            return false;
+        }
+        if get_class(len).is_none() {
+            // Large objects are directly allocated from the buddy allocator
+            return true;
+        }
+        let s = Self::slab_of(off);
+        let slab = Self::slab(s);
+        if slab.magic != SLAB_MAGIC || slab.class as usize >= NUM_CLASSES {
+            return false;
+        }
+        let class = slab.class as usize;
+        let size = SIZE_CLASSES[class];
+        let b = (off - s) as usize;
+        let e = b + len.max(1) - 1;
+        b / size == e / size && b / size < objects_per_slab(class)
+            && slab.is_set(b / size)
+    }
+
+    /// Starts the recovery procedure
+    ///
+    /// See [`BuddyAlg::recover`](./struct.BuddyAlg.html#method.recover) for
+    /// more details.
+    pub fn recover(&mut self) {
+        let self_mut = self as *mut Self;
+        self.buddy.recover_with(|off, len| unsafe {
+            (*self_mut).dealloc_impl(off, len, false);
+        });
+    }
+
+    pub fn recovery_info(&self, info_level: u32) -> String {
+        let mut res = self.buddy.recovery_info(info_level);
+        if info_level > 1 {
+            res += &format!("Partially-free Slabs:      {}\n", self.slab_count());
+        }
+        res
+    }
+
+    fn slab_count(&self) -> usize {
+        let mut cnt = 0;
+        for class in 0..NUM_CLASSES {
+            let mut curr = self.slabs[class];
+            while curr != u64::MAX {
+                cnt += 1;
+                curr = Self::slab(curr).next;
+            }
+        }
+        cnt
+    }
+
+    /// Adds the free region `off..off+len` to the allocator
+    pub unsafe fn extend(&mut self, off: u64, len: usize) {
+        self.buddy.extend(off, len);
+    }
+
+    #[inline]
+    /// Returns the end offset of the region managed by this allocator
+    pub fn end(&self) -> u64 {
+        self.buddy.end()
+    }
+
+    #[inline]
+    /// Returns the pool size
+    pub fn size(&self) -> usize {
+        self.buddy.size()
+    }
+
+    #[inline]
+    /// Returns the total available space in the pool
+    ///
+    /// The free objects inside the slabs are not counted as available.
+    pub fn available(&self) -> usize {
+        self.buddy.available()
+    }
+
+    #[inline]
+    /// Returns the total number of bytes used from the pool
+    pub fn used(&self) -> usize {
+        self.buddy.used()
+    }
+
+    #[cfg(feature = "stat_footprint")]
+    /// Returns the total number of bytes written to the pool
+    pub fn stat_footprint(&self) -> usize {
+        self.buddy.stat_footprint()
+    }
+
+    /// Returns the statistics of the underlying buddy allocator
+    ///
+    /// The free objects inside the slabs are counted as used.
+    pub fn stats(&mut self) -> ZoneStats {
+        self.buddy.stats()
+    }
+
+    /// Returns the statistics of the underlying buddy allocator without
+    /// taking the lock
+    pub fn read_stats(&self) -> ZoneStats {
+        self.buddy.read_stats()
+    }
+
+    pub fn check(&self, f: &str) {
+        self.buddy.check(f);
+    }
+
+    pub fn verify(&mut self) -> bool {
+        self.buddy.verify()
+    }
+
+    /// Validates the free lists and the partially-free slabs of the zone which
+    /// starts at offset `start`
+    ///
+    /// See [`BuddyAlg::validate`](./struct.BuddyAlg.html#method.validate) for
+    /// more details.
+    pub fn validate(&self, start: u64, errors: &mut Vec<String>) -> Vec<(u64, usize)> {
+        let blocks = self.buddy.validate(start, errors);
+        let mut visited = std::collections::HashSet::new();
+        for (class, size) in SIZE_CLASSES.iter().enumerate() {
+            let mut curr = self.slabs[class];
+            while curr != u64::MAX {
+                if !self.is_valid_slab(start, curr, class) {
+                    errors.push(format!("invalid slab 0x{:x} in the list of class {}",
+                        curr, size));
+                    break;
+                }
+                if !visited.insert(curr) {
+                    errors.push(format!("a cyclic link to slab 0x{:x} detected in the \
+                        list of class {}", curr, size));
+                    break;
+                }
+                let end = curr + SLAB_SIZE as u64;
+                if blocks.iter().any(|&(b, idx)| b < end && b + (1u64 << idx) > curr) {
+                    errors.push(format!("slab 0x{:x} overlaps a free block", curr));
+                }
+                curr = Self::slab(curr).next;
+            }
+        }
+        blocks
+    }
+
+    /// Rebuilds the free lists from the given free blocks, and drops the
+    /// invalid slabs from the lists of partially-free slabs
+    ///
+    /// See [`BuddyAlg::rebuild`](./struct.BuddyAlg.html#method.rebuild) for
+    /// more details.
+    pub unsafe fn rebuild(&mut self, start: u64, blocks: &[(u64, usize)]) {
+        self.buddy.rebuild(start, blocks);
+        for class in 0..NUM_CLASSES {
+            let mut visited = std::collections::HashSet::new();
+            let mut link = &mut self.slabs[class] as *mut u64;
+            while *link != u64::MAX {
+                let curr = *link;
+                let free = blocks.iter().any(|&(b, idx)|
+                    b < curr + SLAB_SIZE as u64 && b + (1u64 << idx) > curr);
+                if !self.is_valid_slab(start, curr, class) || free || !visited.insert(curr) {
+                    *link = u64::MAX;
+                    crate::ll::persist_obj(&*link, true);
+                    break;
+                }
+                link = &mut Self::slab(curr).next;
+            }
+        }
+    }
+
+    /// Finds the allocated blocks and objects of the zone which are not
+    /// reachable
+    ///
+    /// A slab is alive if it has a reachable object or it is in a list of
+    /// partially-free slabs. The unused objects of the live slabs are reported
+    /// individually with the size of their class, and the rest is found by
+    /// the underlying buddy allocator. See
+    /// [`BuddyAlg::leaks`](./struct.BuddyAlg.html#method.leaks) for more
+    /// details.
+    pub fn leaks(&self, reachable: &[(u64, usize)]) -> Vec<(u64, usize)> {
+        let base = self.end() - self.size() as u64;
+        let mut live = std::collections::BTreeSet::new();
+        let mut objects = std::collections::HashSet::new();
+        let mut blocks = vec![];
+        for &(off, len) in reachable {
+            if off < base || off >= self.end() {
+                continue;
+            }
+            if get_class(len).is_some() {
+                live.insert(Self::slab_of(off));
+                objects.insert(off);
+            } else {
+                blocks.push((off, len));
+            }
+        }
+        for class in 0..NUM_CLASSES {
+            let mut curr = self.slabs[class];
+            while curr != u64::MAX {
+                live.insert(curr);
+                curr = Self::slab(curr).next;
+            }
+        }
+        blocks.extend(live.iter().map(|s| (*s, SLAB_SIZE)));
+
+        // Slabs and large objects are at least 2048 bytes, so the leaked
+        // buddy blocks are never taken for small objects when deallocating
+        let mut leaks = self.buddy.leaks(&blocks);
+        for &s in &live {
+            let slab = Self::slab(s);
+            if slab.magic != SLAB_MAGIC || slab.class as usize >= NUM_CLASSES {
+                continue;
+            }
+            let class = slab.class as usize;
+            let size = SIZE_CLASSES[class];
+            for b in 0..objects_per_slab(class) {
+                let off = s + (b * size) as u64;
+                if slab.is_set(b) && !objects.contains(&off) {
+                    leaks.push((off, size));
+                }
+            }
+        }
+        leaks.sort_unstable();
+        leaks
+    }
+
+    fn is_valid_slab(&self, start: u64, s: u64, class: usize) -> bool {
+        s >= start && s % SLAB_SIZE as u64 == 0
+            && s + SLAB_SIZE as u64 <= self.end()
+            && {
+                let slab = Self::slab(s);
+                slab.magic == SLAB_MAGIC && slab.class as usize == class
+            }
+    }
+
+    /// Prints the free lists and the partially-free slabs
+    pub fn print(&self) {
+        self.buddy.print();
+        for (class, size) in SIZE_CLASSES.iter().enumerate() {
+            print!("{:>12} [slab] ", size);
+            let mut curr = self.slabs[class];
+            while curr != u64::MAX {
+                let slab = Self::slab(curr);
+                let used: u32 = slab.bitmap.iter().map(|w| w.count_ones()).sum();
+                print!("({}:{}/{})", curr, used, objects_per_slab(class));
+                curr = slab.next;
+            }
+            println!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alloc::MemPoolTraits;
+    use crate::open_flags::*;
+
+    crate::pool!(slab_pool, Allocator, SlabAlg);
+    type P = slab_pool::Allocator;
+
+    #[test]
+    fn slab_alg_test() {
+        let _pool = P::open_no_root("slab.pool", O_CF).unwrap();
+        let used = P::used();
+
+        unsafe {
+            let v: Vec<_> = (0..200).map(|_| P::alloc(72)).collect();
+
+            // 200 objects of 80 bytes take 4 slabs
+            assert_eq!(P::used() - used, 4 * super::SLAB_SIZE);
+            for (p, _, len) in v {
+                P::dealloc(p, len);
+            }
+        }
+
+        // The last empty slab of the class is kept
+        assert_eq!(P::used(), used + super::SLAB_SIZE);
+
+        P::transaction(|j| {
+            let mut v = vec![];
+            for i in 0..200u8 {
+                v.push(slab_pool::Pbox::new([i; 72], j));
+            }
+            for (i, b) in v.iter().enumerate() {
+                assert_eq!(b[71], i as u8);
+            }
+        }).unwrap();
+
+        P::transaction(|j| {
+            let _b = slab_pool::Pbox::new([0u8; 4000], j);
+            let _b = slab_pool::Pbox::new(1u64, j);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use crate::alloc::MemPool;
+use crate::result::Result;
+use std::fmt;
+
+/// The result of checking the consistency of a pool file
+///
+/// It is returned by [`check()`] and [`repair()`].
+///
+/// [`check()`]: ./fn.check.html
+/// [`repair()`]: ./fn.repair.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckReport {
+    /// Description of the inconsistencies
+    pub errors: Vec<String>,
+
+    /// Description of the states which are fixed by the recovery procedure
+    /// when the pool is opened normally
+    pub warnings: Vec<String>,
+
+    /// Number of zones
+    pub zones: usize,
+
+    /// Number of journals
+    pub journals: usize,
+
+    /// Number of valid free blocks
+    pub free_blocks: usize,
+
+    /// Shows that the free lists are rebuilt
+    pub repaired: bool,
+}
+
+impl CheckReport {
+    /// Returns true if no inconsistency is found
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Zones:       {}", self.zones)?;
+        writeln!(f, "Journals:    {}", self.journals)?;
+        writeln!(f, "Free blocks: {}", self.free_blocks)?;
+        for w in &self.warnings {
+            writeln!(f, "warning: {}", w)?;
+        }
+        for e in &self.errors {
+            writeln!(f, "error: {}", e)?;
+        }
+        if self.repaired {
+            writeln!(f, "The free lists are rebuilt")?;
+        }
+        write!(f, "{}", if self.is_ok() { "OK" } else { "FAILED" })
+    }
+}
+
+/// Checks the consistency of a pool file without recovering it
+///
+/// It validates the header, the zone boundaries, the free lists, and the
+/// journals with their log entries. The pool file is opened in the read-only
+/// mode, so it should not be open in this or another process.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::default::*;
+/// # type P = Allocator;
+/// {
+///     let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+/// }
+/// let report = corundum::check::<P>("foo.pool").unwrap();
+/// assert!(report.is_ok(), "{}", report);
+/// ```
+pub fn check<P: MemPool>(path: &str) -> Result<CheckReport> {
+    P::fsck(path, false)
+}
+
+/// Checks the consistency of a pool file and rebuilds its free lists
+///
+/// The free lists are rebuilt from the valid free blocks, and the rest of each
+/// zone, including the reachable allocations, is kept allocated. The space of
+/// the invalid free blocks is not reclaimed. The pending low-level allocator
+/// logs are discarded. The report contains the inconsistencies found before
+/// repairing.
+pub fn repair<P: MemPool>(path: &str) -> Result<CheckReport> {
+    P::fsck(path, true)
+}
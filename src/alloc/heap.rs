@@ -44,6 +44,15 @@ unsafe impl MemPoolTraits for Heap {
         (x, off, size, 0)
     }
 
+    unsafe fn pre_alloc_aligned(size: usize, align: usize) -> (*mut u8, u64, usize, usize) {
+        Self::discard(0);
+        let x = alloc(Layout::from_size_align_unchecked(size, align));
+        let off = x as u64;
+        log!(Self, Green, "", "PRE: {:<6}  ({:>6x}:{:<6x}) = {:<6} POST = {:<6}",
+            0, off, off + size as u64 - 1, size, 0);
+        (x, off, size, 0)
+    }
+
     unsafe fn pre_dealloc(ptr: *mut u8, size: usize) -> usize {
         Self::discard(0);
         let _start = ptr as u64;
@@ -91,25 +100,25 @@ unsafe impl MemPoolTraits for Heap {
         logs.clear()
     }
 
-    fn open_no_root(_path: &str, _flags: u32) -> Result<PoolGuard<Self>> {
+    fn open_no_root_with(_path: &str, opts: &PoolOptions) -> Result<PoolGuard<Self>> {
+        opts.apply_process_settings();
         Ok(PoolGuard::<Self>::new())
     }
 
     #[track_caller]
-    fn open<'a, U: 'a + PSafe + RootObj<Self>>(
+    fn open_with<'a, U: 'a + PSafe + RootObj<Self>>(
         path: &str,
-        flags: u32,
+        opts: &PoolOptions,
     ) -> Result<RootCell<'a, U, Self>> {
-        let slf = Self::open_no_root(path, flags)?;
+        let slf = Self::open_no_root_with(path, opts)?;
         if std::mem::size_of::<U>() == 0 {
-            Err("root type cannot be a ZST".to_string())
+            Err(Error::ZeroSizedRoot)
         } else {
             unsafe {
                 let root_off = Self::transaction(move |j| {
                     let ptr = Self::new(U::init(j), j);
                     Self::off_unchecked(ptr)
-                })
-                .unwrap();
+                })?;
                 let ptr = Self::get_unchecked(root_off);
                 Ok(RootCell::new(ptr, Arc::new(slf)))
             }
@@ -120,7 +129,7 @@ unsafe impl MemPoolTraits for Heap {
         true
     }
 
-    unsafe fn format(_path: &str) -> Result<()> {
+    unsafe fn format_with(_path: &str, _opts: &PoolOptions) -> Result<()> {
         Ok(())
     }
 
@@ -1,12 +1,21 @@
 //! Persistent Memory allocation APIs
 
 mod alg;
+mod fsck;
+mod options;
 mod pool;
+mod stats;
+mod types;
 
 pub mod heap;
 
 pub use alg::buddy::*;
+pub use alg::slab::*;
+pub use fsck::*;
+pub use options::*;
 pub use pool::*;
+pub use stats::*;
+pub use types::*;
 
 /// Determines how much of the `MemPool` is used for the trait object.
 ///
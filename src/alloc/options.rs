@@ -0,0 +1,576 @@
+use crate::alloc::open_flags::*;
+use crate::alloc::DEFAULT_POOL_SIZE;
+use crate::result::Result;
+use crate::Error;
+
+/// Default size of the scratchpad memory in bytes
+pub const DEFAULT_SCRATCHPAD_SIZE: usize = 1024;
+
+/// Default number of log slots in a journal page
+pub const DEFAULT_JOURNAL_PAGE_SLOTS: usize = 128;
+
+/// Options and flags which can be used to configure how a pool is opened
+///
+/// This builder exposes the ability to configure how a pool file is created,
+/// formatted, and opened. It replaces the [`open_flags`] and the environment
+/// variables (e.g. `CPUS`, `RECOVERY_INFO`, and `SPD_SIZE`), so that the
+/// pools can be configured by the application, and different pools in the
+/// same process may have different settings.
+///
+/// The legacy [`open_flags`] are converted to `PoolOptions` using
+/// [`from_flags()`], in which case the environment variables still take
+/// effect.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::PoolOptions;
+///
+/// type P = Allocator;
+///
+/// let _pool = P::open_no_root_with("foo.pool", PoolOptions::new()
+///     .create(true)
+///     .format(true)
+///     .size(16 * 1024 * 1024)
+///     .zones(2)).unwrap();
+///
+/// assert_eq!(P::size(), 16 * 1024 * 1024);
+/// ```
+///
+/// [`open_flags`]: ./open_flags/index.html
+/// [`from_flags()`]: #method.from_flags
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolOptions {
+    size: u64,
+    create: bool,
+    create_if_missing: bool,
+    format: bool,
+    zones: Option<usize>,
+    recovery_info: u32,
+    scratchpad_size: usize,
+    read_only: bool,
+    grow: bool,
+    reserve: Option<usize>,
+    read_info: bool,
+    wait_lock: bool,
+    recover: bool,
+    type_info: bool,
+    redo_log: bool,
+    group_commit: bool,
+    page_slots: usize,
+    journal_cache: usize,
+    deadlock_detection: bool,
+    crash_prob: Option<u64>,
+    verbose: Option<bool>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_POOL_SIZE,
+            create: false,
+            create_if_missing: false,
+            format: false,
+            zones: None,
+            recovery_info: 0,
+            scratchpad_size: DEFAULT_SCRATCHPAD_SIZE,
+            read_only: false,
+            grow: false,
+            reserve: None,
+            read_info: false,
+            wait_lock: false,
+            recover: true,
+            type_info: false,
+            redo_log: false,
+            group_commit: false,
+            page_slots: DEFAULT_JOURNAL_PAGE_SLOTS,
+            journal_cache: 0,
+            deadlock_detection: false,
+            crash_prob: None,
+            verbose: None,
+        }
+    }
+}
+
+impl PoolOptions {
+    /// Creates a blank set of options which opens an existing pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a set of options with the values of the legacy environment
+    /// variables, if they are set
+    ///
+    /// * `CPUS`: number of zones
+    /// * `RECOVERY_INFO`: recovery verbosity level
+    /// * `SPD_SIZE`: scratchpad size in bytes
+    /// * `CRASH_PROB`: probability of crashing at [`may_crash!()`] in 1/10000
+    /// * `VERBOSE`: prints the logs if it is `1` (with the `verbose` feature)
+    ///
+    /// [`may_crash!()`]: ../macro.may_crash.html
+    pub fn from_env() -> Self {
+        let mut opts = Self::default();
+        if let Ok(val) = std::env::var("CPUS") {
+            opts.zones = Some(val.parse::<usize>()
+                .expect("CPUS should be an unsigned integer"));
+        }
+        if let Ok(val) = std::env::var("RECOVERY_INFO") {
+            opts.recovery_info = val.parse::<u32>()
+                .expect("RECOVERY_INFO should be an unsigned integer");
+        }
+        if let Ok(val) = std::env::var("SPD_SIZE") {
+            opts.scratchpad_size = val.parse::<usize>()
+                .expect("SPD_SIZE should be an unsigned integer");
+        }
+        if let Ok(val) = std::env::var("CRASH_PROB") {
+            opts.crash_prob = Some(val.parse::<u64>()
+                .expect("CRASH_PROB should be a non-negative integer"));
+        }
+        if let Ok(val) = std::env::var("VERBOSE") {
+            opts.verbose = Some(val == "1");
+        }
+        opts
+    }
+
+    /// Sets the size of the pool file in bytes, if it is created
+    pub fn size(&mut self, size: u64) -> &mut Self {
+        self.size = size;
+        self
+    }
+
+    /// Creates a new pool file, replacing the existing one (`O_C`)
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates a new pool file only if it does not exist (`O_CNE`)
+    pub fn create_if_missing(&mut self, create: bool) -> &mut Self {
+        self.create_if_missing = create;
+        self
+    }
+
+    /// Formats the pool file if it is newly created, or if it exists and no
+    /// create option is set (`O_F`)
+    pub fn format(&mut self, format: bool) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the number of allocation zones of a newly formatted pool. The
+    /// default value is the number of CPUs.
+    pub fn zones(&mut self, zones: usize) -> &mut Self {
+        self.zones = Some(zones);
+        self
+    }
+
+    /// Sets the verbosity level of the recovery information printed to the
+    /// standard error while opening the pool. Zero disables it.
+    pub fn recovery_info(&mut self, level: u32) -> &mut Self {
+        self.recovery_info = level;
+        self
+    }
+
+    /// Sets the size of the scratchpad memory in bytes
+    pub fn scratchpad_size(&mut self, size: usize) -> &mut Self {
+        self.scratchpad_size = size;
+        self
+    }
+
+    /// Opens the pool in the read-only mode (`O_RDONLY`)
+    ///
+    /// The pool file is mapped copy-on-write and is never written, and it is
+    /// not recovered. Any transaction or allocation on the pool fails, but the
+    /// objects can be read, and read transactions can lock them.
+    ///
+    /// A read-only pool does not lock the pool file, so several processes may
+    /// open it at the same time, even while another process owns it for
+    /// writing. In that case, the reader is not isolated from the writer: the
+    /// pages which the reader has not modified in its private mapping show
+    /// the changes of the writer as they are made, including the ones of the
+    /// transactions in progress.
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Grows the pool automatically when it runs out of space (`O_GROW`)
+    pub fn grow(&mut self, grow: bool) -> &mut Self {
+        self.grow = grow;
+        self
+    }
+
+    /// Sets the size of the address space which is reserved for the pool
+    /// when it is opened, so that it can grow in place up to this size
+    ///
+    /// If it is not set, [`DEFAULT_RESERVE_FACTOR`] times the size of the pool
+    /// file is reserved. A pool which grows beyond its reservation extends it
+    /// if the address range right after it is free. Otherwise, growing fails,
+    /// because the mapping cannot move while there are references to the
+    /// persistent objects.
+    ///
+    /// [`DEFAULT_RESERVE_FACTOR`]: ./constant.DEFAULT_RESERVE_FACTOR.html
+    pub fn reserve(&mut self, size: usize) -> &mut Self {
+        self.reserve = Some(size);
+        self
+    }
+
+    /// Opens the pool only to read its information, without checking the
+    /// magic number or recovering it (`O_READINFO`)
+    ///
+    /// Like a [read-only](#method.read_only) pool, the file is mapped
+    /// copy-on-write and is not locked, so that it can be inspected while
+    /// another process owns it.
+    pub fn read_info(&mut self, read_info: bool) -> &mut Self {
+        self.read_info = read_info;
+        self
+    }
+
+    /// Runs the recovery procedure when the pool is opened. The default value
+    /// is `true`. Pools opened in the read-only mode are never recovered.
+    pub fn recover(&mut self, recover: bool) -> &mut Self {
+        self.recover = recover;
+        self
+    }
+
+    /// Keeps the type information of the allocations in a newly formatted
+    /// pool, so that they can be listed using [`heap_iter()`]
+    ///
+    /// Each allocation made through the typed interfaces records its type and
+    /// length in the allocator metadata, which costs an extra record per
+    /// block. It is a property of the pool file, and takes effect only when
+    /// the pool is formatted.
+    ///
+    /// [`heap_iter()`]: ./trait.MemPoolTraits.html#method.heap_iter
+    pub fn type_info(&mut self, type_info: bool) -> &mut Self {
+        self.type_info = type_info;
+        self
+    }
+
+    /// Keeps redo logs instead of undo logs for the updates of [`PCell`] and
+    /// [`PRefCell`] objects in the transactions of this pool
+    ///
+    /// With undo logging, the first update of an object copies and flushes
+    /// its old value before updating it in place. With redo logging, the
+    /// update goes to a draft in the journal, and the drafts are flushed and
+    /// copied to their original locations when the transaction commits. It
+    /// suits write-heavy transactions. Recovery completes the transactions
+    /// which crashed after their redo logs became durable.
+    ///
+    /// It is a property of the open pool, and does not change the pool file.
+    /// Other objects, and the transactions inside a chaperoned session, still
+    /// use undo logs.
+    ///
+    /// [`PCell`]: ../cell/struct.PCell.html
+    /// [`PRefCell`]: ../cell/struct.PRefCell.html
+    pub fn redo_log(&mut self, redo_log: bool) -> &mut Self {
+        self.redo_log = redo_log;
+        self
+    }
+
+    /// Commits the concurrent transactions of this pool in groups
+    ///
+    /// Instead of fencing every transaction individually, the transactions
+    /// which finish at the same time are committed together with a single
+    /// fence (see [`CommitGroup`]). A transaction returns when its group is
+    /// durable, so it may wait for the others. It suits many small
+    /// transactions running in parallel. The atomicity of every transaction
+    /// and the recovery procedure are not affected.
+    ///
+    /// It is a property of the open pool, and does not change the pool file.
+    /// The transactions inside a chaperoned session are not grouped.
+    ///
+    /// [`CommitGroup`]: ../stm/struct.CommitGroup.html
+    pub fn group_commit(&mut self, group_commit: bool) -> &mut Self {
+        self.group_commit = group_commit;
+        self
+    }
+
+    /// Sets the number of log slots in the journal pages of the pool
+    ///
+    /// A journal allocates a new page whenever its current page is full.
+    /// Larger pages suit large transactions, and smaller pages save space
+    /// when there are many threads with small transactions. The default
+    /// value is [`DEFAULT_JOURNAL_PAGE_SLOTS`]. It applies to the pages
+    /// allocated after the pool is opened.
+    ///
+    /// [`DEFAULT_JOURNAL_PAGE_SLOTS`]: ./constant.DEFAULT_JOURNAL_PAGE_SLOTS.html
+    pub fn journal_page_slots(&mut self, slots: usize) -> &mut Self {
+        self.page_slots = slots;
+        self
+    }
+
+    /// Keeps up to `pages` journal pages per thread across transactions
+    ///
+    /// By default, the journal of a thread and its pages are deallocated when
+    /// the transaction finishes. With a non-zero cache size, the journal is
+    /// kept for the next transactions of the thread, and its emptied pages are
+    /// cached, so that the transactions do not allocate new pages. The cache
+    /// keeps as many pages as the recent transactions used, and it is trimmed
+    /// when the extra pages remain unused for a while. The cached pages are
+    /// persistent, and they are reclaimed on recovery.
+    ///
+    /// A thread may release its journal and cached pages at any time using
+    /// [`trim_journal_cache()`](./trait.MemPoolTraits.html#method.trim_journal_cache).
+    pub fn journal_cache(&mut self, pages: usize) -> &mut Self {
+        self.journal_cache = pages;
+        self
+    }
+
+    /// Detects the deadlocks of the transactions waiting for [`PMutex`]es
+    ///
+    /// The mutexes are held until the end of the transactions, so the
+    /// transactions which lock them in different orders may wait for each
+    /// other forever. With this option, the transactions keep a wait-for
+    /// graph, and a transaction which is about to close a cycle in it aborts
+    /// as the victim with a [`Deadlock`] panic. It rolls back and releases its
+    /// locks, so that the others proceed, and it may be retried.
+    ///
+    /// It is a property of the open pool, and does not change the pool file.
+    /// Alternatively, [`PMutex::lock_timeout()`] bounds the waiting time of
+    /// individual locks.
+    ///
+    /// [`PMutex`]: ../sync/struct.PMutex.html
+    /// [`Deadlock`]: ../sync/struct.Deadlock.html
+    /// [`PMutex::lock_timeout()`]: ../sync/struct.PMutex.html#method.lock_timeout
+    pub fn deadlock_detection(&mut self, detect: bool) -> &mut Self {
+        self.deadlock_detection = detect;
+        self
+    }
+
+    /// Sets the probability of crashing the process at every [`may_crash!()`]
+    /// in units of 1/10000, for testing the recovery
+    ///
+    /// It is a process-wide setting which takes effect when the pool is
+    /// opened. Zero disables the crashes. If it is not set, the current
+    /// setting remains.
+    ///
+    /// [`may_crash!()`]: ../macro.may_crash.html
+    pub fn crash_prob(&mut self, prob: u64) -> &mut Self {
+        self.crash_prob = Some(prob);
+        self
+    }
+
+    /// Prints the logs and the allocations, if the `verbose` feature is
+    /// enabled
+    ///
+    /// It is a process-wide setting which takes effect when the pool is
+    /// opened. If it is not set, the current setting remains.
+    pub fn verbose(&mut self, verbose: bool) -> &mut Self {
+        self.verbose = Some(verbose);
+        self
+    }
+
+    /// Waits for other processes to release the pool file, instead of
+    /// failing when it is locked
+    ///
+    /// A pool file is exclusively locked by the process which opens it for
+    /// writing, and it remains locked until the pool is closed. Pools opened
+    /// in the read-only mode or only to read their information do not lock
+    /// the file, so they never wait.
+    pub fn wait_lock(&mut self, wait: bool) -> &mut Self {
+        self.wait_lock = wait;
+        self
+    }
+
+    /// Returns the number of zones, or the number of CPUs if it is not set
+    pub fn zone_count(&self) -> usize {
+        self.zones.unwrap_or_else(num_cpus::get)
+    }
+
+    /// Applies the process-wide settings, if they are set
+    #[doc(hidden)]
+    pub fn apply_process_settings(&self) {
+        if let Some(prob) = self.crash_prob {
+            crate::utils::set_crash_prob(prob);
+        }
+        if let Some(verbose) = self.verbose {
+            crate::utils::set_verbose(verbose);
+        }
+    }
+
+    /// Checks if the options are consistent
+    pub fn validate(&self) -> Result<()> {
+        if self.size == 0 {
+            return Err(Error::InvalidOptions("Pool size cannot be zero".to_string()));
+        }
+        if self.zones == Some(0) {
+            return Err(Error::InvalidOptions("Number of zones cannot be zero".to_string()));
+        }
+        if self.page_slots == 0 {
+            return Err(Error::InvalidOptions("Number of journal page slots cannot be zero".to_string()));
+        }
+        if self.read_only && (self.create || self.create_if_missing || self.format) {
+            return Err(Error::InvalidOptions("Cannot create or format a pool in the read-only mode".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Converts the legacy [`open_flags`](./open_flags/index.html) to options
+    ///
+    /// The environment variables are also taken into account (see
+    /// [`from_env()`](#method.from_env)).
+    pub fn from_flags(flags: u32) -> Result<Self> {
+        let mut opts = Self::from_env();
+        if flags == O_READINFO {
+            opts.read_info = true;
+            return Ok(opts);
+        }
+        let size = (flags & !(O_GROW | O_RDONLY)) as u64 >> 4;
+        if size.count_ones() > 1 {
+            return Err(Error::InvalidOptions("Cannot have multiple size flags".to_string()));
+        } else if size != 0 {
+            if flags & (O_C | O_CNE) == 0 {
+                return Err(Error::InvalidOptions("Cannot use size flag without a create flag".to_string()));
+            }
+            opts.size = size << 30;
+        }
+        opts.create = flags & O_C != 0;
+        opts.create_if_missing = flags & O_CNE != 0;
+        opts.format = flags & O_F != 0;
+        opts.grow = flags & O_GROW != 0;
+        opts.read_only = flags & O_RDONLY != 0;
+        Ok(opts)
+    }
+}
+
+// The options are read by the expansions of `pool!()` in other crates through
+// the accessors, so that they can only be set by the builder
+impl PoolOptions {
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_create(&self) -> bool {
+        self.create
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_create_if_missing(&self) -> bool {
+        self.create_if_missing
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_format(&self) -> bool {
+        self.format
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_recovery_info(&self) -> u32 {
+        self.recovery_info
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_scratchpad_size(&self) -> usize {
+        self.scratchpad_size
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_grow(&self) -> bool {
+        self.grow
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_reserve(&self) -> Option<usize> {
+        self.reserve
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_read_info(&self) -> bool {
+        self.read_info
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_wait_lock(&self) -> bool {
+        self.wait_lock
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_recover(&self) -> bool {
+        self.recover
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_type_info(&self) -> bool {
+        self.type_info
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_redo_log(&self) -> bool {
+        self.redo_log
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_group_commit(&self) -> bool {
+        self.group_commit
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_page_slots(&self) -> usize {
+        self.page_slots
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_journal_cache(&self) -> usize {
+        self.journal_cache
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_deadlock_detection(&self) -> bool {
+        self.deadlock_detection
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::open_flags::*;
+    use crate::PoolOptions;
+
+    #[test]
+    fn from_flags() {
+        let opts = PoolOptions::from_flags(O_CFNE | O_2GB | O_GROW).unwrap();
+        assert!(!opts.create);
+        assert!(opts.create_if_missing);
+        assert!(opts.format);
+        assert!(opts.grow);
+        assert_eq!(opts.size, 2 << 30);
+
+        assert!(PoolOptions::from_flags(O_1GB | O_2GB | O_C).is_err());
+        assert!(PoolOptions::from_flags(O_1GB).is_err());
+        assert!(PoolOptions::from_flags(O_READINFO).unwrap().read_info);
+        assert!(PoolOptions::from_flags(O_RDONLY).unwrap().read_only);
+    }
+
+    #[test]
+    fn validate() {
+        assert!(PoolOptions::new().zones(0).validate().is_err());
+        assert!(PoolOptions::new().read_only(true).format(true).validate().is_err());
+        assert!(PoolOptions::new().create(true).size(1 << 20).validate().is_ok());
+    }
+}
@@ -9,13 +9,34 @@ use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::ops::Range;
 use std::panic::UnwindSafe;
-use std::path::Path;
+use std::alloc::Layout;
 use std::thread::ThreadId;
 use std::{mem, ptr};
 
 /// Default pool memory size to be used while creating a new pool
 pub const DEFAULT_POOL_SIZE: u64 = 8 * 1024 * 1024;
 
+/// The version of the layout of the pool images
+///
+/// It is stored in the header of every pool image when it is formatted, and
+/// it is increased whenever the layout of the header or of the allocator
+/// metadata changes. Opening an image with another version fails with
+/// [`Error::UnsupportedVersion`] instead of misreading it; the image should be
+/// recreated, e.g. by copying the data with a matching version of the crate.
+///
+/// * `0`: the images made before the format had a version
+/// * `1`: the header keeps the type information pages and the end of each zone
+///
+/// [`Error::UnsupportedVersion`]: ./enum.Error.html#variant.UnsupportedVersion
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The largest alignment that persistent allocations can have
+pub const MAX_ALIGN: usize = 4096;
+
+/// The default size of the address space reserved for a pool, to which it can
+/// grow without moving its mapping, as a multiple of the size of the pool file
+pub const DEFAULT_RESERVE_FACTOR: usize = 8;
+
 /// Open pool flags
 pub mod open_flags {
     /// Open Flag: Create the pool memory file
@@ -84,19 +105,30 @@ pub mod open_flags {
     /// Open Flag: Creates a pool memory file of size 64TB
     pub const O_64TB: u32 = 0x00100000;
 
+    /// Open Flag: Grows the pool automatically when it runs out of space
+    pub const O_GROW: u32 = 0x80000000;
+
+    /// Open Flag: Opens the pool in the read-only mode
+    pub const O_RDONLY: u32 = 0x40000000;
+
     /// Open Flag: Open only to read info
     pub const O_READINFO: u32 = u32::MAX;
 }
 
-use open_flags::*;
-
 /// Shows that the pool has a root object
 pub const FLAG_HAS_ROOT: u64 = 0x0000_0001;
 
+/// Shows that the pool file is extended, but the new region is not completely
+/// added to the allocator yet
+pub const FLAG_GROWING: u64 = 0x0000_0002;
+
+/// Shows that the pool keeps the type information of the allocations
+pub const FLAG_TYPE_INFO: u64 = 0x0000_0004;
+
 /// This macro can be used to access static data of an arbitrary allocator
 #[macro_export]
 macro_rules! static_inner {
-    ($id:ident, $inner:ident, $body:block) => {
+    ($id:expr, $inner:ident, $body:block) => {
         unsafe {
             if let Some($inner) = $id {
                 let $inner = &mut *$inner;
@@ -217,9 +249,18 @@ where
     ///   * O_CF:   create and format a new memory pool file
     ///   * O_CFNE: create and format a memory pool file only if not exists
     /// 
-    /// See [`open_flags`](./open_flags/index.html) for more options.
-    fn open_no_root(_path: &str, _flags: u32) -> Result<PoolGuard<Self>> {
-        unimplemented!()
+    /// See [`open_flags`](./open_flags/index.html) for more options, or use
+    /// [`open_no_root_with()`](#method.open_no_root_with) to configure the
+    /// pool with [`PoolOptions`](./struct.PoolOptions.html).
+    fn open_no_root(path: &str, flags: u32) -> Result<PoolGuard<Self>> {
+        Self::open_no_root_with(path, &PoolOptions::from_flags(flags)?)
+    }
+
+    /// Opens a new pool without any root object using the given options
+    ///
+    /// See [`open_no_root()`](#method.open_no_root) for more details.
+    fn open_no_root_with(_path: &str, _opts: &PoolOptions) -> Result<PoolGuard<Self>> {
+        Err(Error::Unsupported)
     }
 
     /// Commits all changes and clears the logs for all threads
@@ -330,7 +371,15 @@ where
     ///
     /// * A volatile memory pool (e.g. `Heap`) doesn't have a root object.
     /// * The pool should be open before accessing the root object.
-    ///
+    /// * The pool file is locked by another process ([`Error::PoolBusy`]). Use
+    ///   [`PoolOptions::wait_lock()`](./struct.PoolOptions.html#method.wait_lock)
+    ///   to wait for it to be released.
+    /// * The file is not a pool image ([`Error::InvalidMagic`]), or its root
+    ///   object has a different type ([`Error::IncompatibleRoot`]).
+    ///
+    /// [`Error::PoolBusy`]: ../enum.Error.html#variant.PoolBusy
+    /// [`Error::InvalidMagic`]: ../enum.Error.html#variant.InvalidMagic
+    /// [`Error::IncompatibleRoot`]: ../enum.Error.html#variant.IncompatibleRoot
     /// [`RootObj`]: ../stm/trait.RootObj.html
     /// [`Prc`]: ../prc/struct.Prc.html
     /// [`Parc`]: ../sync/parc/struct.Parc.html
@@ -338,10 +387,54 @@ where
     /// [`PRefCell`]: ./default/type.PRefCell.html
     /// [`PMutex`]: ./default/type.PMutex.html
     fn open<'a, U: 'a + PSafe + RootObj<Self>> (
+        path: &str,
+        flags: u32,
+    ) -> Result<RootCell<'a, U, Self>> where Self: MemPool {
+        Self::open_with(path, &PoolOptions::from_flags(flags)?)
+    }
+
+    /// Opens a pool using the given options and retrieves the root object
+    ///
+    /// See [`open()`](#method.open) for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::PoolOptions;
+    ///
+    /// let root = Allocator::open_with::<i32>("foo.pool", PoolOptions::new()
+    ///     .create(true)
+    ///     .format(true)
+    ///     .zones(1)
+    ///     .recovery_info(1)).unwrap();
+    ///
+    /// assert_eq!(*root, i32::default());
+    /// ```
+    fn open_with<'a, U: 'a + PSafe + RootObj<Self>> (
         _path: &str,
-        _flags: u32,
+        _opts: &PoolOptions,
     ) -> Result<RootCell<'a, U, Self>> where Self: MemPool {
-        unimplemented!()
+        Err(Error::Unsupported)
+    }
+
+    /// Opens a pool using the given options and retrieves the root object,
+    /// upgrading it if it has an older schema
+    ///
+    /// If the schema fingerprint of the stored root object is different from
+    /// that of `U`, and there is an upgrade function for it in `migrations`,
+    /// the root object is upgraded to `U` in a transaction. Otherwise, it
+    /// fails with [`Error::IncompatibleRoot`]. See [`Migrations`] for an
+    /// example.
+    ///
+    /// [`Error::IncompatibleRoot`]: ../enum.Error.html#variant.IncompatibleRoot
+    /// [`Migrations`]: ../cell/struct.Migrations.html
+    fn open_with_migrations<'a, U: 'a + PSafe + RootObj<Self>> (
+        _path: &str,
+        _opts: &PoolOptions,
+        _migrations: &Migrations<U, Self>,
+    ) -> Result<RootCell<'a, U, Self>> where Self: MemPool {
+        Err(Error::Unsupported)
     }
 
     /// Returns true if the pool is open
@@ -349,36 +442,228 @@ where
         unimplemented!()
     }
 
+    /// Returns true if the pool is open in the read-only mode
+    ///
+    /// A read-only pool is mapped copy-on-write, so that the file is never
+    /// written, and it is not recovered. Transactions and allocations fail on
+    /// a read-only pool.
+    fn is_read_only() -> bool {
+        false
+    }
+
+    /// Returns true if the transactions of the open pool keep redo logs
+    ///
+    /// See [`PoolOptions::redo_log`](./struct.PoolOptions.html#method.redo_log).
+    fn redo_logging() -> bool {
+        false
+    }
+
+    /// Returns the commit group of the open pool if its transactions are
+    /// committed in groups
+    ///
+    /// See [`PoolOptions::group_commit`](./struct.PoolOptions.html#method.group_commit).
+    fn commit_group() -> Option<&'static CommitGroup> {
+        None
+    }
+
+    /// Returns the number of log slots in the new journal pages of the open
+    /// pool
+    ///
+    /// See [`PoolOptions::journal_page_slots`](./struct.PoolOptions.html#method.journal_page_slots).
+    fn journal_page_slots() -> usize {
+        DEFAULT_JOURNAL_PAGE_SLOTS
+    }
+
+    /// Returns the maximum number of journal pages that each thread keeps
+    /// across transactions
+    ///
+    /// See [`PoolOptions::journal_cache`](./struct.PoolOptions.html#method.journal_cache).
+    fn journal_cache() -> usize {
+        0
+    }
+
+    /// Returns true if the transactions of the open pool detect deadlocks
+    ///
+    /// See [`PoolOptions::deadlock_detection`](./struct.PoolOptions.html#method.deadlock_detection).
+    fn deadlock_detection() -> bool {
+        false
+    }
+
+    /// Releases the journal of the current thread with its cached pages, if
+    /// it has no running transaction
+    ///
+    /// The journal of a thread is kept across transactions when the journal
+    /// cache is enabled (see [`PoolOptions::journal_cache`]). A thread may
+    /// call this function when it becomes idle or before it exits.
+    ///
+    /// [`PoolOptions::journal_cache`]: ./struct.PoolOptions.html#method.journal_cache
+    fn trim_journal_cache() where Self: MemPool {
+        unsafe {
+            if let Some(journal) = Journal::<Self>::try_current() {
+                if *journal.1 == 0 {
+                    Self::drop_journal(as_mut(journal.0));
+                    Self::journals(|journals| {
+                        journals.remove(&std::thread::current().id());
+                    });
+                }
+            }
+        }
+    }
+
     /// Formats the memory pool file
-    unsafe fn format(_path: &str) -> Result<()> {
-        unimplemented!()
+    unsafe fn format(path: &str) -> Result<()> {
+        Self::format_with(path, &PoolOptions::from_env())
+    }
+
+    /// Formats the memory pool file using the given options
+    unsafe fn format_with(_path: &str, _opts: &PoolOptions) -> Result<()> {
+        Err(Error::Unsupported)
     }
 
     /// Applies open pool flags
     unsafe fn apply_flags(path: &str, flags: u32) -> Result<()> {
-        let mut size: u64 = flags as u64 >> 4;
-        if size.count_ones() > 1 {
-            return Err("Cannot have multiple size flags".to_string());
-        } else if size == 0 {
-            size = DEFAULT_POOL_SIZE;
-        } else {
-            if flags & (O_C | O_CNE) == 0 {
-                return Err("Cannot use size flag without a create flag".to_string());
-            }
-            size <<= 30;
-        }
-        let mut format = !Path::new(path).exists() && ((flags & O_F) != 0);
-        if ((flags & O_C) != 0) || ((flags & O_CNE != 0) && !Path::new(path).exists()) {
-            let _=std::fs::remove_file(path);
-            create_file(path, size)?;
-            format = (flags & O_F) != 0;
+        Self::apply_options(path, &PoolOptions::from_flags(flags)?)
+    }
+
+    /// Creates and formats the pool file according to the given options
+    unsafe fn apply_options(path: &str, opts: &PoolOptions) -> Result<()> {
+        opts.validate()?;
+        // An empty file is the one which is just created to be locked
+        let exists = std::fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false);
+        let mut format = !exists && opts.get_format();
+        if opts.get_create() || (opts.get_create_if_missing() && !exists) {
+            create_file(path, opts.get_size())?;
+            format = opts.get_format();
         }
         if format {
-            Self::format(path)?;
+            Self::format_with(path, opts)?;
         }
         Ok(())
     }
 
+    /// Checks the consistency of a closed pool file without recovering it,
+    /// and rebuilds its free lists if `repair` is set
+    ///
+    /// See [`check()`](./fn.check.html) and [`repair()`](./fn.repair.html).
+    fn fsck(_path: &str, _repair: bool) -> Result<CheckReport> {
+        Err(Error::Unsupported)
+    }
+
+    /// Finds the allocations of the open pool which are not reachable from
+    /// the root object of type `U`, and frees them if `collect` is set
+    ///
+    /// See [`find_leaks()`](../fn.find_leaks.html) and
+    /// [`collect_leaks()`](../fn.collect_leaks.html).
+    fn leaks<U: PSafe + RootObj<Self> + Trace<Self>>(_collect: bool) -> Result<LeakReport>
+    where Self: MemPool {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns an iterator over the typed allocations of the open pool
+    ///
+    /// The pool should be formatted with [`PoolOptions::type_info`] set, so
+    /// that the allocations made through the typed interfaces, such as
+    /// [`Pbox::new`], [`Prc::new`], and [`PVec`], record their types. It yields
+    /// `(offset, len, type_name)` tuples sorted by offset. The untyped
+    /// allocations, e.g. the ones from [`alloc()`](#method.alloc), and the
+    /// allocator metadata are not listed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # use corundum::PoolOptions;
+    /// # type P = Allocator;
+    /// let _p = P::open_no_root_with("foo.pool", PoolOptions::new()
+    ///     .create(true).format(true).type_info(true)).unwrap();
+    ///
+    /// P::transaction(|j| {
+    ///     let b = Pbox::new(10u64, j);
+    ///     let (_, len, name) = P::heap_iter().unwrap()
+    ///         .find(|(off, _, _)| *off == b.off()).unwrap();
+    ///     assert_eq!(len, 8);
+    ///     assert_eq!(name, "u64");
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`PoolOptions::type_info`]: ./struct.PoolOptions.html#method.type_info
+    /// [`Pbox::new`]: ../boxed/struct.Pbox.html#method.new
+    /// [`Prc::new`]: ../prc/struct.Prc.html#method.new
+    /// [`PVec`]: ../vec/struct.Vec.html
+    fn heap_iter() -> Result<HeapIter> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns the scratchpad size of the open pool in bytes
+    fn scratchpad_size() -> usize {
+        DEFAULT_SCRATCHPAD_SIZE
+    }
+
+    /// Extends the pool to `new_size` bytes
+    ///
+    /// It extends the pool file, remaps it in place, and adds the new region
+    /// to the allocator in a failure-atomic manner. If a crash happens before
+    /// the new region is completely added, the recovery procedure continues
+    /// it. Pools opened with [`O_GROW`] flag grow automatically when there is
+    /// no space left for an allocation.
+    ///
+    /// The pool grows in place within the address space reserved for it when
+    /// it is opened (see [`PoolOptions::reserve()`]). Beyond that, the
+    /// reservation is extended if the address range right after it is free;
+    /// otherwise, it fails with [`Error::InvalidSize`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # type P = Allocator;
+    /// let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// let size = P::size();
+    ///
+    /// P::grow(2 * size).unwrap();
+    /// assert_eq!(P::size(), 2 * size);
+    /// ```
+    ///
+    /// [`O_GROW`]: ./open_flags/constant.O_GROW.html
+    /// [`PoolOptions::reserve()`]: ./struct.PoolOptions.html#method.reserve
+    /// [`Error::InvalidSize`]: ../enum.Error.html#variant.InvalidSize
+    fn grow(_new_size: usize) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Writes a consistent copy of the open pool to `dest`
+    ///
+    /// It waits for a quiescent point where no transaction is running, and
+    /// copies the pool while new transactions are held back. The copy can be
+    /// opened with [`open()`](#method.open) like the original pool file. It
+    /// fails if it is called inside a transaction.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # type P = Allocator;
+    /// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    /// P::transaction(|j| root.set(10, j)).unwrap();
+    /// P::snapshot("foo.backup").unwrap();
+    /// ```
+    fn snapshot(_dest: &str) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Updates a previous snapshot in `dest` with the current state of the
+    /// pool, and returns the number of modified pages
+    ///
+    /// It is similar to [`snapshot()`](#method.snapshot), except that only the
+    /// pages which differ from the previous snapshot are written. The pages
+    /// are compared with the contents of `dest` rather than tracked while the
+    /// pool is modified, so it saves the writes, but not the reads. If `dest`
+    /// does not exist, it writes a full snapshot. The destination file is not
+    /// consistent until it returns successfully.
+    fn snapshot_incremental(_dest: &str) -> Result<usize> {
+        Err(Error::Unsupported)
+    }
+
     /// Indicates if the given offset is allocated
     #[inline]
     fn allocated(_off: u64, _len: usize) -> bool {
@@ -496,7 +781,7 @@ where
         if Self::allocated(off, mem::size_of::<T>()) {
             Ok(Self::get_unchecked(off))
         } else {
-            Err(format!("Access Violation (0x{:x})", off))
+            Err(Error::AccessViolation(off))
         }
     }
 
@@ -506,7 +791,7 @@ where
         if Self::allocated(off, mem::size_of::<T>()) {
             Ok(Self::get_mut_unchecked(off))
         } else {
-            Err(format!("Access Violation (0x{:x})", off))
+            Err(Error::AccessViolation(off))
         }
     }
 
@@ -516,7 +801,7 @@ where
         if Self::valid(x) {
             Ok(x as *const u8 as u64 - Self::start())
         } else {
-            Err(format!("out of valid range ({:p})", x).to_string())
+            Err(Error::AccessViolation(x as *const u8 as u64))
         }
     }
 
@@ -606,6 +891,41 @@ where
         (p, off, len)
     }
 
+    /// Allocates new memory as described by the given `layout`
+    ///
+    /// The memory is aligned to `layout.align()`, which should not be larger
+    /// than [`MAX_ALIGN`]. The returned block is deallocated by [`dealloc`]
+    /// with `layout.size()`, as the alignment is not needed to reclaim it.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `layout.align()` is larger than [`MAX_ALIGN`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # use std::alloc::Layout;
+    /// # type P = Allocator;
+    /// # let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// unsafe {
+    ///     let (ptr, _, _) = P::alloc_layout(Layout::from_size_align(8, 256).unwrap());
+    ///     assert_eq!(ptr as usize % 256, 0);
+    ///     P::dealloc(ptr, 8);
+    /// }
+    /// ```
+    ///
+    /// [`MAX_ALIGN`]: ./constant.MAX_ALIGN.html
+    /// [`dealloc`]: #method.dealloc
+    #[inline]
+    #[track_caller]
+    unsafe fn alloc_layout(layout: Layout) -> (*mut u8, u64, usize) {
+        let (p, off, len, z) = Self::pre_alloc_aligned(layout.size(), layout.align());
+        Self::drop_on_failure(off, len, z);
+        Self::perform(z);
+        (p, off, len)
+    }
+
     /// Deallocate the block of memory at the given `ptr` pointer with the
     /// given `size`.
     ///
@@ -653,6 +973,38 @@ where
     /// 
     unsafe fn pre_alloc(size: usize) -> (*mut u8, u64, usize, usize);
 
+    /// Prepares an allocation at an address aligned to `align` without
+    /// performing it
+    ///
+    /// It returns the same 4-tuple as [`pre_alloc`]. The default
+    /// implementation discards the allocation if [`pre_alloc`] does not
+    /// return an aligned block.
+    ///
+    /// [`pre_alloc`]: #method.pre_alloc
+    unsafe fn pre_alloc_aligned(size: usize, align: usize) -> (*mut u8, u64, usize, usize) {
+        let (p, off, len, z) = Self::pre_alloc(size);
+        if !p.is_null() && (p as usize) % align != 0 {
+            Self::discard(z);
+            (ptr::null_mut(), u64::MAX, 0, 0)
+        } else {
+            (p, off, len, z)
+        }
+    }
+
+    /// Prepares an allocation for an object of type `type_name` without
+    /// performing it
+    ///
+    /// It is similar to [`pre_alloc_aligned`], except that the pools which
+    /// keep the type information record the type of the block atomically with
+    /// the allocation (see [`heap_iter()`]). The default implementation
+    /// ignores the type.
+    ///
+    /// [`pre_alloc_aligned`]: #method.pre_alloc_aligned
+    /// [`heap_iter()`]: #method.heap_iter
+    unsafe fn pre_alloc_typed(size: usize, align: usize, _type_name: &'static str) -> (*mut u8, u64, usize, usize) {
+        Self::pre_alloc_aligned(size, align)
+    }
+
     /// Prepares deallocation without performing it
     /// 
     /// This function is used internally for low-level atomicity in memory
@@ -803,6 +1155,18 @@ where
         p
     }
 
+    /// Allocates new memory aligned to `align` and then places `x` into it
+    /// with `DropOnFailure` log
+    unsafe fn new_aligned<'a, T: PSafe + 'a>(x: T, align: usize, j: &Journal<Self>) -> &'a mut T where Self: MemPool {
+        debug_assert!(mem::size_of::<T>() != 0, "Cannot allocated ZST");
+
+        let mut log = Log::drop_on_failure(u64::MAX, 1, j);
+        let (p, off, len, z) = Self::atomic_new_aligned(x, align);
+        log.set(off, len, z);
+        Self::perform(z);
+        p
+    }
+
     /// Allocates a new slice and then places `x` into it with `DropOnAbort` log
     unsafe fn new_slice<'a, T: PSafe + 'a>(x: &'a [T], journal: &Journal<Self>) -> &'a mut [T] where Self: MemPool {
         debug_assert!(mem::size_of::<T>() != 0, "Cannot allocate ZST");
@@ -822,9 +1186,10 @@ where
         debug_assert!(s != 0, "Cannot allocated ZST");
 
         let mut log = Log::drop_on_failure(u64::MAX, 1, j);
-        let (p, off, len, z) = Self::pre_alloc(s);
+        let (p, off, len, z) = Self::pre_alloc_typed(s, mem::align_of_val(x),
+            std::any::type_name::<T>());
         if p.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         Self::drop_on_failure(off, len, z);
         std::ptr::copy_nonoverlapping(x as *const T as *const u8, p, s);
@@ -839,9 +1204,10 @@ where
         debug_assert!(s != 0, "Cannot allocated ZST");
 
         let mut log = Log::drop_on_failure(u64::MAX, 1, j);
-        let (p, off, len, z) = Self::pre_alloc(s);
+        let (p, off, len, z) = Self::pre_alloc_typed(s, mem::align_of::<T>(),
+            std::any::type_name::<[T]>());
         if p.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         Self::drop_on_failure(off, len, z);
         std::ptr::copy_nonoverlapping(x as *const [T] as *const u8, p, s);
@@ -852,12 +1218,19 @@ where
 
     /// Allocates new memory and then places `x` into it without realizing the allocation
     unsafe fn atomic_new<'a, T: 'a>(x: T) -> (&'a mut T, u64, usize, usize) {
-        log!(Self, White, "ALLOC", "TYPE: {}", std::any::type_name::<T>());
+        Self::atomic_new_aligned(x, mem::align_of::<T>())
+    }
+
+    /// Allocates new memory aligned to `align` and then places `x` into it
+    /// without realizing the allocation
+    unsafe fn atomic_new_aligned<'a, T: 'a>(x: T, align: usize) -> (&'a mut T, u64, usize, usize) {
+        log!(Self, White, "ALLOC", "TYPE: {} (align = {})", std::any::type_name::<T>(), align);
 
         let size = mem::size_of::<T>();
-        let (raw, off, len, z) = Self::pre_alloc(size);
+        let (raw, off, len, z) = Self::pre_alloc_typed(size, align.max(mem::align_of::<T>()),
+            std::any::type_name::<T>());
         if raw.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         Self::drop_on_failure(off, len, z);
         let p = &mut *utils::read(raw);
@@ -869,9 +1242,10 @@ where
     unsafe fn atomic_new_slice<'a, T: 'a + PSafe>(x: &'a [T]) -> (&'a mut [T], u64, usize, usize) {
         log!(Self, White, "ALLOC", "TYPE: [{}; {}]", std::any::type_name::<T>(), x.len());
 
-        let (ptr, off, size, z) = Self::pre_alloc(mem::size_of_val(x));
+        let (ptr, off, size, z) = Self::pre_alloc_typed(mem::size_of_val(x), mem::align_of::<T>(),
+            std::any::type_name::<[T]>());
         if ptr.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         Self::drop_on_failure(off, size, z);
         ptr::copy_nonoverlapping(
@@ -898,12 +1272,33 @@ where
 
     /// Allocates new memory without copying data
     unsafe fn new_uninit_for_layout(size: usize, journal: &Journal<Self>) -> *mut u8 where Self: MemPool {
-        log!(Self, White, "ALLOC", "{:?}", size);
+        Self::new_uninit_aligned(Layout::from_size_align_unchecked(size, 1), journal)
+    }
+
+    /// Allocates new memory for the given `layout` without copying data
+    unsafe fn new_uninit_aligned(layout: Layout, journal: &Journal<Self>) -> *mut u8 where Self: MemPool {
+        log!(Self, White, "ALLOC", "{:?}", layout);
 
         let mut log = Log::drop_on_abort(u64::MAX, 1, journal);
-        let (p, off, len, z) = Self::pre_alloc(size);
+        let (p, off, len, z) = Self::pre_alloc_aligned(layout.size(), layout.align());
+        if p.is_null() {
+            std::panic::panic_any(Error::OutOfMemory);
+        }
+        Self::drop_on_failure(off, len, z);
+        log.set(off, len, z);
+        Self::perform(z);
+        p
+    }
+
+    /// Allocates new memory for the given `layout` without copying data, and
+    /// records `type_name` as its type
+    unsafe fn new_uninit_typed(layout: Layout, type_name: &'static str, journal: &Journal<Self>) -> *mut u8 where Self: MemPool {
+        log!(Self, White, "ALLOC", "TYPE: {} ({:?})", type_name, layout);
+
+        let mut log = Log::drop_on_abort(u64::MAX, 1, journal);
+        let (p, off, len, z) = Self::pre_alloc_typed(layout.size(), layout.align(), type_name);
         if p.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         Self::drop_on_failure(off, len, z);
         log.set(off, len, z);
@@ -913,9 +1308,10 @@ where
 
     /// Allocates new memory without copying data and realizing the allocation
     unsafe fn atomic_new_uninit<'a, T: 'a>() -> (&'a mut T, u64, usize, usize) {
-        let (ptr, off, len, z) = Self::pre_alloc(mem::size_of::<T>());
+        let (ptr, off, len, z) = Self::pre_alloc_typed(mem::size_of::<T>(), mem::align_of::<T>(),
+            std::any::type_name::<T>());
         if ptr.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         Self::drop_on_failure(off, len, z);
         (&mut *utils::read(ptr), off, len, z)
@@ -925,7 +1321,7 @@ where
     unsafe fn alloc_for_value<'a, T: ?Sized>(x: &T) -> &'a mut T {
         let raw = Self::alloc(mem::size_of_val(x));
         if raw.0.is_null() {
-            panic!("Memory exhausted");
+            std::panic::panic_any(Error::OutOfMemory);
         }
         &mut *utils::read(raw.0)
     }
@@ -998,14 +1394,30 @@ where
                 log!(Self, White, "COMMIT", "JRNL: {:?}", journal.0);
 
                 let journal = as_mut(journal.0);
-                journal.commit(
-                    #[cfg(feature = "check_double_free")]
-                    &mut *Self::dealloc_history()
-                );
+                if let Some(group) = Self::commit_group() {
+                    journal.prepare_commit();
+                    group.commit(journal);
+
+                    // The journal is marked committed by the leader. If it
+                    // crashes before the deallocations are done, the recovery
+                    // procedure performs them for the committed journal.
+                    journal.commit_dealloc(
+                        #[cfg(feature = "check_double_free")]
+                        &mut *Self::dealloc_history()
+                    );
+                } else {
+                    journal.commit(
+                        #[cfg(feature = "check_double_free")]
+                        &mut *Self::dealloc_history()
+                    );
+                }
+                journal.resolve_callbacks(true);
+                let callbacks = journal.take_callbacks();
                 journal.clear(
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
                 );
+                callbacks.run();
             }
         }
     }
@@ -1028,10 +1440,12 @@ where
             if *journal.1 == 0 {
                 log!(Self, White, "COMMIT_NC", "JRNL: {:?}", journal.0);
 
-                as_mut(journal.0).commit(
+                let journal = as_mut(journal.0);
+                journal.commit(
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
                 );
+                journal.resolve_callbacks(true);
             }
         }
     }
@@ -1053,10 +1467,13 @@ where
             if *journal.1 == -1 {
                 log!(Self, White, "CLEAR", "JRNL: {:?}", journal.0);
 
-                as_mut(journal.0).clear(
+                let journal = as_mut(journal.0);
+                let callbacks = journal.take_callbacks();
+                journal.clear(
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
                 );
+                callbacks.run();
             }
         }
     }
@@ -1066,7 +1483,9 @@ where
     ///
     /// If the transaction is nested, it propagates the panic up to the top most
     /// transaction to make all of them tainted. It returns true if it runs the
-    /// rollback procedure; otherwise false.
+    /// rollback procedure; otherwise false. Nested transactions started by
+    /// [`transaction()`](#method.transaction) use
+    /// [`rollback_to()`](#method.rollback_to) instead.
     ///
     /// # Safety
     ///
@@ -1085,10 +1504,13 @@ where
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
                 );
+                journal.resolve_callbacks(false);
+                let callbacks = journal.take_callbacks();
                 journal.clear(
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
                 );
+                callbacks.run();
                 return true;
             } else {
                 // Propagate the panic to the upper transactions
@@ -1098,6 +1520,26 @@ where
         false
     }
 
+    #[inline]
+    /// Discards the changes made after savepoint `sp` in a nested transaction
+    ///
+    /// # Safety
+    ///
+    /// This function is for internal use and should not be called elsewhere.
+    ///
+    unsafe fn rollback_to(sp: &Savepoint) where Self: MemPool {
+        if let Some(journal) = Journal::<Self>::current(false) {
+            *journal.1 -= 1;
+
+            log!(Self, White, "ROLLBACK_SP", "JRNL: {:?}", journal.0);
+
+            as_mut(journal.0).rollback_to(sp,
+                #[cfg(feature = "check_double_free")]
+                &mut *Self::dealloc_history()
+            );
+        }
+    }
+
     #[inline]
     /// Discards all changes without clearing the logs
     ///
@@ -1115,10 +1557,12 @@ where
             if *journal.1 == 0 {
                 log!(Self, White, "ROLLBACK_NC", "JRNL: {:?}", journal.0);
 
-                as_mut(journal.0).rollback(
+                let journal = as_mut(journal.0);
+                journal.rollback(
                     #[cfg(feature = "check_double_free")]
                     &mut *Self::dealloc_history()
                 );
+                journal.resolve_callbacks(false);
             }
         }
     }
@@ -1145,6 +1589,9 @@ where
     /// unreachable persistent objects. The only way out of a transaction for
     /// a persistent object is to be reachable by the root object.
     ///
+    /// If the body panics, all of its changes are rolled back, and the panic
+    /// payload is returned in [`Error::TxAborted`].
+    ///
     /// # Examples
     /// 
     /// ```
@@ -1167,6 +1614,7 @@ where
     /// [`TxInSafe`]: ../trait.TxInSafe.html
     /// [`TxOutSafe`]: ../trait.TxOutSafe.html
     /// [`AssertTxInSafe`]: ../struct.AssertTxInSafe.html
+    /// [`Error::TxAborted`]: ../enum.Error.html#variant.TxAborted
     /// 
     #[inline]
     #[track_caller]
@@ -1177,12 +1625,18 @@ where
     {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<Self>::Transaction;
+
+        if Self::is_read_only() {
+            return Err(Error::ReadOnly);
+        }
         
         #[cfg(feature = "check_allocator_cyclic_links")]
         debug_assert!(Self::verify());
 
         let mut chaperoned = false;
         let cptr = &mut chaperoned as *mut bool;
+        let mut savepoint = None;
+        let sptr = &mut savepoint as *mut Option<Savepoint>;
         let res = std::panic::catch_unwind(|| {
             let chaperon = Chaperon::current();
             if let Some(ptr) = chaperon {
@@ -1215,7 +1669,12 @@ where
                     unsafe {
                         let j = Journal::<Self>::current(true).unwrap();
                         *j.1 += 1;
-                        utils::as_mut(j.0).unset(JOURNAL_COMMITTED);
+                        if *j.1 > 1 {
+                            // A nested transaction rolls back to here on failure
+                            *sptr = Some(utils::as_mut(j.0).mark());
+                        } else {
+                            utils::as_mut(j.0).unset(JOURNAL_COMMITTED);
+                        }
                         &*j.0
                     }
                 })
@@ -1231,15 +1690,23 @@ where
         unsafe {
             crate::ll::sfence();
 
-            if let Ok(res) = res {
-                if !chaperoned {
-                    Self::commit();
+            match res {
+                Ok(res) => {
+                    if !chaperoned {
+                        Self::commit();
+                    }
+                    Ok(res)
                 }
-                Ok(res)
-            } else {
-                if !chaperoned {
+                Err(e) => if let Some(sp) = savepoint {
+                    Self::rollback_to(&sp);
+                    if crate::sync::deadlock::is_victim(&e) {
+                        // The locks of the outer transaction should be released
+                        std::panic::resume_unwind(e);
+                    }
+                    Err(Error::aborted(e))
+                } else if !chaperoned {
                     Self::rollback();
-                    Err("Unsuccessful transaction".to_string())
+                    Err(Error::aborted(e))
                 } else {
                     // Propagates the panic to the top level in enforce rollback
                     panic!("Unsuccessful chaperoned transaction");
@@ -1248,6 +1715,123 @@ where
         }
     }
 
+    /// Executes commands atomically, and re-executes them if the transaction
+    /// fails with a retryable error
+    ///
+    /// It is similar to [`transaction()`], except that `body` may run several
+    /// times. Every failed attempt is rolled back before the next one starts.
+    /// The retryable failures are:
+    ///
+    /// * an explicit request by [`Journal::retry()`], e.g., when
+    ///   [`PMutex::try_lock()`] or [`PMutex::lock_timeout()`] fails,
+    /// * aborting as the victim of a [`Deadlock`], and
+    /// * running out of memory, which may be transient when other threads
+    ///   free memory.
+    ///
+    /// The other failures are returned immediately. The attempts are limited
+    /// and delayed according to `policy`; the error of the last attempt is
+    /// returned if they all fail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::RetryPolicy;
+    /// use std::time::Duration;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<PMutex<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// P::transaction_with_retry(RetryPolicy::new().max_attempts(5), |j| {
+    ///     match root.lock_timeout(j, Duration::from_millis(10)) {
+    ///         Ok(mut v) => *v += 1,
+    ///         Err(_) => j.retry(),
+    ///     }
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`transaction()`]: #method.transaction
+    /// [`Journal::retry()`]: ../stm/journal/struct.Journal.html#method.retry
+    /// [`PMutex::try_lock()`]: ../sync/struct.PMutex.html#method.try_lock
+    /// [`PMutex::lock_timeout()`]: ../sync/struct.PMutex.html#method.lock_timeout
+    /// [`Deadlock`]: ../sync/struct.Deadlock.html
+    #[track_caller]
+    fn transaction_with_retry<T, F: Fn(&'static Journal<Self>) -> T>(policy: &RetryPolicy, body: F) -> Result<T>
+    where
+        F: TxInSafe + RefUnwindSafe,
+        T: TxOutSafe, Self: alloc::pool::MemPool
+    {
+        let mut attempt = 1;
+        loop {
+            match Self::transaction(&body) {
+                Ok(res) => return Ok(res),
+                Err(e) => {
+                    if !e.is_retryable() || attempt >= policy.max_attempts {
+                        return Err(e);
+                    }
+                    log!(Self, White, "RETRY", "ATTEMPT: {}, ERROR: {}", attempt, e);
+                    std::thread::sleep(policy.delay(attempt));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Executes a read-only transaction
+    ///
+    /// The `read_transaction` function takes a closure with one argument of
+    /// type `&ReadJournal<Self>`. Unlike [`transaction()`], it neither creates
+    /// a persistent [`Journal`] nor issues any fences. A [`ReadJournal`] cannot
+    /// be used in place of a `Journal`, so the body cannot modify persistent
+    /// objects or allocate new ones. It may, however, lock [`PMutex`]es via
+    /// [`PMutex::read_lock()`] for isolation. The locks are released when the
+    /// read transaction finishes. Read transactions are also allowed in
+    /// read-only pools.
+    ///
+    /// If the body panics, the locks are released and an error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<PMutex<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// let v = P::read_transaction(|j| {
+    ///     *root.read_lock(j)
+    /// }).unwrap();
+    ///
+    /// assert_eq!(v, P::transaction(|j| *root.lock(j)).unwrap());
+    /// ```
+    ///
+    /// [`transaction()`]: #method.transaction
+    /// [`Journal`]: ../stm/journal/struct.Journal.html
+    /// [`ReadJournal`]: ../stm/struct.ReadJournal.html
+    /// [`PMutex`]: ../sync/struct.PMutex.html
+    /// [`PMutex::read_lock()`]: ../sync/struct.PMutex.html#method.read_lock
+    ///
+    #[inline]
+    #[track_caller]
+    fn read_transaction<T, F: FnOnce(&ReadJournal<Self>) -> T>(body: F) -> Result<T>
+    where
+        F: TxInSafe + UnwindSafe,
+        T: TxOutSafe, Self: alloc::pool::MemPool
+    {
+        let journal = ReadJournal::<Self>::new();
+
+        // The journal only keeps the volatile list of the locks, which are
+        // released below even if the body panics
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| body(&journal)));
+
+        // Releases the locks
+        drop(journal);
+
+        res.map_err(Error::aborted)
+    }
+
     fn gen() -> u32 {
         0
     }
@@ -1259,6 +1843,17 @@ where
     /// Prints memory information
     fn print_info() {}
 
+    /// Returns the statistics of the allocator
+    ///
+    /// It fails with [`Error::Unsupported`] if the pool type does not provide
+    /// them. See [`AllocatorStats`](./struct.AllocatorStats.html) for more
+    /// details.
+    ///
+    /// [`Error::Unsupported`]: ../enum.Error.html#variant.Unsupported
+    fn allocator_stats() -> Result<AllocatorStats> {
+        Err(Error::Unsupported)
+    }
+
     fn stat_footprint() -> usize {
         if cfg!(feature = "stat_footprint") {
             0
@@ -1302,16 +1897,94 @@ pub unsafe trait MemPool:
     UnwindSafe {}
 
 pub(crate) fn create_file(filename: &str, size: u64) -> Result<()> {
-    let file = OpenOptions::new().write(true).create(true).open(filename);
-    if file.is_err() {
-        Err(format!("{}", file.err().unwrap()))
-    } else {
-        if let Some(e) = file.unwrap().set_len(size).err() {
-            Err(format!("{}", e))
-        } else {
-            Ok(())
+    // The file is truncated rather than removed, so that the lock on it stays
+    let file = OpenOptions::new().write(true).create(true).truncate(true).open(filename)?;
+    file.set_len(size)?;
+    Ok(())
+}
+
+/// Takes an advisory lock on an open pool file
+///
+/// The lock is exclusive, unless `exclusive` is `false`, in which case other
+/// processes may take shared locks on the same file at the same time. If
+/// `wait` is `false`, it fails immediately when another process holds a
+/// conflicting lock. The lock is released when the file is closed.
+pub fn lock_file(file: &std::fs::File, exclusive: bool, wait: bool) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let mut op = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+        if !wait {
+            op |= libc::LOCK_NB;
+        }
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+                return Ok(());
+            }
+            let e = std::io::Error::last_os_error();
+            match e.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EWOULDBLOCK) => return Err(Error::PoolBusy),
+                _ => return Err(Error::Io(e)),
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (file, exclusive, wait);
+        Ok(())
+    }
+}
+
+/// Writes the contents of a pool to the `dest` file and returns the number of
+/// written pages
+///
+/// If `skip_unchanged` is set, each page is compared with the existing
+/// contents of `dest`, and only the pages which differ are written. The
+/// modified pages are not tracked, so the whole destination is still read.
+pub fn write_snapshot(dest: &str, src: &[u8], skip_unchanged: bool) -> Result<usize> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(dest)?;
+    let old_len = if skip_unchanged { file.metadata()?.len() as usize } else { 0 };
+    let mut page = vec![0u8; MAX_ALIGN];
+    let mut written = 0;
+    for (i, chunk) in src.chunks(MAX_ALIGN).enumerate() {
+        let off = i * MAX_ALIGN;
+        if off + chunk.len() <= old_len {
+            file.seek(SeekFrom::Start(off as u64))?;
+            file.read_exact(&mut page[..chunk.len()])?;
+            if &page[..chunk.len()] == chunk {
+                continue;
+            }
         }
+        file.seek(SeekFrom::Start(off as u64))?;
+        file.write_all(chunk)?;
+        written += 1;
     }
+    file.set_len(src.len() as u64)?;
+    file.sync_all()?;
+    Ok(written)
+}
+
+/// Opens a pool file for writing and takes an exclusive lock on it
+///
+/// The file is created empty if it is missing and `opts` allows creating it.
+/// The lock is taken before the file is recreated or formatted according to
+/// `opts`, and it is held as long as the returned file is open.
+pub fn open_locked(path: &str, opts: &PoolOptions) -> Result<std::fs::File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(opts.get_create() || opts.get_create_if_missing())
+        .open(path)?;
+    lock_file(&file, true, opts.get_wait_lock())?;
+    Ok(file)
 }
 
 #[cfg(test)]
@@ -0,0 +1,159 @@
+use std::fmt::Write;
+
+/// Statistics of a single allocation zone
+///
+/// It is generated by the allocation algorithm of the zone. See
+/// [`AllocatorStats`](./struct.AllocatorStats.html) for the pool-wide
+/// statistics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ZoneStats {
+    /// Total size of the zone in bytes
+    pub size: usize,
+
+    /// Number of used bytes
+    pub used: usize,
+
+    /// Number of available bytes
+    pub available: usize,
+
+    /// Size of the largest free block in bytes
+    pub largest_free_block: usize,
+
+    /// Number of free blocks per order; the `i`th item is the number of free
+    /// blocks of size `2^i`
+    pub free_blocks: Vec<usize>,
+}
+
+impl ZoneStats {
+    /// Returns the external fragmentation ratio of the zone in `[0, 1]`
+    ///
+    /// It is `1 - largest_free_block / available`. Zero means that all of the
+    /// available space can be allocated in a single block.
+    pub fn fragmentation(&self) -> f64 {
+        fragmentation(self.largest_free_block, self.available)
+    }
+}
+
+/// Statistics of a memory pool
+///
+/// It is returned by [`MemPool::allocator_stats()`] to inspect the state of the
+/// allocator without parsing the output of `print_info()`.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::default::*;
+/// # type P = Allocator;
+/// let _pool = P::open_no_root("foo.pool", O_CF).unwrap();
+/// let stats = P::allocator_stats().unwrap();
+///
+/// assert_eq!(stats.used + stats.available, stats.size);
+/// println!("{}", stats.to_json());
+/// ```
+///
+/// [`MemPool::allocator_stats()`]: ./trait.MemPoolTraits.html#method.allocator_stats
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AllocatorStats {
+    /// Total size of the pool in bytes
+    pub size: usize,
+
+    /// Number of used bytes
+    pub used: usize,
+
+    /// Number of available bytes
+    pub available: usize,
+
+    /// Size of the largest free block in bytes
+    pub largest_free_block: usize,
+
+    /// External fragmentation ratio in `[0, 1]`
+    pub fragmentation: f64,
+
+    /// Number of journals in the pool
+    pub journals: usize,
+
+    /// Maximum number of bytes used from the pool, if `stat_footprint` feature
+    /// is enabled
+    pub footprint: Option<usize>,
+
+    /// Per-zone statistics
+    pub zones: Vec<ZoneStats>,
+}
+
+impl AllocatorStats {
+    /// Creates the pool statistics from per-zone statistics
+    pub fn from_zones(zones: Vec<ZoneStats>, journals: usize) -> Self {
+        let mut stats = Self {
+            journals,
+            ..Default::default()
+        };
+        for z in &zones {
+            stats.size += z.size;
+            stats.used += z.used;
+            stats.available += z.available;
+            stats.largest_free_block = stats.largest_free_block.max(z.largest_free_block);
+        }
+        stats.fragmentation = fragmentation(stats.largest_free_block, stats.available);
+        stats.zones = zones;
+        stats
+    }
+
+    /// Serializes the statistics in JSON format
+    pub fn to_json(&self) -> String {
+        let mut res = String::new();
+        write!(res, "{{\"size\":{},\"used\":{},\"available\":{},\
+            \"largest_free_block\":{},\"fragmentation\":{:.6},\"journals\":{},\
+            \"footprint\":{},\"zones\":[",
+            self.size, self.used, self.available, self.largest_free_block,
+            self.fragmentation, self.journals,
+            self.footprint.map_or("null".to_string(), |f| f.to_string())).unwrap();
+        for (i, z) in self.zones.iter().enumerate() {
+            if i != 0 {
+                res.push(',');
+            }
+            write!(res, "{{\"size\":{},\"used\":{},\"available\":{},\
+                \"largest_free_block\":{},\"fragmentation\":{:.6},\"free_blocks\":{:?}}}",
+                z.size, z.used, z.available, z.largest_free_block,
+                z.fragmentation(), z.free_blocks).unwrap();
+        }
+        res += "]}";
+        res
+    }
+}
+
+#[inline]
+fn fragmentation(largest: usize, available: usize) -> f64 {
+    if available == 0 {
+        0.0
+    } else {
+        1.0 - largest as f64 / available as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stats_json() {
+        let z = ZoneStats {
+            size: 64,
+            used: 16,
+            available: 48,
+            largest_free_block: 32,
+            free_blocks: vec![0, 0, 0, 0, 1, 1]
+        };
+        let stats = AllocatorStats::from_zones(vec![z.clone(), z], 2);
+        assert_eq!(stats.size, 128);
+        assert_eq!(stats.available, 96);
+        assert_eq!(stats.largest_free_block, 32);
+        assert!((stats.fragmentation - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.to_json(), "{\"size\":128,\"used\":32,\"available\":96,\
+            \"largest_free_block\":32,\"fragmentation\":0.666667,\"journals\":2,\
+            \"footprint\":null,\"zones\":[\
+            {\"size\":64,\"used\":16,\"available\":48,\"largest_free_block\":32,\
+            \"fragmentation\":0.333333,\"free_blocks\":[0, 0, 0, 0, 1, 1]},\
+            {\"size\":64,\"used\":16,\"available\":48,\"largest_free_block\":32,\
+            \"fragmentation\":0.333333,\"free_blocks\":[0, 0, 0, 0, 1, 1]}]}");
+    }
+}
@@ -0,0 +1,315 @@
+use crate::alloc::MemPool;
+use crate::ll::persist_obj;
+use crate::utils;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::{Mutex, MutexGuard};
+
+/// Number of type records in a type page
+pub const TYPE_PAGE_SLOTS: usize = 170;
+
+/// The type information of a single allocation. A record with `off ==
+/// u64::MAX` is free.
+#[repr(C)]
+struct TypeRecord {
+    off: u64,
+    len: u64,
+    id: u64,
+}
+
+/// A page of type records. Pages are linked from the pool header, and are
+/// never freed.
+#[repr(C)]
+struct TypePage {
+    next: u64,
+    records: [TypeRecord; TYPE_PAGE_SLOTS],
+}
+
+/// The header of a persistent type name, followed by `len` bytes of the name
+#[repr(C)]
+struct TypeName {
+    next: u64,
+    id: u64,
+    len: u64,
+}
+
+/// Returns the identifier of a type name which is stored in the type records
+pub fn type_id(type_name: &str) -> u64 {
+    let mut s = DefaultHasher::new();
+    type_name.hash(&mut s);
+    s.finish()
+}
+
+/// Volatile index of the type records
+struct TypeIndex {
+    /// Maps the offset of an allocation to the offset of its record
+    records: HashMap<u64, u64>,
+
+    /// Offsets of the records which can be reused once they are cleared
+    free: Vec<u64>,
+
+    /// Names of the types by their identifiers
+    names: HashMap<u64, String>,
+}
+
+impl TypeIndex {
+    /// Takes a free record whose clearing log is already performed
+    unsafe fn pop_free<A: MemPool>(&mut self) -> Option<u64> {
+        for i in (0..self.free.len()).rev() {
+            if A::get_unchecked::<TypeRecord>(self.free[i]).off == u64::MAX {
+                return Some(self.free.swap_remove(i));
+            }
+        }
+        None
+    }
+}
+
+/// The per-block type information of an open pool
+///
+/// The records are kept in persistent pages linked from the pool header, and
+/// this table keeps their volatile index. A record is written using the
+/// low-level logs of the zone which allocates the block, so it is updated
+/// atomically with the allocation, and is cleared atomically with the
+/// deallocation.
+///
+/// The index lock is never held while taking a zone lock. New pages and names
+/// are allocated in their own atomic sections while holding the `grow` lock.
+pub struct TypeTable {
+    index: Mutex<TypeIndex>,
+    grow: Mutex<()>,
+    pages: u64,
+    names: u64,
+}
+
+/// Reads a list head. The heads are in the pool header, which is not an
+/// allocated block, so they are not accessed through `get_unchecked()`.
+unsafe fn head<A: MemPool>(off: u64) -> u64 {
+    *utils::read_addr::<u64>(A::start() + off)
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    match m.lock() {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    }
+}
+
+impl TypeTable {
+    /// Loads the type records of an open pool. `pages` and `names` are the
+    /// offsets of the list heads in the pool header.
+    ///
+    /// The records of the blocks which are not allocated belong to the
+    /// allocations which are reclaimed by the recovery procedure. If `repair`
+    /// is set, they are cleared. Otherwise, they are skipped.
+    pub unsafe fn load<A: MemPool>(pages: u64, names: u64, repair: bool) -> Self {
+        let mut index = TypeIndex {
+            records: HashMap::new(),
+            free: vec![],
+            names: HashMap::new(),
+        };
+        let mut curr = head::<A>(names);
+        while curr != u64::MAX {
+            let name = A::get_unchecked::<TypeName>(curr);
+            let bytes = A::deref_slice_unchecked::<u8>(
+                curr + mem::size_of::<TypeName>() as u64, name.len as usize);
+            index.names.insert(name.id, String::from_utf8_lossy(bytes).to_string());
+            curr = name.next;
+        }
+        let mut curr = head::<A>(pages);
+        while curr != u64::MAX {
+            let page = A::get_mut_unchecked::<TypePage>(curr);
+            for (i, r) in page.records.iter_mut().enumerate() {
+                let slot = curr + (mem::size_of::<u64>() + i * mem::size_of::<TypeRecord>()) as u64;
+                if r.off != u64::MAX && A::allocated(r.off, r.len as usize) {
+                    index.records.insert(r.off, slot);
+                } else {
+                    if r.off != u64::MAX && repair {
+                        r.off = u64::MAX;
+                        persist_obj(&r.off, false);
+                    }
+                    index.free.push(slot);
+                }
+            }
+            curr = page.next;
+        }
+        if repair {
+            crate::ll::sfence();
+        }
+        Self {
+            index: Mutex::new(index),
+            grow: Mutex::new(()),
+            pages,
+            names,
+        }
+    }
+
+    /// Allocates a new page of free records and links it to the list
+    unsafe fn new_page<A: MemPool>(&self) -> u64 {
+        let (p, off, _, z) = A::pre_alloc(mem::size_of::<TypePage>());
+        if p.is_null() {
+            std::panic::panic_any(crate::Error::OutOfMemory);
+        }
+        let page = utils::read::<TypePage>(p);
+        page.next = head::<A>(self.pages);
+        for r in page.records.iter_mut() {
+            r.off = u64::MAX;
+            r.len = 0;
+            r.id = 0;
+        }
+        persist_obj(page, true);
+        A::log64(self.pages, off, z);
+        A::perform(z);
+        off
+    }
+
+    /// Stores a type name persistently and links it to the list
+    unsafe fn new_name<A: MemPool>(&self, id: u64, type_name: &str) {
+        let len = mem::size_of::<TypeName>() + type_name.len();
+        let (p, off, _, z) = A::pre_alloc(len);
+        if p.is_null() {
+            std::panic::panic_any(crate::Error::OutOfMemory);
+        }
+        let name = utils::read::<TypeName>(p);
+        name.next = head::<A>(self.names);
+        name.id = id;
+        name.len = type_name.len() as u64;
+        let bytes = std::slice::from_raw_parts_mut(
+            p.add(mem::size_of::<TypeName>()), type_name.len());
+        bytes.copy_from_slice(type_name.as_bytes());
+        persist_obj(std::slice::from_raw_parts(p, len), true);
+        A::log64(self.names, off, z);
+        A::perform(z);
+    }
+
+    /// Reserves a free record for a new allocation of type `type_name`, and
+    /// returns its offset. The record should be either written by
+    /// [`tag()`](#method.tag) or returned by [`release()`](#method.release).
+    /// It should not be called while holding a zone lock.
+    // The index is not locked while the name is written to the pool
+    #[allow(clippy::map_entry)]
+    pub unsafe fn reserve<A: MemPool>(&self, type_name: &str) -> u64 {
+        let id = type_id(type_name);
+        if !lock(&self.index).names.contains_key(&id) {
+            let _grow = lock(&self.grow);
+            if !lock(&self.index).names.contains_key(&id) {
+                self.new_name::<A>(id, type_name);
+                lock(&self.index).names.insert(id, type_name.to_string());
+            }
+        }
+        loop {
+            if let Some(slot) = lock(&self.index).pop_free::<A>() {
+                return slot;
+            }
+            let _grow = lock(&self.grow);
+            if let Some(slot) = lock(&self.index).pop_free::<A>() {
+                return slot;
+            }
+            let page = self.new_page::<A>();
+            let mut index = lock(&self.index);
+            for i in 0..TYPE_PAGE_SLOTS {
+                index.free.push(page +
+                    (mem::size_of::<u64>() + i * mem::size_of::<TypeRecord>()) as u64);
+            }
+        }
+    }
+
+    /// Returns an unused reserved record
+    pub fn release(&self, slot: u64) {
+        lock(&self.index).free.push(slot);
+    }
+
+    /// Writes the type information of the prepared allocation at `off` to the
+    /// reserved record using the low-level logs of zone `z`
+    pub unsafe fn tag<A: MemPool>(&self, slot: u64, off: u64, len: usize, type_name: &str, z: usize) {
+        A::log64(slot + 8, len as u64, z);
+        A::log64(slot + 16, type_id(type_name), z);
+        A::log64(slot, off, z);
+        let mut index = lock(&self.index);
+        if let Some(old) = index.records.insert(off, slot) {
+            // The previous allocation at `off` was discarded
+            index.free.push(old);
+        }
+    }
+
+    /// Clears the record of the block at `off` using the low-level logs of
+    /// zone `z`, if it has one. The record is reused after the logs are
+    /// performed.
+    pub unsafe fn untag<A: MemPool>(&self, off: u64, z: usize) {
+        let mut index = lock(&self.index);
+        if let Some(slot) = index.records.remove(&off) {
+            A::log64(slot, u64::MAX, z);
+            index.free.push(slot);
+        }
+    }
+
+    /// Returns the `(offset, len, type_name)` tuples of the typed allocations
+    /// sorted by offset
+    pub fn entries<A: MemPool>(&self) -> Vec<(u64, usize, String)> {
+        let index = lock(&self.index);
+        let mut entries = vec![];
+        for (off, slot) in &index.records {
+            let r = unsafe { A::get_unchecked::<TypeRecord>(*slot) };
+            // Skip the allocations which are not realized yet
+            if r.off == *off {
+                let name = index.names.get(&r.id).cloned()
+                    .unwrap_or_else(|| format!("<unknown type 0x{:x}>", r.id));
+                entries.push((*off, r.len as usize, name));
+            }
+        }
+        entries.sort_by_key(|e| e.0);
+        entries
+    }
+
+    /// Returns the `(offset, len)` pairs of the blocks used for keeping the
+    /// type information
+    pub fn blocks<A: MemPool>(&self) -> Vec<(u64, usize)> {
+        let _grow = lock(&self.grow);
+        let mut blocks = vec![];
+        unsafe {
+            let mut curr = head::<A>(self.pages);
+            while curr != u64::MAX {
+                blocks.push((curr, mem::size_of::<TypePage>()));
+                curr = A::get_unchecked::<TypePage>(curr).next;
+            }
+            let mut curr = head::<A>(self.names);
+            while curr != u64::MAX {
+                let name = A::get_unchecked::<TypeName>(curr);
+                blocks.push((curr, mem::size_of::<TypeName>() + name.len as usize));
+                curr = name.next;
+            }
+        }
+        blocks
+    }
+}
+
+/// An iterator over the typed allocations of a pool
+///
+/// It is created by [`heap_iter()`], and yields `(offset, len, type_name)`
+/// tuples sorted by offset. `len` is the requested size of the allocation.
+/// The allocations are listed as they were when the iterator was created.
+///
+/// [`heap_iter()`]: ./trait.MemPoolTraits.html#method.heap_iter
+pub struct HeapIter {
+    entries: std::vec::IntoIter<(u64, usize, String)>,
+}
+
+impl HeapIter {
+    /// Creates an iterator over the given entries
+    pub fn new(entries: Vec<(u64, usize, String)>) -> Self {
+        Self { entries: entries.into_iter() }
+    }
+}
+
+impl Iterator for HeapIter {
+    type Item = (u64, usize, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
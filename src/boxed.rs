@@ -3,6 +3,7 @@
 use crate::alloc::MemPool;
 use crate::cell::RootObj;
 use crate::clone::*;
+use crate::trace::{Trace, Tracer};
 use crate::ptr::Ptr;
 use crate::stm::*;
 use crate::{PSafe, VSafe, TxOutSafe};
@@ -138,6 +139,36 @@ impl<T: PSafe, A: MemPool> Pbox<T, A> {
         }
     }
 
+    /// Allocates memory on the persistent heap at an address aligned to
+    /// `align`, and then places `x` into it.
+    ///
+    /// `align` should be a power of two not larger than [`MAX_ALIGN`]. If it
+    /// is smaller than the alignment of `T`, the alignment of `T` is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # type P = Allocator;
+    /// # let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// P::transaction(|j| {
+    ///     let line = Pbox::new_aligned([0u8; 16], 256, j);
+    ///     assert_eq!(&*line as *const _ as usize % 256, 0);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`MAX_ALIGN`]: ../alloc/constant.MAX_ALIGN.html
+    pub fn new_aligned(x: T, align: usize, journal: &Journal<A>) -> Pbox<T, A> {
+        if mem::size_of::<T>() == 0 {
+            Pbox(Ptr::dangling(), 0)
+        } else {
+            unsafe {
+                let p = A::new_aligned(x, align, journal);
+                Pbox(Ptr::from_mut(p), 0)
+            }
+        }
+    }
+
     pub fn off(&self) -> u64 {
         self.0.off()
     }
@@ -250,12 +281,11 @@ impl<T: PSafe, A: MemPool> Pbox<T, A> {
     /// ```
     ///
     pub fn initialize(boxed: &Option<Pbox<T, A>>, value: T) -> crate::result::Result<()> {
-        assert!(
-            !Journal::<A>::is_running(),
-            "Pbox::initialize() cannot be used inside a transaction"
-        );
+        if Journal::<A>::is_running() {
+            return Err(crate::Error::InTransaction);
+        }
         match boxed {
-            Some(_) => Err("already initialized".to_string()),
+            Some(_) => Err(crate::Error::AlreadyInitialized),
             None => if A::valid(boxed) {
                 unsafe {
                     let new = A::atomic_new(value);
@@ -270,7 +300,7 @@ impl<T: PSafe, A: MemPool> Pbox<T, A> {
                 }
                 Ok(())
             } else {
-                Err("The object is not in the PM".to_string())
+                Err(crate::Error::NotInPool)
             }
         }
     }
@@ -447,6 +477,19 @@ impl<T: RootObj<A> + PSafe, A: MemPool> RootObj<A> for Pbox<T, A> {
     default fn init(journal: &Journal<A>) -> Pbox<T, A> {
         Pbox::new(T::init(journal), journal)
     }
+
+    #[inline]
+    default fn schema() -> String {
+        format!("Pbox<{}>", T::schema())
+    }
+}
+
+impl<T: PSafe + Trace<A> + ?Sized, A: MemPool> Trace<A> for Pbox<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        if !self.0.is_dangling() && tracer.mark_obj(self.get_ref()) {
+            self.get_ref().trace(tracer);
+        }
+    }
 }
 
 impl<T: PSafe + PClone<A> + ?Sized, A: MemPool> PClone<A> for Pbox<T, A> {
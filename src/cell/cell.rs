@@ -11,6 +11,9 @@ use std::{fmt, mem, ptr};
 #[cfg(any(feature = "use_pspd", feature = "use_vspd"))]
 use crate::cell::TCell;
 
+#[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))]
+use crate::stm::Log;
+
 /// A persistent mutable memory location with recoverability
 ///
 /// This is one of the safe ways to provide interior mutability for pointer
@@ -195,13 +198,13 @@ impl<T: PSafe, A: MemPool> PCell<T, A> {
     /// ```
     #[inline]
     pub fn swap(&self, other: &Self, journal: &Journal<A>) {
-        let this = unsafe { self.as_mut() };
-        let that = unsafe { other.as_mut() };
-        if ptr::eq(this, that) {
+        if ptr::eq(self, other) {
             return;
         }
         self.create_log(journal);
         other.create_log(journal);
+        let this = unsafe { self.as_mut() };
+        let that = unsafe { other.as_mut() };
 
         // SAFETY: This can be risky if called from separate threads, but `PCell`
         // is `!Sync` so this won't happen. This also won't invalidate any
@@ -245,7 +248,7 @@ impl<T: PSafe, A: MemPool> PCell<T, A> {
         }
 
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))]
-        mem::replace(unsafe { &mut (*self.value.get()).1 }, val)
+        mem::replace(unsafe { &mut *self.value_ptr() }, val)
     }
 
     /// Unwraps the value.
@@ -309,7 +312,7 @@ impl<T: PSafe, A: MemPool> PCell<T, A> {
             }
             
             #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-                (*self.value.get()).1
+                *self.value_ptr()
             }
         }
     }
@@ -346,6 +349,17 @@ impl<T: PSafe, A: MemPool> PCell<T, A> {
 }
 
 impl<T: PSafe + ?Sized, A: MemPool> PCell<T, A> {
+    /// Returns a pointer to the current value, which is the draft if the
+    /// value is redo-logged
+    #[inline]
+    #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))]
+    fn value_ptr(&self) -> *mut T {
+        unsafe {
+            let inner = &*self.value.get();
+            Log::<A>::redirect(inner.0, &inner.1)
+        }
+    }
+
     #[inline]
     #[track_caller]
     pub(crate) fn create_log(&self, journal: &Journal<A>) {
@@ -363,7 +377,12 @@ impl<T: PSafe + ?Sized, A: MemPool> PCell<T, A> {
                 use crate::stm::Notifier;
                 if inner.0 == 0 {
                     assert!(A::valid(inner), "The object is not in the pool's valid range");
-                    inner.1.create_log(journal, Notifier::NonAtomic(Ptr::from_ref(&inner.0)));
+                    let notifier = Notifier::NonAtomic(Ptr::from_ref(&inner.0));
+                    if journal.redo_logging() {
+                        Log::create_redo(&inner.1, journal, notifier);
+                    } else {
+                        inner.1.create_log(journal, notifier);
+                    }
                 }
             }
         }
@@ -406,7 +425,7 @@ impl<T: PSafe + ?Sized, A: MemPool> PCell<T, A> {
         }
 
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            unsafe { &mut (*self.value.get()).1 }
+            unsafe { &mut *self.value_ptr() }
         }
     }
     
@@ -443,7 +462,7 @@ impl<T: PSafe + ?Sized, A: MemPool> PCell<T, A> {
             }
         }
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            &mut (*self.value.get()).1
+            &mut *self.value_ptr()
         }
     }
 }
@@ -478,7 +497,19 @@ impl<T: fmt::Debug + PSafe + Copy, A: MemPool> fmt::Debug for PCell<T, A> {
         }
 
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            unsafe { (*self.value.get()).1.fmt(f) }
+            unsafe { (*self.value_ptr()).fmt(f) }
+        }
+    }
+}
+
+impl<T: PSafe + Trace<A>, A: MemPool> Trace<A> for PCell<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
+            unsafe { (*self.value.get()).trace(tracer) }
+        }
+
+        #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
+            unsafe { (*self.value_ptr()).trace(tracer) }
         }
     }
 }
@@ -491,7 +522,7 @@ impl<T: PSafe + Logger<A> + Copy, A: MemPool> PClone<A> for PCell<T, A> {
         }
 
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            unsafe { PCell::new((*self.value.get()).1) }
+            unsafe { PCell::new(*self.value_ptr()) }
         }
     }
 }
@@ -504,7 +535,7 @@ impl<T: PSafe + Logger<A> + Copy, A: MemPool> Clone for PCell<T, A> {
         }
 
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            unsafe { PCell::new((*self.value.get()).1.clone()) }
+            unsafe { PCell::new(*self.value_ptr()) }
         }
     }
 }
\ No newline at end of file
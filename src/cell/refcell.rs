@@ -12,6 +12,9 @@ use std::panic::{RefUnwindSafe, UnwindSafe};
 #[cfg(any(feature = "use_pspd", feature = "use_vspd"))]
 use crate::cell::TCell;
 
+#[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))]
+use crate::stm::Log;
+
 #[cfg(not(feature = "no_dyn_borrow_checking"))]
 use crate::cell::VCell;
 
@@ -195,6 +198,12 @@ impl<T: PSafe + RootObj<A>, A: MemPool> RootObj<A> for PRefCell<T, A> {
     }
 }
 
+impl<T: PSafe + Trace<A> + ?Sized, A: MemPool> Trace<A> for PRefCell<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        self.as_ref().trace(tracer)
+    }
+}
+
 impl<T: PSafe + Display + ?Sized, A: MemPool> Display for PRefCell<T, A> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         self.as_ref().fmt(fmt)
@@ -240,19 +249,18 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
     /// }).unwrap();
     /// ```
     pub fn get_mut(&mut self, journal: &Journal<A>) -> &mut T {
-        let inner = unsafe { &mut *self.value.get() };
         self.create_log(journal);
 
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] unsafe {
             if let Some(tmp) = *self.temp {
                 &mut *tmp
             } else {
-                &mut *inner
+                &mut *self.value.get()
             }
         }
     
-        #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            &mut inner.1
+        #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] unsafe {
+            &mut *self.value_ptr()
         }
     }
 
@@ -290,7 +298,7 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
         }
     
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            &mut (*self.value.get()).1
+            &mut *self.value_ptr()
         }
     }
 
@@ -307,7 +315,7 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
             }
         
             #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-                &(*self.value.get()).1
+                &*self.value_ptr()
             }
         }
     }
@@ -359,6 +367,17 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
         self.as_ref().clone()
     }
 
+    /// Returns a pointer to the current value, which is the draft if the
+    /// value is redo-logged
+    #[inline]
+    #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))]
+    fn value_ptr(&self) -> *mut T {
+        unsafe {
+            let inner = &*self.value.get();
+            Log::<A>::redirect(inner.0, &inner.1)
+        }
+    }
+
     #[inline]
     #[track_caller]
     pub(crate) fn create_log(&self, journal: &Journal<A>) {
@@ -378,7 +397,12 @@ impl<T: PSafe + ?Sized, A: MemPool> PRefCell<T, A> {
                 use crate::stm::{Notifier, Logger};
                 if inner.0 == 0 {
                     assert!(A::valid(inner), "The object is not in the pool's valid range");
-                    inner.1.create_log(journal, Notifier::NonAtomic(Ptr::from_ref(&inner.0)));
+                    let notifier = Notifier::NonAtomic(Ptr::from_ref(&inner.0));
+                    if journal.redo_logging() {
+                        Log::create_redo(&inner.1, journal, notifier);
+                    } else {
+                        inner.1.create_log(journal, notifier);
+                    }
                 }
             }
         }
@@ -496,7 +520,8 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
             LogNonNull::new_unchecked(inner, journal)
         }
         #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-            LogNonNull::new_unchecked(&mut inner.1, &mut inner.0, journal)
+            LogNonNull::new_unchecked(Log::<A>::redirect(inner.0, &inner.1),
+                &mut inner.0, journal)
         }
     }
 
@@ -508,7 +533,7 @@ impl<T: PSafe, A: MemPool> PRefCell<T, A> {
                 NonNull::new_unchecked(inner)
             }
             #[cfg(not(any(feature = "use_pspd", feature = "use_vspd")))] {
-                NonNull::new_unchecked(&mut inner.1)
+                NonNull::new_unchecked(Log::<A>::redirect(inner.0, &inner.1))
             }
         }
     }
@@ -98,6 +98,166 @@ impl<T: Display + PSafe, A: MemPool> Display for RootCell<'_, T, A> {
 /// [`Default`]: std::default::Default
 pub trait RootObj<A: MemPool> {
     fn init(journal: &Journal<A>) -> Self;
+
+    /// Returns a structural description of the type
+    ///
+    /// The pool stores a fingerprint of the schema of the root type to detect
+    /// incompatible root types (see [`fingerprint()`]). By default, it
+    /// consists of the type name and size. `#[derive(Root)]` describes the
+    /// names, offsets and schemas of the fields, so that reordering or
+    /// retyping them changes the fingerprint.
+    ///
+    /// [`fingerprint()`]: ./fn.fingerprint.html
+    fn schema() -> String where Self: Sized {
+        format!("{} ({})", std::any::type_name::<Self>(), std::mem::size_of::<Self>())
+    }
+}
+
+/// Returns the schema fingerprint of the root type `T`
+///
+/// The name of the pool type is removed from the schema, as it does not
+/// change the layout. So, a pool file can be opened by any instance of the
+/// pool type, or by another pool type.
+pub fn fingerprint<T: RootObj<A>, A: MemPool>() -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut s = DefaultHasher::new();
+    T::schema().replace(std::any::type_name::<A>(), "_").hash(&mut s);
+    s.finish()
+}
+
+/// Returns the fingerprint which was stored for the root type `T` before the
+/// schemas were fingerprinted. It consists of the type name and the size.
+#[doc(hidden)]
+pub fn legacy_fingerprint<T, A: MemPool>() -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut s = DefaultHasher::new();
+    format!("{} ({})", std::any::type_name::<T>(), std::mem::size_of::<T>()).hash(&mut s);
+    s.finish()
+}
+
+/// A set of upgrade functions for the root object
+///
+/// When a pool is opened using [`open_with_migrations()`] and the stored root
+/// object has the schema of one of the registered old types, its upgrade
+/// function creates the new root object from the old one. The new root object
+/// is created, it replaces the old one in the pool header, and the old root
+/// object is dropped, all in one transaction. So, a failure in the middle of
+/// the upgrade leaves the old root object in place, and nothing leaks.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::{Migrations, PoolOptions};
+///
+/// type P = Allocator;
+///
+/// #[derive(Root)]
+/// struct V1 {
+///     value: PCell<i32>,
+/// }
+///
+/// #[derive(Root)]
+/// struct V2 {
+///     value: PCell<i64>,
+///     count: PCell<usize>,
+/// }
+///
+/// {
+///     let root = P::open::<V1>("foo.pool", O_CF).unwrap();
+///     transaction(|j| root.value.set(10, j)).unwrap();
+/// }
+///
+/// let mut migrations = Migrations::new();
+/// migrations.register(|old: &V1, _j| V2 {
+///     value: PCell::new(old.value.get() as i64),
+///     count: PCell::new(1),
+/// });
+///
+/// let root = P::open_with_migrations::<V2>("foo.pool", &PoolOptions::new(),
+///     &migrations).unwrap();
+/// assert_eq!(root.value.get(), 10);
+/// ```
+///
+/// [`open_with_migrations()`]: ../alloc/trait.MemPoolTraits.html#method.open_with_migrations
+pub struct Migrations<U, A: MemPool> {
+    list: Vec<Migration<U, A>>,
+}
+
+/// A registered migration: the fingerprint of the old root type, the
+/// conversion into the new root, and the function dropping the old root
+type Migration<U, A> = (u64, Box<dyn Fn(u64, &Journal<A>) -> U>, unsafe fn(u64));
+
+/// Drops and frees the old root object at offset `off`
+unsafe fn drop_root<Old: PSafe + RootObj<A>, A: MemPool>(off: u64) {
+    let old = A::get_mut_unchecked::<Old>(off);
+    std::ptr::drop_in_place(old);
+    A::free(old);
+}
+
+/// The upgrade functions are function pointers, so they can be asserted to go
+/// in the transaction which upgrades the root object
+unsafe impl<U, A: MemPool> LooseTxInUnsafe for Migrations<U, A> {}
+
+impl<U, A: MemPool> Migrations<U, A> {
+    /// Creates an empty set of migrations
+    pub fn new() -> Self {
+        Self { list: vec![] }
+    }
+
+    /// Registers an upgrade function from root type `Old` to `U`
+    pub fn register<Old: PSafe + RootObj<A> + 'static>(
+        &mut self,
+        f: fn(&Old, &Journal<A>) -> U,
+    ) -> &mut Self where U: 'static {
+        self.list.push((fingerprint::<Old, A>(), Box::new(move |off, j| unsafe {
+            f(A::get_unchecked::<Old>(off), j)
+        }), drop_root::<Old, A>));
+        self
+    }
+
+    /// Returns true if there is an upgrade function for the given fingerprint
+    pub fn supports(&self, fingerprint: u64) -> bool {
+        self.list.iter().any(|(id, _, _)| *id == fingerprint)
+    }
+
+    /// Creates the new root object from the old one at offset `off` with the
+    /// given schema fingerprint. The old root object is left intact.
+    ///
+    /// # Safety
+    ///
+    /// `off` should point to a valid root object with the given fingerprint
+    pub unsafe fn migrate(&self, fingerprint: u64, off: u64, j: &Journal<A>) -> Option<U> {
+        self.list
+            .iter()
+            .find(|(id, _, _)| *id == fingerprint)
+            .map(|(_, f, _)| f(off, j))
+    }
+
+    /// Drops the old root object at offset `off` with the given schema
+    /// fingerprint, and returns false if there is no such migration
+    ///
+    /// # Safety
+    ///
+    /// `off` should point to a valid root object with the given fingerprint
+    /// which is no longer the root object of the pool
+    pub unsafe fn drop_old(&self, fingerprint: u64, off: u64, _j: &Journal<A>) -> bool {
+        self.list
+            .iter()
+            .find(|(id, _, _)| *id == fingerprint)
+            .map(|(_, _, d)| d(off))
+            .is_some()
+    }
+}
+
+impl<U, A: MemPool> Default for Migrations<U, A> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Default, A: MemPool> RootObj<A> for T {
@@ -2,6 +2,7 @@ use crate::RootObj;
 use crate::stm::Journal;
 use crate::alloc::MemPool;
 use crate::{PSafe, VSafe, utils};
+use crate::trace::{Trace, Tracer};
 use std::cmp::*;
 use std::mem::*;
 use std::marker::PhantomData;
@@ -119,6 +120,12 @@ impl<T: Default + VSafe, A: MemPool> TCell<T, A> {
     }
 }
 
+/// Volatile data does not own persistent allocations
+impl<T: Default + VSafe + ?Sized, A: MemPool> Trace<A> for TCell<T, A> {
+    #[inline]
+    fn trace(&self, _tracer: &mut Tracer<A>) {}
+}
+
 impl<T: Default + VSafe, A: MemPool> RootObj<A> for TCell<T, A> {
     fn init(j: &Journal<A>) -> Self {
         Self {
@@ -1,5 +1,6 @@
 use crate::alloc::MemPool;
 use crate::{PSafe, VSafe, utils};
+use crate::trace::{Trace, Tracer};
 use std::cmp::*;
 use std::mem::*;
 use std::marker::PhantomData;
@@ -96,6 +97,12 @@ impl<T: Default + VSafe, A: MemPool> VCell<T, A> {
     }
 }
 
+/// Volatile data does not own persistent allocations
+impl<T: Default + VSafe + ?Sized, A: MemPool> Trace<A> for VCell<T, A> {
+    #[inline]
+    fn trace(&self, _tracer: &mut Tracer<A>) {}
+}
+
 impl<T: Default + VSafe, A: MemPool> Default for VCell<T, A> {
     fn default() -> Self {
         Self {
@@ -0,0 +1,256 @@
+//! The error type of the crate
+use std::any::Any;
+use std::fmt;
+use std::io;
+
+/// The errors of pool management and transactions
+///
+/// It is the error type of [`corundum::result::Result`]. The errors which do
+/// not have a dedicated variant are kept in [`Other`] with a description.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::Error;
+///
+/// type P = Allocator;
+///
+/// let _root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+///
+/// match P::transaction(|_| panic!("abort")) {
+///     Err(Error::TxAborted { payload }) => {
+///         assert_eq!(payload.downcast_ref::<&str>(), Some(&"abort"));
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// [`corundum::result::Result`]: ./result/type.Result.html
+/// [`Other`]: #variant.Other
+#[non_exhaustive]
+pub enum Error {
+    /// An I/O error occurred while accessing a file
+    Io(io::Error),
+
+    /// The file is not a valid pool image
+    InvalidMagic,
+
+    /// The pool image has a format version other than [`FORMAT_VERSION`].
+    /// The images made before the format had a version have version `0`.
+    ///
+    /// [`FORMAT_VERSION`]: ./constant.FORMAT_VERSION.html
+    UnsupportedVersion(u32),
+
+    /// The root object of the pool has a different type
+    IncompatibleRoot,
+
+    /// The pool has no root object
+    NoRoot,
+
+    /// The pool has no free block large enough for an allocation
+    OutOfMemory,
+
+    /// The transaction panicked and was rolled back; `payload` is the panic
+    /// payload
+    TxAborted {
+        /// The object with which the transaction panicked
+        payload: Box<dyn Any + Send>,
+    },
+
+    /// The transaction was aborted and rolled back to break a deadlock
+    Deadlock,
+
+    /// The pool file is locked by another process, or another session is
+    /// running
+    PoolBusy,
+
+    /// The operation requires an open pool, but no pool is open
+    PoolNotOpen,
+
+    /// The operation requires the pool to be closed
+    PoolOpen,
+
+    /// The operation cannot be performed inside a transaction, or while an
+    /// uncommitted transaction exists
+    InTransaction,
+
+    /// The operation cannot be performed on a read-only pool
+    ReadOnly,
+
+    /// The root type is a zero-sized type
+    ZeroSizedRoot,
+
+    /// The offset or the address is not in the allocated memory of the pool
+    AccessViolation(u64),
+
+    /// The object is already initialized
+    AlreadyInitialized,
+
+    /// The object is not in the persistent memory pool
+    NotInPool,
+
+    /// The pool cannot have the requested size in bytes
+    InvalidSize(usize),
+
+    /// The pool does not keep the type information of the allocations
+    NoTypeInfo,
+
+    /// The operation is not supported on this platform
+    Unsupported,
+
+    /// The pool options are inconsistent
+    InvalidOptions(String),
+
+    /// Any other error with its description
+    Other(String),
+}
+
+/// The errors do not hold any persistent pointer, so they can be returned from
+/// a transaction
+unsafe impl crate::TxOutSafe for Error {}
+
+impl Error {
+    /// Returns the panic message if this is a [`TxAborted`] error whose
+    /// payload is a string
+    ///
+    /// [`TxAborted`]: #variant.TxAborted
+    pub fn panic_message(&self) -> Option<&str> {
+        match self {
+            Error::TxAborted { payload } => {
+                if let Some(s) = payload.downcast_ref::<&str>() {
+                    Some(s)
+                } else {
+                    payload.downcast_ref::<String>().map(|s| s.as_str())
+                }
+            }
+            _ => None
+        }
+    }
+
+    /// Returns true if the failed transaction may succeed if it is executed
+    /// again
+    ///
+    /// These are the transactions which requested a retry using
+    /// [`Journal::retry()`], the victims of deadlocks, and the ones which ran
+    /// out of memory.
+    ///
+    /// [`Journal::retry()`]: ./stm/journal/struct.Journal.html#method.retry
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::TxAborted { payload } => payload.is::<crate::stm::Retry>(),
+            Error::Deadlock | Error::OutOfMemory => true,
+            _ => false
+        }
+    }
+
+    /// Converts the payload of a panicked transaction into an error
+    ///
+    /// The operations which fail inside a transaction, e.g. allocations when
+    /// the pool is out of memory, panic with an `Error` payload, which is
+    /// returned as it is.
+    pub(crate) fn aborted(payload: Box<dyn Any + Send>) -> Self {
+        if crate::sync::deadlock::is_victim(&payload) {
+            Error::Deadlock
+        } else {
+            match payload.downcast::<Error>() {
+                Ok(e) => *e,
+                Err(payload) => Error::TxAborted { payload }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::InvalidMagic => write!(f, "Invalid magic number for the pool image file"),
+            Error::UnsupportedVersion(v) => write!(f,
+                "Unsupported pool format version {} (expected {})", v, crate::FORMAT_VERSION),
+            Error::IncompatibleRoot => write!(f, "Incompatible root type"),
+            Error::NoRoot => write!(f, "The pool has no root object"),
+            Error::OutOfMemory => write!(f, "Memory exhausted"),
+            Error::TxAborted { .. } => {
+                if let Some(msg) = self.panic_message() {
+                    write!(f, "Unsuccessful transaction: {}", msg)
+                } else {
+                    write!(f, "Unsuccessful transaction")
+                }
+            }
+            Error::Deadlock => write!(f, "Transaction aborted to break a deadlock"),
+            Error::PoolBusy => write!(f, "The pool is busy"),
+            Error::PoolNotOpen => write!(f, "No memory pool is open"),
+            Error::PoolOpen => write!(f, "The pool is open"),
+            Error::InTransaction => write!(f, "Cannot be done inside a transaction"),
+            Error::ReadOnly => write!(f, "The pool is read-only"),
+            Error::ZeroSizedRoot => write!(f, "The root type cannot be a ZST"),
+            Error::AccessViolation(off) => write!(f, "Access Violation (0x{:x})", off),
+            Error::AlreadyInitialized => write!(f, "The object is already initialized"),
+            Error::NotInPool => write!(f, "The object is not in the PM"),
+            Error::InvalidSize(size) => write!(f, "The pool cannot have {} bytes", size),
+            Error::NoTypeInfo => write!(f, "The pool does not keep the type information"),
+            Error::Unsupported => write!(f, "Not supported on this platform"),
+            Error::InvalidOptions(msg) => write!(f, "Invalid pool options: {}", msg),
+            Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => f.debug_tuple("Io").field(e).finish(),
+            Error::TxAborted { .. } => f.debug_struct("TxAborted")
+                .field("payload", &self.panic_message().unwrap_or("Any"))
+                .finish(),
+            Error::InvalidOptions(msg) => f.debug_tuple("InvalidOptions").field(msg).finish(),
+            Error::Other(msg) => f.debug_tuple("Other").field(msg).finish(),
+            Error::InvalidMagic => write!(f, "InvalidMagic"),
+            Error::UnsupportedVersion(v) => f.debug_tuple("UnsupportedVersion").field(v).finish(),
+            Error::IncompatibleRoot => write!(f, "IncompatibleRoot"),
+            Error::NoRoot => write!(f, "NoRoot"),
+            Error::OutOfMemory => write!(f, "OutOfMemory"),
+            Error::Deadlock => write!(f, "Deadlock"),
+            Error::PoolBusy => write!(f, "PoolBusy"),
+            Error::PoolNotOpen => write!(f, "PoolNotOpen"),
+            Error::PoolOpen => write!(f, "PoolOpen"),
+            Error::InTransaction => write!(f, "InTransaction"),
+            Error::ReadOnly => write!(f, "ReadOnly"),
+            Error::ZeroSizedRoot => write!(f, "ZeroSizedRoot"),
+            Error::AccessViolation(off) => f.debug_tuple("AccessViolation").field(off).finish(),
+            Error::AlreadyInitialized => write!(f, "AlreadyInitialized"),
+            Error::NotInPool => write!(f, "NotInPool"),
+            Error::InvalidSize(size) => f.debug_tuple("InvalidSize").field(size).finish(),
+            Error::NoTypeInfo => write!(f, "NoTypeInfo"),
+            Error::Unsupported => write!(f, "Unsupported"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Error::Other(msg)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(msg: &str) -> Self {
+        Error::Other(msg.to_string())
+    }
+}
@@ -135,8 +135,6 @@
 #![allow(incomplete_features)]
 #![allow(type_alias_bounds)]
 
-pub(crate) const PAGE_LOG_SLOTS: usize = 128;
-
 extern crate crndm_derive;
 extern crate impl_trait_for_tuples;
 
@@ -146,6 +144,7 @@ pub mod sync;
 pub mod ptr;
 pub mod stm;
 pub mod stat;
+pub mod sim;
 pub mod utils;
 pub mod stl;
 pub mod gen;
@@ -154,9 +153,11 @@ mod alloc;
 mod boxed;
 mod cell;
 mod clone;
+mod trace;
 mod str;
 pub mod vec;
 mod convert;
+mod error;
 mod marker;
 mod tests;
 
@@ -168,18 +169,20 @@ pub use boxed::*;
 pub use prc::Prc;
 pub use sync::{Parc,PMutex};
 pub use clone::*;
+pub use trace::*;
 pub use vec::Vec as PVec;
 pub use self::str::{String as PString, ToPString, ToPStringSlice};
 pub use cell::*;
 pub use alloc::*;
 pub use convert::*;
-pub use stm::Journal;
+pub use error::Error;
+pub use stm::{Journal, ReadJournal, RetryPolicy};
 
 // This is an example of defining a new buddy allocator type
 // `Allocator` is the default allocator with Buddy Allocation algorithm
 crate::pool!(default);
 
-/// A `Result` type with string error messages
+/// A `Result` type with [`Error`](../enum.Error.html)
 pub mod result {
-    pub type Result<T: ?Sized> = std::result::Result<T, String>;
+    pub type Result<T: ?Sized> = std::result::Result<T, crate::Error>;
 }
\ No newline at end of file
@@ -82,10 +82,16 @@ pub fn persist_obj<T: ?Sized>(obj: &T, fence: bool) {
 pub fn clflush<T: ?Sized>(ptr: *const T, len: usize, fence: bool) {
     #[cfg(not(feature = "no_persist"))]
     {
+        crate::sim::crash_point();
+
         let ptr = ptr as *const u8 as *mut u8;
         let mut start = ptr as usize;
-        start = (start >> 9) << 9;
         let end = start + len;
+        start = (start >> 6) << 6;
+
+        if crate::sim::is_active() {
+            crate::sim::flush(start, end - start);
+        }
 
         #[cfg(feature = "stat_print_flushes")]
         println!("flush {:x} ({})", start, len);
@@ -124,9 +130,14 @@ pub fn clflush<T: ?Sized>(ptr: *const T, len: usize, fence: bool) {
 /// Store fence
 #[inline(always)]
 pub fn sfence() {
+    crate::sim::crash_point();
+
     #[cfg(any(feature = "use_clwb", feature = "use_clflushopt"))] unsafe {
         _mm_sfence();
     }
+    if crate::sim::is_active() {
+        crate::sim::fence();
+    }
 }
 
 /// Memory fence
@@ -766,12 +766,11 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
     /// ```
     ///
     pub fn initialize(rc: &Option<Prc<T, A>>, value: T) -> crate::result::Result<()> {
-        assert!(
-            !Journal::<A>::is_running(),
-            "Prc::initialize() cannot be used inside a transaction"
-        );
+        if Journal::<A>::is_running() {
+            return Err(crate::Error::InTransaction);
+        }
         match rc {
-            Some(_) => Err("already initialized".to_string()),
+            Some(_) => Err(crate::Error::AlreadyInitialized),
             None => if A::valid(rc) {
                 unsafe {
                     let new = A::atomic_new(
@@ -810,7 +809,7 @@ impl<T: PSafe, A: MemPool> Prc<T, A> {
                 }
                 Ok(())
             } else {
-                Err("The object is not in the PM".to_string())
+                Err(crate::Error::NotInPool)
             }
         }
     }
@@ -901,11 +900,25 @@ impl<T: PSafe + ?Sized, A: MemPool> PClone<A> for Prc<T, A> {
     }
 }
 
+impl<T: PSafe + Trace<A> + ?Sized, A: MemPool> Trace<A> for Prc<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        let inner = self.inner();
+        if tracer.mark_obj(inner) {
+            inner.value.trace(tracer);
+        }
+    }
+}
+
 impl<T: RootObj<A> + PSafe, A: MemPool> RootObj<A> for Prc<T, A> {
     #[inline]
     default fn init(journal: &Journal<A>) -> Prc<T, A> {
         Prc::new(T::init(journal), journal)
     }
+
+    #[inline]
+    default fn schema() -> String {
+        format!("Prc<{}>", T::schema())
+    }
 }
 
 // impl<T: Default + PSafe + ?Sized, A: MemPool> RootObj<A> for Prc<T, A> {
@@ -1155,6 +1168,17 @@ impl<T: PSafe + fmt::Debug + ?Sized, A: MemPool> fmt::Debug for Weak<T, A> {
     }
 }
 
+impl<T: PSafe + Trace<A> + ?Sized, A: MemPool> Trace<A> for Weak<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        if let Some(inner) = self.inner() {
+            // The value is already dropped if there is no strong reference
+            if tracer.mark_obj(inner) && inner.strong() > 0 {
+                inner.value.trace(tracer);
+            }
+        }
+    }
+}
+
 impl<T: PSafe + ?Sized, A: MemPool> RootObj<A> for Weak<T, A> {
     fn init(_: &Journal<A>) -> Weak<T, A> {
         Weak::new()
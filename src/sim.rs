@@ -0,0 +1,527 @@
+//! Simulation of persistent memory failures
+//!
+//! Writes to a memory-mapped file on a regular file system survive a crash of
+//! the process even if they are never flushed, and the [`Heap`] pool does not
+//! outlive the process at all. Therefore, a missing [`persist`] call may stay
+//! hidden until the program runs on real persistent memory. This module simulates the persistence
+//! domain of an open pool at the cache line granularity, and produces crash
+//! images in which the unflushed writes are lost.
+//!
+//! [`PmSim`] keeps a copy of the durable contents of the pool. A cache line is
+//! durable once it is flushed by [`clflush`]. With `use_clwb` or
+//! `use_clflushopt` features, the flushed lines become durable only after the
+//! next [`sfence`]. Any line which differs from its durable copy at the crash
+//! point is unflushed, and it is either reverted or randomly kept in the crash
+//! image according to the [`CrashMode`].
+//!
+//! # Examples
+//!
+//! ```
+//! use corundum::default::*;
+//! use corundum::sim::*;
+//!
+//! type P = Allocator;
+//!
+//! {
+//!     let root = P::open::<PCell<i32>>("sim.pool", O_CF).unwrap();
+//!     let sim = PmSim::<P>::start().unwrap();
+//!     P::transaction(|j| {
+//!         root.set(10, j);
+//!         // A crash in the middle of the transaction
+//!         sim.crash("sim.crash", CrashMode::Revert).unwrap();
+//!     }).unwrap();
+//! }
+//!
+//! // The transaction is rolled back by the recovery procedure
+//! PmSim::<P>::recover::<PCell<i32>, _, _>("sim.crash", |root| {
+//!     assert_eq!(root.get(), 0);
+//! }).unwrap();
+//! ```
+//!
+//! [`CrashHarness`] enumerates the crash points of a workload, and checks the
+//! consistency of the pool after crashing at each of them.
+//!
+//! [`Heap`]: ../alloc/heap/struct.Heap.html
+//! [`CrashHarness`]: ./struct.CrashHarness.html
+//! [`persist`]: ../ll/fn.persist.html
+//! [`clflush`]: ../ll/fn.clflush.html
+//! [`sfence`]: ../ll/fn.sfence.html
+//! [`PmSim`]: ./struct.PmSim.html
+//! [`CrashMode`]: ./enum.CrashMode.html
+
+use crate::alloc::{write_snapshot, MemPool};
+use crate::cell::{LazyCell, RootObj};
+use crate::result::Result;
+use crate::{Error, PSafe};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// The granularity of tracking the writes
+pub const CACHE_LINE: usize = 64;
+
+/// The persistence domain of the simulated region
+struct Domain {
+    pid: u32,
+    start: usize,
+    durable: Vec<u8>,
+
+    /// Flushed lines which become durable at the next fence
+    pending: HashMap<usize, [u8; CACHE_LINE]>,
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static DOMAIN: LazyCell<Mutex<Option<Domain>>> = LazyCell::new(|| Mutex::new(None));
+
+fn domain() -> MutexGuard<'static, Option<Domain>> {
+    match DOMAIN.lock() {
+        Ok(g) => g,
+        Err(p) => p.into_inner(),
+    }
+}
+
+/// Indicates if a simulation is running
+#[inline(always)]
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Records a flush of the cache lines in `[addr, addr+len)`
+///
+/// It is called by [`ll::clflush`](../ll/fn.clflush.html).
+pub fn flush(addr: usize, len: usize) {
+    let mut domain = domain();
+    if let Some(d) = &mut *domain {
+        let end = (addr + len).min(d.start + d.durable.len());
+        let mut line = addr.max(d.start);
+        line -= (line - d.start) % CACHE_LINE;
+        while line < end {
+            let off = line - d.start;
+            let size = CACHE_LINE.min(d.durable.len() - off);
+            let live = unsafe { std::slice::from_raw_parts(line as *const u8, size) };
+            if cfg!(any(feature = "use_clwb", feature = "use_clflushopt")) {
+                let mut data = [0u8; CACHE_LINE];
+                data[..size].copy_from_slice(live);
+                d.pending.insert(off, data);
+            } else {
+                // `clflush` is ordered with the other writes and flushes
+                d.durable[off..off + size].copy_from_slice(live);
+            }
+            line += CACHE_LINE;
+        }
+    }
+}
+
+/// Makes the pending flushed lines durable
+///
+/// It is called by [`ll::sfence`](../ll/fn.sfence.html).
+pub fn fence() {
+    let mut domain = domain();
+    if let Some(d) = &mut *domain {
+        let len = d.durable.len();
+        for (off, data) in d.pending.drain() {
+            let size = CACHE_LINE.min(len - off);
+            d.durable[off..off + size].copy_from_slice(&data[..size]);
+        }
+    }
+}
+
+/// Determines what happens to the unflushed cache lines in a crash image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashMode {
+    /// All unflushed lines are lost
+    Revert,
+
+    /// Each unflushed line is kept with the probability of one half. The
+    /// random choices are determined by the seed.
+    Random(u64),
+}
+
+/// The result of producing a crash image
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrashReport {
+    /// Number of the unflushed cache lines at the crash point
+    pub unflushed: usize,
+
+    /// Number of the unflushed cache lines which are kept in the image
+    pub kept: usize,
+}
+
+/// Builds the contents of the simulated region after a crash at this point
+fn crash_image(mode: CrashMode) -> Option<(Vec<u8>, CrashReport)> {
+    let mut rng = match mode {
+        CrashMode::Revert => None,
+        CrashMode::Random(seed) => Some(StdRng::seed_from_u64(seed)),
+    };
+    let mut report = CrashReport::default();
+    let domain = domain();
+    let d = domain.as_ref()?;
+    let live = unsafe {
+        std::slice::from_raw_parts(d.start as *const u8, d.durable.len())
+    };
+    let mut image = d.durable.clone();
+    for (i, line) in live.chunks(CACHE_LINE).enumerate() {
+        let off = i * CACHE_LINE;
+        if line != &d.durable[off..off + line.len()] {
+            report.unflushed += 1;
+            if let Some(rng) = &mut rng {
+                if rng.gen::<bool>() {
+                    image[off..off + line.len()].copy_from_slice(line);
+                    report.kept += 1;
+                }
+            }
+        }
+    }
+    Some((image, report))
+}
+
+/// A simulated persistent memory for an open pool
+///
+/// Only one simulation may run at a time in a process. It covers the pool as
+/// it is when the simulation starts, so it should start right after opening
+/// the pool, when all of its contents are durable. The region added by
+/// [`grow()`] is not covered. The simulation stops when `PmSim` is dropped.
+///
+/// See the [module-level documentation](./index.html) for more details.
+///
+/// [`grow()`]: ../alloc/trait.MemPoolTraits.html#method.grow
+pub struct PmSim<P: MemPool> {
+    phantom: PhantomData<P>,
+}
+
+impl<P: MemPool> PmSim<P> {
+    /// Starts simulating the persistence domain of the open pool `P`
+    pub fn start() -> Result<Self> {
+        if !P::is_open() {
+            return Err(Error::PoolNotOpen);
+        }
+        let mut domain = domain();
+        if let Some(d) = &*domain {
+            // A simulation copied from the parent process is replaced
+            if d.pid == std::process::id() {
+                return Err(Error::PoolBusy);
+            }
+        }
+        let durable = unsafe {
+            std::slice::from_raw_parts(P::start() as *const u8, P::size())
+        };
+        *domain = Some(Domain {
+            pid: std::process::id(),
+            start: P::start() as usize,
+            durable: durable.to_vec(),
+            pending: HashMap::new(),
+        });
+        ACTIVE.store(true, Ordering::Release);
+        Ok(Self { phantom: PhantomData })
+    }
+
+    /// Returns the offsets of the cache lines which are not durable yet
+    pub fn unflushed_lines(&self) -> Vec<u64> {
+        let domain = domain();
+        let d = domain.as_ref().unwrap();
+        let live = unsafe {
+            std::slice::from_raw_parts(d.start as *const u8, d.durable.len())
+        };
+        live.chunks(CACHE_LINE)
+            .zip(d.durable.chunks(CACHE_LINE))
+            .enumerate()
+            .filter(|(_, (l, p))| l != p)
+            .map(|(i, _)| (i * CACHE_LINE) as u64)
+            .collect()
+    }
+
+    /// Writes the image of the pool after a crash at this point to `dest`
+    ///
+    /// The durable lines are written as they are, and the unflushed lines are
+    /// either reverted to their durable contents or kept according to `mode`.
+    /// The image is a regular pool file which can be recovered by
+    /// [`recover()`](#method.recover).
+    pub fn crash(&self, dest: &str, mode: CrashMode) -> Result<CrashReport> {
+        let (image, report) = crash_image(mode)
+            .ok_or_else(|| Error::Other("No simulation is running".to_string()))?;
+        write_snapshot(dest, &image, false)?;
+        Ok(report)
+    }
+
+    /// Opens the crash image at `path` with the recovery procedure, runs `f`
+    /// on its root object to check the invariants, and then checks the
+    /// consistency of the allocator
+    ///
+    /// Pool `P` should be closed before calling this function.
+    pub fn recover<U: PSafe + RootObj<P>, T, F: FnOnce(&U) -> T>(path: &str, f: F) -> Result<T> {
+        if P::is_open() {
+            return Err(Error::PoolOpen);
+        }
+        let res = {
+            let root = P::open::<U>(path, 0)?;
+            f(&*root)
+        };
+        let report = crate::check::<P>(path)?;
+        if report.is_ok() {
+            Ok(res)
+        } else {
+            Err(Error::Other(report.to_string()))
+        }
+    }
+}
+
+impl<P: MemPool> Drop for PmSim<P> {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::Release);
+        *domain() = None;
+    }
+}
+
+/// The exit code of a child process which crashed at the chosen point
+pub const CRASH_EXIT_CODE: i32 = 86;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static POINTS: AtomicUsize = AtomicUsize::new(0);
+static CRASH_AT: AtomicUsize = AtomicUsize::new(usize::MAX);
+static CRASH_TARGET: LazyCell<Mutex<Option<(String, CrashMode)>>> =
+    LazyCell::new(|| Mutex::new(None));
+
+/// Marks a crash point
+///
+/// It is called by [`ll::clflush`](../ll/fn.clflush.html) and
+/// [`ll::sfence`](../ll/fn.sfence.html) before they take effect. While a
+/// [`CrashHarness`](./struct.CrashHarness.html) runs a workload, it counts the
+/// points, and terminates the process at the chosen one.
+#[inline(always)]
+pub fn crash_point() {
+    if COUNTING.load(Ordering::Relaxed) {
+        let n = POINTS.fetch_add(1, Ordering::Relaxed) + 1;
+        if n == CRASH_AT.load(Ordering::Relaxed) {
+            crash_now();
+        }
+    }
+}
+
+/// Terminates the process without unwinding. If a [`PmSim`] is running, the
+/// unflushed writes are lost according to the harness mode.
+///
+/// [`PmSim`]: ./struct.PmSim.html
+fn crash_now() -> ! {
+    COUNTING.store(false, Ordering::Relaxed);
+    let target = match CRASH_TARGET.lock() {
+        Ok(g) => g.clone(),
+        Err(p) => p.into_inner().clone(),
+    };
+    if let Some((path, mode)) = target.filter(|_| is_active()) {
+        if let Some((image, _)) = crash_image(mode) {
+            // The pool file is overwritten with the crash image
+            let _ = write_snapshot(&path, &image, false);
+        }
+    }
+    unsafe { libc::_exit(CRASH_EXIT_CODE) }
+}
+
+/// The result of running a [`CrashHarness`](./struct.CrashHarness.html)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HarnessReport {
+    /// Number of the crash points of the workload
+    pub points: usize,
+
+    /// Number of the tested crash points
+    pub tested: usize,
+
+    /// The failed crash points with the description of the failures
+    pub failures: Vec<(usize, String)>,
+}
+
+impl HarnessReport {
+    /// Returns true if the consistency check passed at all tested points
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for HarnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Crash points: {} ({} tested)", self.points, self.tested)?;
+        for (point, e) in &self.failures {
+            writeln!(f, "failed at crash point {}: {}", point, e)?;
+        }
+        write!(f, "{}", if self.is_ok() { "OK" } else { "FAILED" })
+    }
+}
+
+/// A deterministic crash-point enumeration harness
+///
+/// Every call to [`clflush`] or [`sfence`] is a crash point. The harness
+/// first runs the workload in a forked child process to count its crash
+/// points. Then, for each point `i` from 1 to `N`, it restores the pool file,
+/// runs the workload in a new child which terminates right before point `i`,
+/// and runs the consistency check in the current process. The check should
+/// reopen the pool, so that it is recovered.
+///
+/// The workload should be deterministic, and it should not start threads
+/// which write to the pool, or the crash points of the runs do not match. If
+/// the workload starts a [`PmSim`] after opening the pool, the unflushed
+/// writes are also lost according to the [`mode`](#method.mode).
+///
+/// Forking a multi-threaded process only copies the calling thread, so the
+/// harness should not run while other threads hold locks that the workload
+/// needs.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::default::*;
+/// use corundum::sim::*;
+///
+/// type P = Allocator;
+///
+/// type Root = PRefCell<PVec<u32>>;
+///
+/// // The pool is created before enumerating the crash points
+/// let _ = P::open::<Root>("harness.pool", O_CF).unwrap();
+///
+/// let report = CrashHarness::new("harness.pool").run(|| {
+///     let root = P::open::<Root>("harness.pool", 0).unwrap();
+///     P::transaction(|j| {
+///         root.borrow_mut(j).extend_from_slice(&[1, 2, 3], j);
+///     }).unwrap();
+/// }, || {
+///     // The vector has either all or none of the items
+///     let root = P::open::<Root>("harness.pool", 0)?;
+///     let len = root.borrow().len();
+///     if len != 0 && len != 3 {
+///         return Err(format!("{} items", len).into());
+///     }
+///     Ok(())
+/// }).unwrap();
+///
+/// assert!(report.is_ok(), "{}", report);
+/// ```
+///
+/// [`clflush`]: ../ll/fn.clflush.html
+/// [`sfence`]: ../ll/fn.sfence.html
+/// [`PmSim`]: ./struct.PmSim.html
+pub struct CrashHarness {
+    path: String,
+    mode: CrashMode,
+    limit: Option<usize>,
+}
+
+impl CrashHarness {
+    /// Creates a harness for a workload which uses the pool file at `path`
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            mode: CrashMode::Revert,
+            limit: None,
+        }
+    }
+
+    /// Sets what happens to the unflushed writes at a crash if the workload
+    /// runs a [`PmSim`](./struct.PmSim.html). The default is
+    /// [`CrashMode::Revert`](./enum.CrashMode.html#variant.Revert).
+    pub fn mode(&mut self, mode: CrashMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Tests only the first `n` crash points
+    pub fn limit(&mut self, n: usize) -> &mut Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Puts the pool file back to its original state
+    fn restore(&self, original: &Option<Vec<u8>>) -> Result<()> {
+        match original {
+            Some(data) => write_snapshot(&self.path, data, false).map(|_| ()),
+            None => {
+                let _ = std::fs::remove_file(&self.path);
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs `workload` in a child process which crashes at point `crash_at`,
+    /// and returns its exit code and the number of the crash points it
+    /// passed
+    fn fork<W: Fn()>(&self, workload: &W, crash_at: usize) -> Result<(i32, usize)> {
+        let mut fds = [0i32; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(Error::Io(std::io::Error::last_os_error()));
+            }
+            let pid = libc::fork();
+            if pid < 0 {
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+                return Err(Error::Io(std::io::Error::last_os_error()));
+            }
+            if pid == 0 {
+                libc::close(fds[0]);
+                // Only a simulation started by the workload is used
+                ACTIVE.store(false, Ordering::Release);
+                *match CRASH_TARGET.lock() {
+                    Ok(g) => g,
+                    Err(p) => p.into_inner(),
+                } = Some((self.path.clone(), self.mode));
+                POINTS.store(0, Ordering::Relaxed);
+                CRASH_AT.store(crash_at, Ordering::Relaxed);
+                COUNTING.store(true, Ordering::Relaxed);
+                let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(workload));
+                COUNTING.store(false, Ordering::Relaxed);
+                let points = POINTS.load(Ordering::Relaxed).to_ne_bytes();
+                libc::write(fds[1], points.as_ptr() as *const libc::c_void, points.len());
+                libc::_exit(if res.is_ok() { 0 } else { 1 });
+            }
+            libc::close(fds[1]);
+            let mut points = [0u8; mem::size_of::<usize>()];
+            let n = libc::read(fds[0], points.as_mut_ptr() as *mut libc::c_void, points.len());
+            libc::close(fds[0]);
+            let mut status = 0;
+            libc::waitpid(pid, &mut status, 0);
+            let code = if libc::WIFEXITED(status) { libc::WEXITSTATUS(status) } else { -1 };
+            let points = if n == points.len() as isize { usize::from_ne_bytes(points) } else { 0 };
+            Ok((code, points))
+        }
+    }
+
+    /// Enumerates the crash points of `workload`, and runs `check` after
+    /// crashing at each of them
+    ///
+    /// It fails if the workload does not complete successfully without a
+    /// crash. The pool file is left as it is after the last crash.
+    pub fn run<W: Fn(), C: Fn() -> Result<()>>(&self, workload: W, check: C) -> Result<HarnessReport> {
+        let original = std::fs::read(&self.path).ok();
+        let (code, points) = self.fork(&workload, usize::MAX)?;
+        if code != 0 {
+            return Err(Error::Other("The workload failed without a crash".to_string()));
+        }
+        let mut report = HarnessReport {
+            points,
+            ..Default::default()
+        };
+        let n = self.limit.map_or(points, |l| l.min(points));
+        for point in 1..=n {
+            self.restore(&original)?;
+            let (code, _) = self.fork(&workload, point)?;
+            report.tested += 1;
+            let res = if code == CRASH_EXIT_CODE {
+                match std::panic::catch_unwind(std::panic::AssertUnwindSafe(&check)) {
+                    Ok(res) => res,
+                    Err(_) => Err(Error::Other("The consistency check panicked".to_string())),
+                }
+            } else if code == 0 {
+                Err(Error::Other("The workload did not reach the crash point".to_string()))
+            } else {
+                Err(Error::Other(format!("The workload failed before the crash point (exit code {})", code)))
+            };
+            if let Err(e) = res {
+                report.failures.push((point, e.to_string()));
+            }
+        }
+        Ok(report)
+    }
+}
@@ -27,6 +27,13 @@ impl<K: PartialEq + Hash + PSafe, V: PSafe, P: MemPool> RootObj<P> for HashMap<K
     }
 }
 
+impl<K: PSafe + Trace<P>, V: PSafe + Trace<P>, P: MemPool> Trace<P> for HashMap<K, V, P> {
+    fn trace(&self, tracer: &mut Tracer<P>) {
+        self.buckets.trace(tracer);
+        self.values.trace(tracer);
+    }
+}
+
 impl<K: PSafe, V: PSafe, P: MemPool> HashMap<K, V, P> {
     pub fn foreach<F: FnMut(&K, &V) -> ()>(&self, mut f: F) {
         for i in 0..BUCKETS_MAX {
@@ -1,10 +1,10 @@
 use crate::result::Result;
 use crate::cell::LazyCell;
-use crate::{TxInSafe, TxOutSafe, utils};
+use crate::{Error, TxInSafe, TxOutSafe, utils};
 use std::collections::hash_map::HashMap;
 use std::fmt::{self, Debug};
 use std::fs::OpenOptions;
-use std::io::{self, Error, Write};
+use std::io::{self, Write};
 use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::path::Path;
 use std::sync::Mutex;
@@ -90,10 +90,9 @@ fn new_chaperon(filename: &str) -> Result<*mut Chaperon> {
     };
     let tid = thread::current().id();
     if clist.contains_key(&tid) {
-        return Err("Another chaperoned transaction is open".to_string());
+        return Err(Error::PoolBusy);
     }
-    let c = Chaperon::new(filename.to_string())
-        .expect(&format!("could not create chaperon file `{}`", filename));
+    let c = Chaperon::new(filename.to_string())?;
     clist.entry(tid).or_insert(SyncBox::new(c));
     Ok(clist.get(&tid).unwrap().get())
 }
@@ -121,7 +120,7 @@ fn current_chaperon() -> Option<*mut Chaperon> {
 }
 
 impl Chaperon {
-    pub(crate) fn new(filename: String) -> io::Result<&'static mut Self> {
+    pub(crate) fn new(filename: String) -> Result<&'static mut Self> {
         let mut file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -150,7 +149,7 @@ impl Chaperon {
         for i in 0..4096.min(filename.len()) {
             a.filename[i] = bytes[i];
         }
-        file.write_all(a.as_bytes()).unwrap();
+        file.write_all(a.as_bytes())?;
         unsafe { Self::load(&filename) }
     }
 
@@ -165,15 +164,18 @@ impl Chaperon {
     }
 
     /// Loads a chaperon file
-    pub unsafe fn load(filename: &str) -> io::Result<&'static mut Self> {
+    pub unsafe fn load(filename: &str) -> Result<&'static mut Self> {
         if Path::new(&filename).exists() {
             let file = OpenOptions::new().read(true).write(true).open(&filename)?;
-            let mut mmap = memmap::MmapOptions::new().map_mut(&file).unwrap();
-            let slf = Self::deref(mmap.get_mut(0).unwrap());
+            if file.metadata()?.len() < mem::size_of::<Self>() as u64 {
+                return Err(Error::InvalidMagic);
+            }
+            let mut mmap = memmap::MmapOptions::new().map_mut(&file)?;
+            let slf = Self::deref(mmap.as_mut_ptr());
             mem::forget(ptr::replace(&mut slf.vdata, Some(VData::new(mmap))));
             Ok(slf)
         } else {
-            Err(Error::last_os_error())
+            Err(Error::Io(io::ErrorKind::NotFound.into()))
         }
     }
 
@@ -363,15 +365,17 @@ impl Chaperon {
         T: panic::UnwindSafe + TxOutSafe,
     {
         let chaperon = unsafe { &mut *new_chaperon(filename)? };
-        let res = panic::catch_unwind(|| body());
-        if let Ok(res) = res {
-            chaperon.execute_delayed_commits();
-            drop_chaperon();
-            Ok(res)
-        } else {
-            chaperon.execute_delayed_rollbacks();
-            drop_chaperon();
-            Err("Unsuccessful chaperoned transaction".to_string())
+        match panic::catch_unwind(|| body()) {
+            Ok(res) => {
+                chaperon.execute_delayed_commits();
+                drop_chaperon();
+                Ok(res)
+            }
+            Err(e) => {
+                chaperon.execute_delayed_rollbacks();
+                drop_chaperon();
+                Err(Error::aborted(e))
+            }
         }
     }
 }
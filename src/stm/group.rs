@@ -0,0 +1,102 @@
+//! Group commit of concurrent transactions
+use crate::alloc::MemPool;
+use crate::ll::sfence;
+use crate::stm::Journal;
+use crate::*;
+use std::mem;
+use std::sync::{Condvar, Mutex};
+
+struct GroupState {
+    /// The journals waiting for the open group to be committed
+    pending: Vec<u64>,
+
+    /// True if a leader is committing a group
+    leading: bool,
+
+    /// The sequence number of the open group
+    open: u64,
+
+    /// The sequence number of the last committed group
+    done: u64,
+}
+
+/// A coordinator for committing the transactions of many threads together
+///
+/// When a pool is opened with [`PoolOptions::group_commit`] set, every
+/// transaction prepares its journal and joins the open group. The first
+/// waiting thread becomes the leader: it takes all pending journals, flushes
+/// their data, and issues a single fence. Then, it sets `JOURNAL_COMMITTED`
+/// on all of them, and issues another fence for all. Meanwhile, the other
+/// transactions form the next group. A transaction returns only after its
+/// group is durable.
+///
+/// The leader issues all flushes of the group itself, so that its fences
+/// order them. The deallocations postponed to commit are performed by the
+/// owner threads after the group is durable. If a crash occurs in between,
+/// the recovery procedure finds the journal committed and performs its
+/// deallocations, rather than rolling it back.
+///
+/// [`PoolOptions::group_commit`]: ../alloc/struct.PoolOptions.html#method.group_commit
+pub struct CommitGroup {
+    state: Mutex<GroupState>,
+    cond: Condvar,
+}
+
+impl Default for CommitGroup {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(GroupState {
+                pending: vec![],
+                leading: false,
+                open: 1,
+                done: 0,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+}
+
+impl CommitGroup {
+    /// Adds a prepared `journal` to the open group, and blocks until the
+    /// group is committed
+    pub(crate) unsafe fn commit<A: MemPool>(&self, journal: &mut Journal<A>) {
+        let mut state = match self.state.lock() {
+            Ok(g) => g,
+            Err(p) => p.into_inner()
+        };
+        state.pending.push(journal as *mut Journal<A> as u64);
+        let group = state.open;
+        while state.done < group {
+            if state.leading {
+                state = match self.cond.wait(state) {
+                    Ok(g) => g,
+                    Err(p) => p.into_inner()
+                };
+            } else {
+                state.leading = true;
+                let journals = mem::take(&mut state.pending);
+                let seq = state.open;
+                state.open += 1;
+                drop(state);
+
+                log!(A, White, "GROUP", "SEQ: {}, SIZE: {}", seq, journals.len());
+                for j in &journals {
+                    (*(*j as *mut Journal<A>)).flush_data();
+                }
+                sfence();
+                for j in &journals {
+                    (*(*j as *mut Journal<A>)).mark_committed();
+                }
+                sfence();
+
+                state = match self.state.lock() {
+                    Ok(g) => g,
+                    Err(p) => p.into_inner()
+                };
+                state.leading = false;
+                state.done = seq;
+                self.cond.notify_all();
+            }
+        }
+    }
+}
@@ -4,8 +4,11 @@ use crate::ll::*;
 use crate::ptr::Ptr;
 use crate::stm::*;
 use crate::*;
-use std::collections::HashMap;
+use crate::result::Result;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Debug, Formatter};
+use std::panic::UnwindSafe;
 
 #[cfg(feature = "check_double_free")]
 use std::collections::HashSet;
@@ -22,6 +25,13 @@ use crate::stm::vspd::Scratchpad;
 /// Determines that the changes are committed
 pub const JOURNAL_COMMITTED: u64 = 0x0000_0001;
 
+/// Determines that the redo logs are durable, and the transaction should be
+/// completed on recovery
+pub const JOURNAL_REDO_LOGGED: u64 = 0x0000_0002;
+
+/// Determines that the redo logs are applied to their original locations
+pub const JOURNAL_REDO_APPLIED: u64 = 0x0000_0004;
+
 /// A Journal object to be used for writing logs onto
 ///
 /// Each transaction, hence each thread, may have only one journal for every
@@ -45,19 +55,36 @@ pub const JOURNAL_COMMITTED: u64 = 0x0000_0001;
 /// ```
 /// 
 /// A `Journal` consists of one or more `page`s. A `page` provides a fixed
-/// number of log slots which is specified by `PAGE_SIZE` (64). This helps
-/// performance as the logs are pre-allocated. When the number of logs in a page
-/// exceeds 64, `Journal` object atomically allocate a new page for another 64
-/// pages before running the operations.
+/// number of log slots which is specified by
+/// [`PoolOptions::journal_page_slots`] (128 by default). This helps
+/// performance as the logs are pre-allocated. When a page is full, `Journal`
+/// object atomically allocates a new page before running the operations.
 ///
 /// `Journal`s by default are deallocated after the transaction or recovery.
-/// However, it is possible to pin journals in the pool if they are used
-/// frequently by enabling "pin_journals" feature.
+/// However, it is possible to keep them with a bounded number of cached pages
+/// for the next transactions of the thread using [`PoolOptions::journal_cache`],
+/// or to pin all journals in the pool by enabling "pin_journals" feature.
+/// 
+/// [`PoolOptions::journal_page_slots`]: ../alloc/struct.PoolOptions.html#method.journal_page_slots
+/// [`PoolOptions::journal_cache`]: ../alloc/struct.PoolOptions.html#method.journal_cache
+/// 
+/// If the pool is opened with [`PoolOptions::redo_log`] set, the journal
+/// keeps redo logs for [`PCell`] and [`PRefCell`] updates instead of undo
+/// logs. The new values are written to drafts, and they are copied to their
+/// original locations on commit. The commit point of such a transaction is
+/// when all drafts and the undo-logged data of the transaction are durable
+/// and `JOURNAL_REDO_LOGGED` is set; a crash after
+/// that completes the transaction on recovery instead of rolling it back.
+/// 
+/// [`PoolOptions::redo_log`]: ../alloc/struct.PoolOptions.html#method.redo_log
+/// [`PCell`]: ../cell/struct.PCell.html
+/// [`PRefCell`]: ../cell/struct.PRefCell.html
 /// 
 /// [`transaction()`]: ./fn.transaction.html
 /// 
 pub struct Journal<A: MemPool> {
     pages: Ptr<Page<A>, A>,
+    free: Ptr<Page<A>, A>,
 
     #[cfg(feature = "pin_journals")]
     current: Ptr<Page<A>, A>,
@@ -82,45 +109,183 @@ impl<A: MemPool> !LooseTxInUnsafe for Journal<A> {}
 impl<A: MemPool> !std::panic::RefUnwindSafe for Journal<A> {}
 impl<A: MemPool> !std::panic::UnwindSafe for Journal<A> {}
 
-#[derive(Clone, Copy)]
+/// A position in a journal to which the transaction can be rolled back
+///
+/// It keeps the number of logs in each page, and the contents of the redo-log
+/// drafts, which are updated in place by the later writes. It is used by
+/// [`Journal::savepoint()`] and nested transactions.
+///
+/// [`Journal::savepoint()`]: ./struct.Journal.html#method.savepoint
+pub struct Savepoint {
+    lens: HashMap<u64, usize>,
+    drafts: Vec<(u64, Vec<u8>)>,
+    callbacks: (usize, usize),
+}
+
+impl Savepoint {
+    /// Returns the number of logs of page `off` at the savepoint
+    fn len(&self, off: u64) -> usize {
+        self.lens.get(&off).cloned().unwrap_or(0)
+    }
+}
+
+/// The volatile closures to run when a transaction finishes
+///
+/// See [`Journal::on_commit()`] and [`Journal::on_abort()`].
+///
+/// [`Journal::on_commit()`]: ./struct.Journal.html#method.on_commit
+/// [`Journal::on_abort()`]: ./struct.Journal.html#method.on_abort
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    commit: Vec<Box<dyn FnOnce()>>,
+    abort: Vec<Box<dyn FnOnce()>>,
+    committed: bool,
+}
+
+impl Callbacks {
+    /// Runs the commit callbacks in the order of registration if the
+    /// transaction is committed; otherwise, runs the abort callbacks in the
+    /// reverse order
+    pub(crate) fn run(self) {
+        if self.committed {
+            for f in self.commit {
+                f();
+            }
+        } else {
+            for f in self.abort.into_iter().rev() {
+                f();
+            }
+        }
+    }
+}
+
+/// The number of transactions after which the unused cached pages are trimmed
+const CACHE_IDLE_TRANSACTIONS: usize = 64;
+
+thread_local! {
+    /// The number of pages used by the recent transactions of the cached
+    /// journals of this thread as `(peak, age)` pairs, mapped from the
+    /// address of the journals
+    static CACHE_USAGE: RefCell<HashMap<u64, (usize, usize)>> = RefCell::new(HashMap::new());
+}
+
+thread_local! {
+    /// The callbacks of the running transactions of this thread, mapped from
+    /// the address of their journals
+    static CALLBACKS: RefCell<HashMap<u64, Callbacks>> = RefCell::new(HashMap::new());
+}
+
+thread_local! {
+    /// The ranges of data which are undo-logged by the running transactions
+    /// of this thread, mapped from the address of their journals. The ranges
+    /// are kept as disjoint `start -> end` pairs, and the adjacent ones are
+    /// merged.
+    static LOGGED: RefCell<HashMap<u64, BTreeMap<u64, u64>>> = RefCell::new(HashMap::new());
+}
+
+/// A journal page with `cap` log slots which follow the header
+#[repr(C)]
 struct Page<A: MemPool> {
     len: usize,
     head: usize,
+    cap: usize,
     next: Ptr<Page<A>, A>,
-    logs: [Log<A>; PAGE_LOG_SLOTS],
+    slots: [Log<A>; 0],
 }
 
 impl<A: MemPool> Page<A> {
+    /// Returns the size of a page with `cap` log slots in bytes
+    #[inline]
+    fn size_of(cap: usize) -> usize {
+        std::mem::size_of::<Self>() + cap * std::mem::size_of::<Log<A>>()
+    }
+
+    /// Returns the size of the page in bytes
+    #[inline]
+    fn size(&self) -> usize {
+        Self::size_of(self.cap)
+    }
+
+    #[inline]
+    fn logs(&self) -> &[Log<A>] {
+        unsafe { std::slice::from_raw_parts(self.slots.as_ptr(), self.cap) }
+    }
+
+    #[inline]
+    fn logs_mut(&mut self) -> &mut [Log<A>] {
+        unsafe { std::slice::from_raw_parts_mut(self.slots.as_mut_ptr(), self.cap) }
+    }
+
     #[inline]
     /// Writes a new log to the journal
     fn write(&mut self, log: LogEnum, notifier: Notifier<A>) -> Ptr<Log<A>, A> {
+        let len = self.len;
+        let slot = &mut self.logs_mut()[len];
         #[cfg(not(feature = "use_ntstore"))] {
-            self.logs[self.len] = Log::new(log, notifier);
+            *slot = Log::new(log, notifier);
         }
         #[cfg(feature = "use_ntstore")] unsafe {
-            std::intrinsics::nontemporal_store(&mut self.logs[self.len], Log::new(log, notifier));
+            std::intrinsics::nontemporal_store(slot as *mut Log<A>, Log::new(log, notifier));
         }
-        persist_with_log::<_,A>(&self.logs[self.len], std::mem::size_of::<Log<A>>(), false);
+        persist_with_log::<_,A>(slot, std::mem::size_of::<Log<A>>(), false);
 
-        let log = unsafe { Ptr::new_unchecked(&self.logs[self.len]) };
+        // The log is visible to the recovery procedure once `len` is durable.
+        // The caller fences once before it modifies the logged data. If `len`
+        // becomes durable before the slot, the slot is still empty (see
+        // `reset()`).
+        let log = unsafe { Ptr::new_unchecked(slot) };
         self.len += 1;
+        persist_obj(&self.len, false);
         log
     }
 
+    /// Empties the page for reuse
+    ///
+    /// The used slots are reset before `len`, so that the slots after `len`
+    /// are always empty logs.
+    unsafe fn reset(&mut self) {
+        let len = self.len;
+        for log in &mut self.logs_mut()[..len] {
+            std::ptr::write(log, Default::default());
+        }
+        if len > 0 {
+            persist_with_log::<_,A>(self.logs().as_ptr(), len * std::mem::size_of::<Log<A>>(), true);
+        }
+        self.len = 0;
+        self.head = 0;
+        persist_obj_with_log::<_,A>(&self.len, false);
+    }
+
     #[inline]
     fn is_full(&self) -> bool {
-        self.len == PAGE_LOG_SLOTS
+        self.len == self.cap
     }
 
     unsafe fn notify(&mut self) {
         for i in 0..self.len {
-            self.logs[i].notify(0);
+            self.logs_mut()[i].notify(0);
+        }
+    }
+
+    fn data_ranges(&self, ranges: &mut Vec<(u64, u64)>) {
+        for log in &self.logs()[..self.len] {
+            if let Some((off, len)) = log.data_range() {
+                ranges.push((off, off + len as u64));
+            }
+        }
+    }
+
+    fn persist_redo(&self) -> bool {
+        let mut redo = false;
+        for i in 0..self.len {
+            redo |= self.logs()[i].persist_redo();
         }
+        redo
     }
 
-    unsafe fn commit_data(&mut self) {
+    unsafe fn apply_redo(&mut self) {
         for i in 0..self.len {
-            self.logs[i].commit_data();
+            self.logs_mut()[i].apply_redo();
         }
     }
 
@@ -129,7 +294,7 @@ impl<A: MemPool> Page<A> {
         check_double_free: &mut HashSet<u64>
     ) {
         for i in 0..self.len {
-            self.logs[i].commit_dealloc(
+            self.logs_mut()[i].commit_dealloc(
                 #[cfg(feature = "check_double_free")]
                 check_double_free
             );
@@ -137,8 +302,9 @@ impl<A: MemPool> Page<A> {
     }
 
     unsafe fn rollback(&mut self) {
-        for i in 0..self.len {
-            self.logs[self.len - i - 1].rollback();
+        let len = self.len;
+        for log in self.logs_mut()[..len].iter_mut().rev() {
+            log.rollback();
         }
     }
 
@@ -147,7 +313,7 @@ impl<A: MemPool> Page<A> {
         check_double_free: &mut HashSet<u64>
     ) {
         for i in 0..self.len {
-            self.logs[i].rollback_drop_on_abort(
+            self.logs_mut()[i].rollback_drop_on_abort(
                 #[cfg(feature = "check_double_free")]
                 check_double_free
             );
@@ -158,18 +324,36 @@ impl<A: MemPool> Page<A> {
         #[cfg(feature = "check_double_free")]
         check_double_free: &mut HashSet<u64>
     ) {
-        for i in 0..self.len {
-            self.logs[self.len - i - 1].recover(rollback, 
+        let len = self.len;
+        for log in self.logs_mut()[..len].iter_mut().rev() {
+            log.recover(rollback, 
                 #[cfg(feature = "check_double_free")]
                 check_double_free
             );
         }
     }
 
+    /// Prepares the logs for a savepoint. The owners of the undo-logged data
+    /// are notified to take new logs, and the drafts are copied.
+    unsafe fn mark(&mut self, drafts: &mut Vec<(u64, Vec<u8>)>) {
+        for i in 0..self.len {
+            match self.logs_mut()[i].inner() {
+                LogEnum::DataLog(_, _, _) => self.logs_mut()[i].notify(0),
+                LogEnum::RedoLog(dst, draft, len) if dst != u64::MAX && draft != u64::MAX => {
+                    let data = A::deref_slice_unchecked::<u8>(draft, len);
+                    drafts.push((draft, data.to_vec()));
+                }
+                _ => {}
+            }
+        }
+    }
+
     unsafe fn ignore(&mut self) {
         self.len = 0;
         self.head = 0;
-        self.logs = [Default::default(); PAGE_LOG_SLOTS];
+        for log in self.logs_mut() {
+            *log = Default::default();
+        }
     }
 
     unsafe fn clear(&mut self, 
@@ -177,7 +361,7 @@ impl<A: MemPool> Page<A> {
         check_double_free: &mut HashSet<u64>
     ) {
         for i in self.head..self.len {
-            self.logs[i].clear(
+            self.logs_mut()[i].clear(
                 #[cfg(feature = "check_double_free")]
                 check_double_free
             );
@@ -185,14 +369,11 @@ impl<A: MemPool> Page<A> {
         }
 
         #[cfg(feature = "pin_journals")]
-        {
-            self.head = 0;
-            self.len = 0;
-        }
+        self.reset();
     }
 
     fn into_iter(&self) -> std::vec::IntoIter<Log<A>> {
-        Vec::from(self.logs).into_iter()
+        Vec::from(self.logs()).into_iter()
     }
 }
 
@@ -200,7 +381,7 @@ impl<A: MemPool> Debug for Page<A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "LOGS:")?;
         for i in 0..self.len {
-            writeln!(f, "    {:?}", self.logs[i])?;
+            writeln!(f, "    {:?}", self.logs()[i])?;
         }
         Ok(())
     }
@@ -211,6 +392,7 @@ impl<A: MemPool> Journal<A> {
     pub unsafe fn new(gen: u32) -> Self {
         Self {
             pages: Ptr::dangling(),
+            free: Ptr::dangling(),
 
             #[cfg(feature = "pin_journals")]
             current: Ptr::dangling(),
@@ -293,13 +475,37 @@ impl<A: MemPool> Journal<A> {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<A>::NewPage(std::time::Instant::now());
         unsafe {
-            let page = Page::<A> {
-                len: 0,
-                head: 0,
-                next: self.pages,
-                logs: [Default::default(); PAGE_LOG_SLOTS]
+            let (off, z) = if let Some(page) = self.free.clone().as_option() {
+                // Reuses a cached page
+                let off = page.off();
+                let z = A::zone(off);
+                A::prepare(z);
+                A::log64(A::off_unchecked(self.free.off_ref()), page.next.off(), z);
+                A::log64(A::off_unchecked(page.next.off_ref()), self.pages.off(), z);
+                (off, z)
+            } else {
+                let cap = A::journal_page_slots();
+                let size = Page::<A>::size_of(cap);
+                let (raw, off, len, z) = A::pre_alloc_typed(size,
+                    std::mem::align_of::<Page<A>>(), std::any::type_name::<Page<A>>());
+                if raw.is_null() {
+                    std::panic::panic_any(crate::Error::OutOfMemory);
+                }
+                A::drop_on_failure(off, len, z);
+                let page = &mut *(raw as *mut Page<A>);
+                std::ptr::write(page, Page::<A> {
+                    len: 0,
+                    head: 0,
+                    cap,
+                    next: self.pages,
+                    slots: []
+                });
+                for log in page.logs_mut() {
+                    std::ptr::write(log, Default::default());
+                }
+                persist_obj_with_log::<_,A>(page, false);
+                (off, z)
             };
-            let (_, off, _, z) = A::atomic_new(page);
             A::log64(A::off_unchecked(self.pages.off_ref()), off, z);
             
             #[cfg(feature = "pin_journals")] {
@@ -331,7 +537,7 @@ impl<A: MemPool> Journal<A> {
     pub unsafe fn drop_pages(&mut self) {
         while let Some(page) = self.pages.clone().as_option() {
             let nxt = page.next;
-            let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, std::mem::size_of::<Page<A>>());
+            let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, page.size());
             A::log64(A::off_unchecked(self.pages.off_ref()), nxt.off(), z);
             A::perform(z);
         }
@@ -339,6 +545,92 @@ impl<A: MemPool> Journal<A> {
         self.pages = Ptr::dangling();
     }
 
+    /// Returns the number of cached pages
+    fn cached_pages(&self) -> usize {
+        let mut cnt = 0;
+        let mut curr = self.free;
+        while let Some(page) = curr.as_option() {
+            cnt += 1;
+            curr = page.next;
+        }
+        cnt
+    }
+
+    /// Deallocates the cached pages in excess of `keep`
+    unsafe fn trim_cache(&mut self, keep: usize) {
+        let mut cnt = self.cached_pages();
+        while cnt > keep {
+            if let Some(page) = self.free.clone().as_option() {
+                let nxt = page.next;
+                let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, page.size());
+                A::log64(A::off_unchecked(self.free.off_ref()), nxt.off(), z);
+                A::perform(z);
+            }
+            cnt -= 1;
+        }
+    }
+
+    /// Deallocates the cached pages
+    ///
+    /// See [`PoolOptions::journal_cache`].
+    ///
+    /// [`PoolOptions::journal_cache`]: ../alloc/struct.PoolOptions.html#method.journal_cache
+    pub unsafe fn drop_cache(&mut self) {
+        self.trim_cache(0);
+        let key = self as *const Self as u64;
+        CACHE_USAGE.with(|usage| usage.borrow_mut().remove(&key));
+    }
+
+    /// Returns true if the journal is kept for the next transactions of its
+    /// thread, i.e., the page cache is enabled and it belongs to the current
+    /// thread
+    unsafe fn cached(&self) -> bool {
+        if A::journal_cache() == 0 {
+            return false;
+        }
+        let me = A::off_unchecked(self);
+        let tid = std::thread::current().id();
+        A::journals(|journals| journals.get(&tid).map_or(false, |(off, _)| *off == me))
+    }
+
+    /// Returns the number of pages to keep in the cache after a transaction
+    /// which used `used` pages
+    ///
+    /// It is the largest number of pages used by the recent transactions,
+    /// limited to the cache size. The extra pages are trimmed after the thread
+    /// runs `CACHE_IDLE_TRANSACTIONS` transactions without using them.
+    fn cache_limit(&self, used: usize) -> usize {
+        let key = self as *const Self as u64;
+        let peak = CACHE_USAGE.with(|usage| {
+            let mut usage = usage.borrow_mut();
+            let (peak, age) = usage.entry(key).or_insert((0, 0));
+            if used >= *peak {
+                *peak = used;
+                *age = 0;
+            } else {
+                *age += 1;
+                if *age >= CACHE_IDLE_TRANSACTIONS {
+                    *peak = used;
+                    *age = 0;
+                }
+            }
+            *peak
+        });
+        peak.min(A::journal_cache())
+    }
+
+    /// Returns true if the updates of this journal should be redo-logged
+    ///
+    /// Redo logging is enabled per pool using [`PoolOptions::redo_log`]. It is
+    /// not used in chaperoned sessions, as a committed session may still be
+    /// rolled back on recovery.
+    ///
+    /// [`PoolOptions::redo_log`]: ../alloc/struct.PoolOptions.html#method.redo_log
+    #[inline]
+    pub fn redo_logging(&self) -> bool {
+        A::redo_logging() && self.sec_id == 0
+    }
+
     /// Writes a new log to the journal
     #[cfg(any(feature = "use_pspd", feature = "use_vspd"))]
     #[inline]
@@ -395,9 +687,14 @@ impl<A: MemPool> Journal<A> {
 
         let mut res = format!("Committed: {}\n", 
             if self.is_committed() { "Yes" } else { "No" });
+        if self.is_set(JOURNAL_REDO_LOGGED) {
+            res += &format!("Redo logs: {}\n",
+                if self.is_set(JOURNAL_REDO_APPLIED) { "Applied" } else { "Durable" });
+        }
         res += &format!("Chaperoned session id: {}\n", self.sec_id);
         res += &format!("Chaperone file: {}\n", String::from_utf8(self.chaperon.to_vec()).unwrap_or("".to_string()));
         res += &format!("Number of pages: {}\n", i-1);
+        res += &format!("Number of cached pages: {}\n", self.cached_pages());
 
         #[cfg(feature = "pin_journals")] {
             res += &format!("current page at offset {:x} (index = {})", self.current.off(), _cidx);
@@ -420,24 +717,158 @@ impl<A: MemPool> Journal<A> {
         res
     }
 
+    /// Checks the integrity of the pages and the log entries of the journal
+    ///
+    /// It returns a description of every page or log entry which is out of
+    /// the pool, and of cyclic page links.
+    pub fn check(&self) -> std::vec::Vec<String> {
+        let valid = |off: u64, len: usize| {
+            len == 0 || off.checked_add(len as u64)
+                .map_or(false, |end| off < A::size() as u64 && end <= A::size() as u64)
+        };
+        let mut errors = vec![];
+        let mut pages = std::collections::HashSet::new();
+        for &head in &[self.pages, self.free] {
+            let mut curr = head;
+            while !curr.is_dangling() {
+                let off = curr.off();
+                if !valid(off, std::mem::size_of::<Page<A>>()) ||
+                    !valid(off, curr.as_ref().size()) {
+                    errors.push(format!("page at offset 0x{:x} is out of the pool", off));
+                    break;
+                }
+                if !pages.insert(off) {
+                    errors.push(format!("cyclic link to page at offset 0x{:x}", off));
+                    break;
+                }
+                let page = curr.as_ref();
+                if page.len > page.cap || page.head > page.len {
+                    errors.push(format!("page at offset 0x{:x} has invalid length {} (head = {})",
+                        off, page.len, page.head));
+                } else {
+                    for log in &page.logs()[..page.len] {
+                        for (off, len) in log.ranges() {
+                            if !valid(off, len) {
+                                errors.push(format!("log {:?} refers to the invalid range 0x{:x}..0x{:x}",
+                                    log, off, off.saturating_add(len as u64)));
+                            }
+                        }
+                    }
+                }
+                curr = page.next;
+            }
+        }
+        errors
+    }
+
+    /// Returns the `(offset, len)` pairs of the pages allocated for the journal
+    pub fn page_blocks(&self) -> std::vec::Vec<(u64, usize)> {
+        let mut blocks = vec![];
+        for &head in &[self.pages, self.free] {
+            let mut curr = head;
+            while !curr.is_dangling() {
+                blocks.push((curr.off(), curr.as_ref().size()));
+                curr = curr.as_ref().next;
+            }
+        }
+        blocks
+    }
+
     /// Commits all logs in the journal
     pub unsafe fn commit(&mut self, 
         #[cfg(feature = "check_double_free")]
         check_double_free: &mut HashSet<u64>
     ) {
+        self.prepare_commit();
+        self.flush_data();
+        sfence();
+        self.set(JOURNAL_COMMITTED);
+
+        // The deallocations are not undone on rollback, so they happen after
+        // the commit point. If it crashes in between, the recovery procedure
+        // performs them again.
+        self.commit_dealloc(
+            #[cfg(feature = "check_double_free")]
+            check_double_free
+        );
+    }
+
+    /// Commits the scratchpad and the redo logs, and notifies the owners of
+    /// the data logs
+    pub(crate) unsafe fn prepare_commit(&mut self) {
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
             self.spd.commit();
         }
+        let mut redo = false;
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
-            page.notify();
+            redo |= page.persist_redo();
             curr = page.next;
         }
+        if redo {
+            // The commit point: the transaction is completed on recovery
+            // once the drafts are durable and the flag is set. The undo-logged
+            // data of the transaction is not rolled back after that point, so
+            // it should be durable too.
+            self.flush_data();
+            sfence();
+            self.set(JOURNAL_REDO_LOGGED);
+            self.apply_redo();
+        }
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
-            page.commit_data();
+            page.notify();
             curr = page.next;
         }
+    }
+
+    /// Flushes the logged data without a fence
+    ///
+    /// The overlapping ranges, and the ones sharing a cache line, are merged
+    /// so that every cache line is flushed once. The data of a redo-logged
+    /// journal is flushed once, before its commit point.
+    pub(crate) unsafe fn flush_data(&mut self) {
+        #[cfg(all(not(feature = "no_flush_updates"), not(feature = "replace_with_log")))]
+        if !self.is_set(JOURNAL_REDO_LOGGED) {
+            #[cfg(feature = "stat_perf")]
+            let _perf = crate::stat::Measure::<A>::CommitLog(std::time::Instant::now());
+
+            let mut ranges = vec![];
+            let mut curr = self.pages;
+            while let Some(page) = curr.as_option() {
+                page.data_ranges(&mut ranges);
+                curr = page.next;
+            }
+            ranges.sort_unstable();
+
+            let line = crate::sim::CACHE_LINE as u64;
+            let mut spans: Vec<(u64, u64)> = vec![];
+            for (start, end) in ranges {
+                match spans.last_mut() {
+                    Some(last) if start <= (last.1 + line - 1) / line * line => {
+                        last.1 = last.1.max(end);
+                    }
+                    _ => spans.push((start, end)),
+                }
+            }
+
+            for (start, end) in spans {
+                persist_with_log::<u8,A>(A::get_mut_unchecked(start), (end - start) as usize, false);
+            }
+        }
+    }
+
+    /// Sets `JOURNAL_COMMITTED` and flushes it without a fence
+    pub(crate) unsafe fn mark_committed(&mut self) {
+        self.flags |= JOURNAL_COMMITTED;
+        persist_obj_with_log::<_,A>(&self.flags, false);
+    }
+
+    /// Performs the deallocations postponed to commit
+    pub(crate) unsafe fn commit_dealloc(&mut self, 
+        #[cfg(feature = "check_double_free")]
+        check_double_free: &mut HashSet<u64>
+    ) {
         let mut curr = self.pages;
         while let Some(page) = curr.as_option() {
             page.commit_dealloc(
@@ -446,8 +877,351 @@ impl<A: MemPool> Journal<A> {
             );
             curr = page.next;
         }
+    }
+
+    /// Applies the redo logs to their original locations, and sets
+    /// `JOURNAL_REDO_APPLIED` so that they are not applied again after the
+    /// targets are deallocated
+    unsafe fn apply_redo(&mut self) {
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            page.apply_redo();
+            curr = page.next;
+        }
         sfence();
-        self.set(JOURNAL_COMMITTED);
+        self.set(JOURNAL_REDO_APPLIED);
+    }
+
+    /// Returns a savepoint at the current position of the journal
+    ///
+    /// Data which is already logged is logged again when it is updated after
+    /// the savepoint, so that it can be restored by [`rollback_to()`].
+    ///
+    /// [`rollback_to()`]: #method.rollback_to
+    pub(crate) unsafe fn mark(&mut self) -> Savepoint {
+        let mut lens = HashMap::new();
+        let mut drafts = vec![];
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            lens.insert(page.off(), page.len);
+            page.mark(&mut drafts);
+            curr = page.next;
+        }
+        self.uncover();
+        let key = self as *const Self as u64;
+        let callbacks = CALLBACKS.with(|cbs| {
+            cbs.borrow().get(&key).map_or((0, 0), |c| (c.commit.len(), c.abort.len()))
+        });
+        Savepoint { lens, drafts, callbacks }
+    }
+
+    /// Reverts the changes made after savepoint `sp`, and discards their logs
+    ///
+    /// The allocations made after the savepoint are reclaimed using their
+    /// `DropOnFailure` logs, and the ones dropped while unwinding are
+    /// reclaimed using their `DropOnAbort` logs. The mutexes locked after the
+    /// savepoint are unlocked. A crash in the middle rolls back the whole
+    /// transaction.
+    pub(crate) unsafe fn rollback_to(&mut self, sp: &Savepoint, 
+        #[cfg(feature = "check_double_free")]
+        check_double_free: &mut HashSet<u64>
+    ) {
+        let mut allocs = std::collections::HashSet::new();
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            let (from, len) = (sp.len(page.off()), page.len);
+            for log in &mut page.logs_mut()[from..len] {
+                log.notify(0);
+                if let LogEnum::DropOnFailure(off, _) = log.inner() {
+                    if off != u64::MAX {
+                        allocs.insert(off);
+                    }
+                }
+            }
+            curr = page.next;
+        }
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            let (from, len) = (sp.len(page.off()), page.len);
+            for log in &mut page.logs_mut()[from..len] {
+                log.cancel_drop(&allocs);
+            }
+            curr = page.next;
+        }
+        sfence();
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            let (from, len) = (sp.len(page.off()), page.len);
+            for log in page.logs_mut()[from..len].iter_mut().rev() {
+                log.rollback_partial(
+                    #[cfg(feature = "check_double_free")]
+                    check_double_free
+                );
+            }
+            curr = page.next;
+        }
+        for (off, data) in &sp.drafts {
+            let draft = A::get_mut_unchecked::<u8>(*off);
+            std::ptr::copy_nonoverlapping(data.as_ptr(), draft, data.len());
+        }
+        sfence();
+        let mut curr = self.pages;
+        while let Some(page) = curr.as_option() {
+            let from = sp.len(page.off());
+            let len = page.len;
+            for log in &mut page.logs_mut()[from..len] {
+                log.clear(
+                    #[cfg(feature = "check_double_free")]
+                    check_double_free
+                );
+            }
+            page.len = from;
+            persist_obj_with_log::<_,A>(&page.len, false);
+            curr = page.next;
+        }
+        sfence();
+        self.uncover();
+
+        let key = self as *const Self as u64;
+        let (commits, aborts) = sp.callbacks;
+        let aborted = CALLBACKS.with(|cbs| {
+            cbs.borrow_mut().get_mut(&key).map(|c| {
+                c.commit.truncate(commits);
+                Callbacks {
+                    abort: c.abort.split_off(aborts),
+                    ..Default::default()
+                }
+            })
+        });
+        if let Some(aborted) = aborted {
+            aborted.run();
+        }
+    }
+
+    /// Registers a closure to run after the transaction commits
+    ///
+    /// The closure runs after the transaction is committed and its logs are
+    /// cleared, or after the chaperoned session completes if the transaction
+    /// is chaperoned. It is discarded if the transaction aborts, or if the
+    /// nested scope in which it is registered fails. The closures run in the
+    /// order of registration.
+    ///
+    /// This is useful for updating volatile state, such as caches and metrics,
+    /// only when the persistent changes are durable. The closures are not
+    /// persistent; they do not run after a crash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// type P = Allocator;
+    ///
+    /// static COMMITS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// P::transaction(|j| {
+    ///     root.set(root.get() + 1, j);
+    ///     j.on_commit(|| { COMMITS.fetch_add(1, Ordering::SeqCst); });
+    ///     assert_eq!(COMMITS.load(Ordering::SeqCst), 0);
+    /// }).unwrap();
+    ///
+    /// assert_eq!(COMMITS.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn on_commit<F: FnOnce() + 'static>(&self, f: F) {
+        let key = self as *const Self as u64;
+        CALLBACKS.with(|cbs| {
+            cbs.borrow_mut().entry(key).or_default().commit.push(Box::new(f));
+        });
+    }
+
+    /// Registers a closure to run after the transaction aborts
+    ///
+    /// The closure runs after the changes are rolled back, or after the
+    /// chaperoned session completes if the transaction is chaperoned. If it is
+    /// registered in a nested scope (i.e., a [`savepoint()`] or a nested
+    /// transaction), it also runs when that scope fails. The closures run in
+    /// the reverse order of registration. They are not persistent; they do not
+    /// run after a crash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// type P = Allocator;
+    ///
+    /// static ABORTS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// let res = P::transaction(|j| {
+    ///     root.set(root.get() + 1, j);
+    ///     j.on_abort(|| { ABORTS.fetch_add(1, Ordering::SeqCst); });
+    ///     panic!("abort");
+    /// });
+    ///
+    /// assert!(res.is_err());
+    /// assert_eq!(ABORTS.load(Ordering::SeqCst), 1);
+    /// ```
+    ///
+    /// [`savepoint()`]: #method.savepoint
+    pub fn on_abort<F: FnOnce() + 'static>(&self, f: F) {
+        let key = self as *const Self as u64;
+        CALLBACKS.with(|cbs| {
+            cbs.borrow_mut().entry(key).or_default().abort.push(Box::new(f));
+        });
+    }
+
+    /// Determines which callbacks to run when the transaction finishes
+    pub(crate) fn resolve_callbacks(&self, committed: bool) {
+        let key = self as *const Self as u64;
+        CALLBACKS.with(|cbs| {
+            if let Some(c) = cbs.borrow_mut().get_mut(&key) {
+                c.committed = committed;
+            }
+        });
+    }
+
+    /// Takes the callbacks of the transaction
+    pub(crate) fn take_callbacks(&self) -> Callbacks {
+        let key = self as *const Self as u64;
+        CALLBACKS.with(|cbs| cbs.borrow_mut().remove(&key).unwrap_or_default())
+    }
+
+    /// Marks `len` bytes at `off` as undo-logged in the current transaction,
+    /// and returns the parts of it which were not logged before as
+    /// `(offset, len)` pairs
+    pub(crate) fn cover(&self, off: u64, len: usize) -> Vec<(u64, usize)> {
+        let key = self as *const Self as u64;
+        let end = off + len as u64;
+        LOGGED.with(|logged| {
+            let mut logged = logged.borrow_mut();
+            let ranges = logged.entry(key).or_default();
+            let from = match ranges.range(..=off).next_back() {
+                Some((s, e)) if *e >= off => *s,
+                _ => off,
+            };
+            let touching: Vec<(u64, u64)> = ranges.range(from..=end)
+                .map(|(s, e)| (*s, *e)).collect();
+            let mut gaps = vec![];
+            let mut pos = off;
+            for (s, e) in &touching {
+                if *s > pos {
+                    gaps.push((pos, (*s - pos) as usize));
+                }
+                pos = pos.max(*e);
+                ranges.remove(s);
+            }
+            if pos < end {
+                gaps.push((pos, (end - pos) as usize));
+            }
+            let start = touching.first().map_or(off, |(s, _)| off.min(*s));
+            let end = touching.last().map_or(end, |(_, e)| end.max(*e));
+            ranges.insert(start, end);
+            gaps
+        })
+    }
+
+    /// Forgets the logged ranges, so that the data is logged again when it is
+    /// updated
+    fn uncover(&self) {
+        let key = self as *const Self as u64;
+        LOGGED.with(|logged| logged.borrow_mut().remove(&key));
+    }
+
+    /// Aborts the transaction, and requests to execute it again
+    ///
+    /// The changes are rolled back, and if the transaction is started with
+    /// [`transaction_with_retry()`], its body is executed again according to
+    /// the retry policy. Otherwise, the transaction fails. In a nested
+    /// transaction or a savepoint, only the nested scope is aborted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use corundum::RetryPolicy;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// type P = Allocator;
+    ///
+    /// static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    /// let old = root.get();
+    ///
+    /// P::transaction_with_retry(&RetryPolicy::new(), |j| {
+    ///     root.set(root.get() + 1, j);
+    ///     if ATTEMPTS.fetch_add(1, Ordering::SeqCst) < 2 {
+    ///         j.retry();
+    ///     }
+    /// }).unwrap();
+    ///
+    /// assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 3);
+    /// assert_eq!(root.get(), old + 1);
+    /// ```
+    ///
+    /// [`transaction_with_retry()`]: ../../alloc/trait.MemPoolTraits.html#method.transaction_with_retry
+    pub fn retry(&self) -> ! {
+        std::panic::resume_unwind(Box::new(Retry))
+    }
+
+    /// Runs `body` in a nested scope which may fail without aborting the
+    /// transaction
+    ///
+    /// If `body` panics, only the changes made in it are rolled back, and an
+    /// error is returned; the transaction continues from the savepoint.
+    /// Otherwise, its changes become a part of the transaction, and are
+    /// committed or rolled back with it. Nested transactions on the same pool
+    /// behave the same way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+    ///
+    /// P::transaction(|j| {
+    ///     root.set(1, j);
+    ///     let res = j.savepoint(|j| {
+    ///         root.set(2, j);
+    ///         panic!("try something else");
+    ///     });
+    ///     assert!(res.is_err());
+    ///     assert_eq!(root.get(), 1);
+    /// }).unwrap();
+    ///
+    /// assert_eq!(root.get(), 1);
+    /// ```
+    pub fn savepoint<T, F: FnOnce(&Self) -> T>(&self, body: F) -> Result<T>
+    where
+        F: TxInSafe + UnwindSafe
+    {
+        unsafe {
+            let sp = utils::as_mut(self).mark();
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| body(self))) {
+                Ok(res) => Ok(res),
+                Err(e) => {
+                    utils::as_mut(self).rollback_to(&sp,
+                        #[cfg(feature = "check_double_free")]
+                        &mut *A::dealloc_history()
+                    );
+                    if crate::sync::deadlock::is_victim(&e) {
+                        // The locks taken before the savepoint should be
+                        // released too
+                        std::panic::resume_unwind(e);
+                    }
+                    Err(Error::aborted(e))
+                }
+            }
+        }
     }
 
     /// Reverts all changes
@@ -493,7 +1267,8 @@ impl<A: MemPool> Journal<A> {
         let mut curr = self.pages;
         let resume = self.resume();
         if !self.is_set(JOURNAL_COMMITTED) || resume {
-            let rollback = !resume || !self.is_set(JOURNAL_COMMITTED);
+            let redo = self.is_set(JOURNAL_REDO_LOGGED);
+            let rollback = !redo && (!resume || !self.is_set(JOURNAL_COMMITTED));
             #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
                 if rollback {
                     self.spd.rollback();
@@ -501,6 +1276,23 @@ impl<A: MemPool> Journal<A> {
                     self.spd.recover();
                 }
             }
+            if redo && !self.is_set(JOURNAL_COMMITTED) {
+                // The transaction passed its commit point; complete it
+                if !self.is_set(JOURNAL_REDO_APPLIED) {
+                    self.apply_redo();
+                }
+            }
+            if !rollback {
+                // The deallocations postponed to commit are performed after
+                // the commit point, both by `commit()` and by the owners of
+                // the journals of a group commit. They may be incomplete, so
+                // they are performed again. Each one is failure-atomic and
+                // resets its log, so it is not repeated.
+                self.commit_dealloc(
+                    #[cfg(feature = "check_double_free")]
+                    check_double_free
+                );
+            }
             while let Some(page) = curr.as_option() {
                 page.recover(rollback, 
                     #[cfg(feature = "check_double_free")]
@@ -520,6 +1312,7 @@ impl<A: MemPool> Journal<A> {
         #[cfg(any(feature = "use_pspd", feature = "use_vspd"))] {
             self.spd.clear();
         }
+        self.uncover();
         #[cfg(feature = "pin_journals")]
         {
             let mut page = self.pages.as_option();
@@ -533,20 +1326,54 @@ impl<A: MemPool> Journal<A> {
             self.current = self.pages;
         }
 
+        #[cfg(not(feature = "pin_journals"))]
+        let cached = self.cached();
+
         #[cfg(not(feature = "pin_journals"))] {
+            let keep = if cached {
+                let mut used = 0;
+                let mut curr = self.pages;
+                while let Some(page) = curr.as_option() {
+                    used += 1;
+                    curr = page.next;
+                }
+                self.cache_limit(used)
+            } else {
+                0
+            };
+            let mut cnt = self.cached_pages();
+            let head = A::off_unchecked(self.pages.off_ref());
             while let Some(page) = self.pages.as_option() {
                 let nxt = page.next;
                 page.clear(
                     #[cfg(feature = "check_double_free")]
                     check_double_free
                 );
-                let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, std::mem::size_of::<Page<A>>());
-                A::log64(A::off_unchecked(self.pages.off_ref()), nxt.off(), z);
-                A::perform(z);
+                if cnt < keep {
+                    // Moves the page to the cache
+                    page.reset();
+                    let z = A::zone(page.off());
+                    A::prepare(z);
+                    A::log64(head, nxt.off(), z);
+                    A::log64(A::off_unchecked(page.next.off_ref()), self.free.off(), z);
+                    A::log64(A::off_unchecked(self.free.off_ref()), page.off(), z);
+                    A::perform(z);
+                    cnt += 1;
+                } else {
+                    let z = A::pre_dealloc(page.as_mut_ptr() as *mut u8, page.size());
+                    A::log64(A::off_unchecked(self.pages.off_ref()), nxt.off(), z);
+                    A::perform(z);
+                }
 
                 #[cfg(feature = "check_allocator_cyclic_links")]
                 debug_assert!(A::verify());
             }
+            self.trim_cache(keep);
+        }
+        if self.flags & (JOURNAL_REDO_LOGGED | JOURNAL_REDO_APPLIED) != 0 {
+            // A reused journal should not look committed by redo logs
+            self.unset(JOURNAL_REDO_LOGGED | JOURNAL_REDO_APPLIED);
+            persist_obj_with_log::<_,A>(&self.flags, true);
         }
         // if let Ok(prev) = A::deref_mut::<Self>(self.prev_off) {
         //     prev.next_off = self.next_off;
@@ -557,10 +1384,12 @@ impl<A: MemPool> Journal<A> {
         self.complete();
 
         #[cfg(not(feature = "pin_journals"))] {
-            A::drop_journal(self);
-            A::journals(|journals| {
-                journals.remove(&std::thread::current().id());
-            });
+            if !cached {
+                A::drop_journal(self);
+                A::journals(|journals| {
+                    journals.remove(&std::thread::current().id());
+                });
+            }
         }
     }
 
@@ -5,6 +5,8 @@ use crate::ptr::Ptr;
 use crate::stm::*;
 use crate::*;
 use std::clone::Clone;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::{self, Debug};
 use std::ptr;
 
@@ -17,9 +19,15 @@ type Offset = u64;
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum LogEnum {
     /// `(src, log, len)`: An undo log of slice `src..src+len` kept in
-    /// `log..log+len`.
+    /// `log..log+len`. If `log` is `u64::MAX`, the slice is covered by the
+    /// other data logs of the transaction, and this log only keeps the
+    /// notifier of the owner.
     DataLog(u64, u64, usize),
 
+    /// `(dst, draft, len)`: A redo log of slice `dst..dst+len` whose new value
+    /// is kept in `draft..draft+len` until the transaction commits.
+    RedoLog(u64, u64, usize),
+
     /// `(u64, usize)`: Similar to [`DropOnFailure`] except that it
     /// drops the allocation when the high-level transaction is aborted. This is
     /// useful for temporarily unowned allocations, such as slices, because they
@@ -57,6 +65,7 @@ impl Debug for LogEnum {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::result::Result<(), fmt::Error> {
         match *self {
             DataLog(off, _, _)       => write!(f, "DataLog         ({})", offset_to_str(off)),
+            RedoLog(off, _, _)       => write!(f, "RedoLog         ({})", offset_to_str(off)),
             DropOnAbort(off, _)      => write!(f, "DropOnAbort     ({})", offset_to_str(off)),
             DropOnCommit(off, _)     => write!(f, "DropOnCommit    ({})", offset_to_str(off)),
             DropOnFailure(off, _)    => write!(f, "DropOnFailure   ({})", offset_to_str(off)),
@@ -75,6 +84,11 @@ impl Debug for LogEnum {
 /// keeps a pointer to the flag and updates it accordingly. The pointer is
 /// persistent meaning that it remains valid after restart of crash.
 /// 
+/// The flag is `1` if the data has an undo log, and `2` if it has a redo log,
+/// in which case the new value is in the draft (see [`Log::redirect`]).
+/// 
+/// [`Log::redirect`]: ./struct.Log.html#method.redirect
+/// 
 #[derive(PartialEq, Eq)]
 pub enum Notifier<A: MemPool> {
     /// Atomically update the log flag
@@ -277,6 +291,7 @@ impl<A: MemPool> Log<A> {
     pub fn kind(&self) -> String {
         match self.0 {
             DataLog(_, _, _) => "DataLog",
+            RedoLog(_, _, _) => "RedoLog",
             DropOnAbort(_, _) => "DropOnAbort",
             DropOnCommit(_, _) => "DropOnCommit",
             DropOnFailure(_, _) => "DropOnFailure",
@@ -290,16 +305,38 @@ impl<A: MemPool> Log<A> {
     pub fn inner(&self) -> LogEnum {
         self.0
     }
+
+    /// Returns the regions of the pool that this log refers to as
+    /// `(offset, len)` pairs
+    pub fn ranges(&self) -> std::vec::Vec<(u64, usize)> {
+        match self.0 {
+            DataLog(src, log, len) if src != u64::MAX && log == u64::MAX => vec![(src, len)],
+            DataLog(src, log, len) |
+            RedoLog(src, log, len) if src != u64::MAX => vec![(src, len), (log, len)],
+            DropOnAbort(off, len) |
+            DropOnCommit(off, len) |
+            DropOnFailure(off, len) if off != u64::MAX => vec![(off, len)],
+            RecountOnFailure(off, _) => vec![(off, std::mem::size_of::<usize>())],
+            UnlockOnCommit(off) => vec![(off, 1)],
+            _ => vec![]
+        }
+    }
 }
 
 use LogEnum::*;
 
+thread_local! {
+    /// The drafts of the redo-logged objects of the running transactions of
+    /// this thread, mapped from the virtual address of the objects
+    static DRAFTS: RefCell<HashMap<u64, u64>> = RefCell::new(HashMap::new());
+}
+
 #[cfg(feature = "verbose")]
 fn dump_data<A: MemPool>(tag: &str, off: u64, len: usize) {
     use term_painter::Color::*;
     use term_painter::ToStyle;
 
-    if *crate::utils::VERBOSE {
+    if crate::utils::verbose() {
         print!("{:<8} {}", A::name().to_owned() + ":", 
             BrightBlue.paint(format!("{:>10}  ", tag)));
         for i in 0..len {
@@ -358,7 +395,10 @@ impl<A: MemPool> Log<A> {
                 dump_data::<A>("DATA", pointer.off(), len);
             }
 
-            let log = unsafe { pointer.dup() };
+            let gaps = journal.cover(pointer.off(), len);
+            if gaps != [(pointer.off(), len)] {
+                return Self::create_gaps(pointer.off(), len, gaps, journal, notifier);
+            }
 
             // if cfg!(feature = "replace_with_log") {
             //     pointer.replace(log.replace(pointer.off()));
@@ -370,8 +410,11 @@ impl<A: MemPool> Log<A> {
 
             //     Self::create_impl(log.off(), pointer.off(), len, journal, notifier)
             // } else {
-            crate::ll::persist_obj_with_log::<_,A>(log.as_ref(), false);
-            Self::create_impl(pointer.off(), log.off(), len, journal, notifier)
+            let log = Self::write_with_copy(DataLog(pointer.off(), u64::MAX, len),
+                pointer.off(), len, journal, notifier.clone());
+            notifier.update(1);
+            sfence();
+            log
             // }
         }
     }
@@ -401,12 +444,130 @@ impl<A: MemPool> Log<A> {
                 dump_data::<A>("DATA", slice.off(), len);
             }
 
-            let log = unsafe { slice.dup() };
+            let gaps = journal.cover(slice.off(), len);
+            if gaps != [(slice.off(), len)] {
+                return Self::create_gaps(slice.off(), len, gaps, journal, notifier);
+            }
 
-            crate::ll::persist_obj_with_log::<_,A>(log.as_ref(), false);
-            Self::create_impl(slice.off(), log.off(), len, journal, notifier)
-            // }
+            let log = Self::write_with_copy(DataLog(slice.off(), u64::MAX, len),
+                slice.off(), len, journal, notifier.clone());
+            notifier.update(1);
+            sfence();
+            log
+        }
+    }
+
+    /// Logs the `gaps` of slice `off..off+len` which are not already logged in
+    /// the current transaction. Overlapping data is not copied again. The
+    /// owner is notified through a log without a copy, so that its flag is
+    /// reset when the transaction finishes.
+    fn create_gaps(
+        off: u64,
+        len: usize,
+        gaps: Vec<(u64, usize)>,
+        journal: &Journal<A>,
+        notifier: Notifier<A>,
+    ) -> Ptr<Log<A>, A> {
+        for (gap, gap_len) in gaps {
+            log!(A, Yellow, "LOG GAP", "FOR:         ({:>6x}:{:<6x}) = {:<6}",
+                gap, gap as usize + (gap_len - 1), gap_len
+            );
+            Self::write_with_copy(DataLog(gap, u64::MAX, gap_len),
+                gap, gap_len, journal, Notifier::None);
         }
+        Self::create_impl(off, u64::MAX, len, journal, notifier)
+    }
+
+    /// Creates a redo log of `x` into `journal` and notifies the owner that
+    /// it is redo-logged. It returns a pointer to the draft of `x` which
+    /// receives the new value until the transaction commits.
+    ///
+    /// Unlike [`create()`](#method.create), the draft is not flushed until the
+    /// commit, and `x` remains unchanged until then. The owner should use
+    /// [`redirect()`](#method.redirect) to access the current value.
+    pub fn create_redo<T: ?Sized>(
+        x: &T,
+        journal: &Journal<A>,
+        mut notifier: Notifier<A>,
+    ) -> *mut T {
+        #[cfg(feature = "stat_perf")]
+        let _perf = crate::stat::Measure::<A>::DataLog(std::time::Instant::now());
+
+        let len = std::mem::size_of_val(x);
+        if len == 0 {
+            notifier.update(1);
+            x as *const T as *mut T
+        } else {
+            let pointer = unsafe { Ptr::<T, A>::new_unchecked(x) };
+
+            log!(A, Yellow, "LOG", "FOR:         ({:>6}:{:<6}) = {:<6} RedoLog  TYPE: {}",
+                offset_to_str(pointer.off()), offset_to_str((pointer.off() as usize + (len - 1)) as u64),
+                len, std::any::type_name_of_val(x)
+            );
+
+            let log = Self::write_with_copy(RedoLog(pointer.off(), u64::MAX, len),
+                pointer.off(), len, journal, notifier.clone());
+            let draft = match log.0 {
+                RedoLog(_, draft, _) => draft,
+                _ => unreachable!()
+            };
+            let draft = unsafe { A::get_mut_unchecked::<T>(draft) as *mut T };
+            DRAFTS.with(|d| {
+                d.borrow_mut().insert(x as *const T as *const u8 as u64,
+                    draft as *const u8 as u64);
+            });
+            notifier.update(2);
+            sfence();
+            draft
+        }
+    }
+
+    /// Returns a pointer to the current value of `x` according to its log
+    /// flag. If `x` is redo-logged (`flag == 2`), it is the draft of `x`.
+    /// Otherwise, it is `x` itself.
+    #[inline]
+    pub fn redirect<T: ?Sized>(flag: u8, x: &T) -> *mut T {
+        let p = x as *const T as *mut T;
+        if flag == 2 {
+            let addr = p as *const u8 as u64;
+            if let Some(draft) = DRAFTS.with(|d| d.borrow().get(&addr).cloned()) {
+                return unsafe { utils::read_addr::<T>(draft) };
+            }
+        }
+        p
+    }
+
+    /// Writes a data or a redo `log` on `journal` and links it to a new copy
+    /// of the `len` bytes at `off`
+    /// 
+    /// The log is written without a copy first. Then, the copy is linked to it
+    /// in the same failure-atomic section as its allocation, so that a crash
+    /// in between does not leak the copy.
+    fn write_with_copy(
+        log: LogEnum,
+        off: u64,
+        len: usize,
+        journal: &Journal<A>,
+        notifier: Notifier<A>,
+    ) -> Ptr<Log<A>, A> {
+        let mut log = journal.write(log, notifier);
+        unsafe {
+            let (p, copy, size, z) = A::pre_alloc(len);
+            if p.is_null() {
+                std::panic::panic_any(crate::Error::OutOfMemory);
+            }
+            A::drop_on_failure(copy, size, z);
+            let data = A::deref_slice_unchecked::<u8>(off, len);
+            ptr::copy_nonoverlapping(data.as_ptr(), p, len);
+            persist_with_log::<_,A>(p, len, false);
+            match &mut log.as_mut().0 {
+                DataLog(_, c, _) |
+                RedoLog(_, c, _) => A::log64(A::off_unchecked(c), copy, z),
+                _ => unreachable!()
+            }
+            A::perform(z);
+        }
+        log
     }
 
     /// Writes a `log` on a given `journal` and notifies the owner, if specified
@@ -584,6 +745,38 @@ impl<A: MemPool> Log<A> {
         }
     }
 
+    /// Discards a `DropOnAbort` log of an allocation in `allocs`, as it is
+    /// reclaimed by its `DropOnFailure` log in a partial rollback
+    pub(crate) unsafe fn cancel_drop(&mut self, allocs: &std::collections::HashSet<u64>) {
+        if let DropOnAbort(src, _) = &mut self.0 {
+            if allocs.contains(src) {
+                *src = u64::MAX;
+                persist_obj_with_log::<_,A>(src, false);
+            }
+        }
+    }
+
+    /// Reverts the changes of this log when the transaction is rolled back to
+    /// a savepoint before it
+    pub(crate) unsafe fn rollback_partial(&mut self, 
+        #[cfg(feature = "check_double_free")]
+        check_double_free: &mut HashSet<u64>
+    ) {
+        match self.0 {
+            DropOnAbort(_, _) => self.rollback_drop_on_abort(
+                #[cfg(feature = "check_double_free")]
+                check_double_free
+            ),
+            DataLog(_, _, _) |
+            DropOnFailure(_, _) |
+            RecountOnFailure(_, _) => self.recover(true, 
+                #[cfg(feature = "check_double_free")]
+                check_double_free
+            ),
+            _ => {}
+        }
+    }
+
     /// Recovers from the crash or power failure
     pub(crate) unsafe fn recover(&mut self, rollback: bool, 
         #[cfg(feature = "check_double_free")]
@@ -593,7 +786,8 @@ impl<A: MemPool> Log<A> {
             DataLog(src, log, layout) => {
                 if rollback {
                     debug_assert!(A::allocated(*src, 1), "Access Violation at address 0x{:x}", *src);
-                    debug_assert!(A::allocated(*log, 1), "Access Violation at address 0x{:x}", *log);
+                    debug_assert!(*log == u64::MAX || A::allocated(*log, 1),
+                        "Access Violation at address 0x{:x}", *log);
                     Self::rollback_datalog(src, log, layout);
                     self.notify(0);
                     self.1 = Notifier::None;
@@ -628,7 +822,7 @@ impl<A: MemPool> Log<A> {
                     let c = A::get_mut_unchecked::<u64>(off);
                     let z = A::zone(off);
                     A::prepare(z);
-                    if *c != u64::MAX {
+                    if rollback && *c != u64::MAX {
                         if *inc {
                             A::log64(off, *c as u64 + 1, z);
                         } else {
@@ -649,21 +843,50 @@ impl<A: MemPool> Log<A> {
         }
     }
 
-    /// Commits changes
-    pub(crate) fn commit_data(&mut self) {
+    /// Flushes the draft of a redo log, and returns true if it is a redo log
+    pub(crate) fn persist_redo(&self) -> bool {
+        match self.0 {
+            RedoLog(dst, draft, len) => {
+                if dst != u64::MAX && draft != u64::MAX {
+                    unsafe {
+                        persist_with_log::<u8,A>(A::get_mut_unchecked(draft), len, false);
+                    }
+                }
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Copies the draft of a redo log to its original location
+    pub(crate) unsafe fn apply_redo(&mut self) {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<A>::CommitLog(std::time::Instant::now());
 
-        match &mut self.0 {
-            DataLog(_src, _log, _len) => {
-                debug_assert!(A::allocated(*_src, 1), "Access Violation at address 0x{:x}", *_src);
+        if let RedoLog(dst, draft, len) = self.0 {
+            if dst != u64::MAX && draft != u64::MAX {
+                log!(A, Magenta, "APPLY", "FOR:         ({:>6x}:{:<6x}) = {:<6} RedoLog({})",
+                    dst, dst as usize + (len - 1), len, draft
+                );
+                debug_assert!(A::allocated(dst, 1), "Access Violation at address 0x{:x}", dst);
+                debug_assert!(A::allocated(draft, 1), "Access Violation at address 0x{:x}", draft);
+                let dst = A::get_mut_unchecked::<u8>(dst);
+                let draft = A::get_mut_unchecked::<u8>(draft);
+                ptr::copy_nonoverlapping(draft, dst, len);
+                persist_with_log::<_,A>(dst, len, false);
+            }
+        }
+    }
 
-                #[cfg(all(not(feature = "no_flush_updates"), not(feature = "replace_with_log")))]
-                unsafe {
-                    persist_with_log::<u8,A>(A::get_mut_unchecked(*_src), *_len, false);
-                }
+    /// Returns the `(offset, len)` of the data that should be flushed on
+    /// commit, if this is a data log with a copy
+    pub(crate) fn data_range(&self) -> Option<(u64, usize)> {
+        match self.0 {
+            DataLog(src, log, len) if src != u64::MAX && log != u64::MAX => {
+                debug_assert!(A::allocated(src, 1), "Access Violation at address 0x{:x}", src);
+                Some((src, len))
             }
-            _ => {}
+            _ => Option::None
         }
     }
 
@@ -700,8 +923,9 @@ impl<A: MemPool> Log<A> {
 
     /// Clears this log and notifies the owner
     /// 
-    /// * If it is a [`DataLog`](./enum.LogEnum.html#variant.DataLog), it reclaims
-    /// the allocation for the log.
+    /// * If it is a [`DataLog`](./enum.LogEnum.html#variant.DataLog) or a
+    /// [`RedoLog`](./enum.LogEnum.html#variant.RedoLog), it reclaims the
+    /// allocation for the log.
     /// * If it is a [`UnlockOnCommit`](./enum.LogEnum.html#variant.UnlockOnCommit),
     /// it unlocks the mutex.
     /// 
@@ -712,8 +936,12 @@ impl<A: MemPool> Log<A> {
         #[cfg(feature = "stat_perf")]
         let _perf = crate::stat::Measure::<A>::ClearLog(std::time::Instant::now());
 
+        #[cfg(feature = "verbose")]
+        let kind = self.kind();
+
         match &mut self.0 {
-            DataLog(_src, log, len) => {
+            DataLog(_src, log, len) |
+            RedoLog(_src, log, len) => {
                 if *log != u64::MAX {
                     #[cfg(feature = "check_double_free")] {
                         if check_double_free.contains(&*log) {
@@ -721,8 +949,8 @@ impl<A: MemPool> Log<A> {
                         }
                         check_double_free.insert(*log);
                     }
-                    log!(A, Magenta, "DEL LOG", "FOR:         ({:>6x}:{:<6x}) = {:<6} DataLog({})",
-                        *_src, *_src as usize + (*len - 1), *len, log
+                    log!(A, Magenta, "DEL LOG", "FOR:         ({:>6x}:{:<6x}) = {:<6} {}({})",
+                        *_src, *_src as usize + (*len - 1), *len, kind, log
                     );
                     debug_assert!(A::allocated(*log, *len), "Access Violation at address 0x{:x}", *log);
 
@@ -740,6 +968,9 @@ impl<A: MemPool> Log<A> {
             UnlockOnCommit(src) => {
                 if *src != u64::MAX {
                     log!(A, Magenta, "UNLOCK", "FOR:          v@{}", *src);
+                    if A::deadlock_detection() {
+                        crate::sync::deadlock::released(*src);
+                    }
                     #[cfg(not(any(feature = "no_pthread", windows)))] {
                         let b = &mut *(*src as *mut (bool, libc::pthread_mutex_t, libc::pthread_mutexattr_t));
                         b.0 = false;
@@ -767,10 +998,22 @@ impl<A: MemPool> Log<A> {
     /// Notify the owner that the log is created/cleared according to `v`
     #[inline]
     pub unsafe fn notify(&mut self, v: u8) {
-        if let DataLog(src, _, _) = self.0 {
-            if src != u64::MAX {
-                self.1.update(v);
+        match self.0 {
+            DataLog(src, _, _) => {
+                if src != u64::MAX {
+                    self.1.update(v);
+                }
+            }
+            RedoLog(src, _, _) => {
+                if src != u64::MAX {
+                    if v == 0 {
+                        let addr = A::start() + src;
+                        DRAFTS.with(|d| d.borrow_mut().remove(&addr));
+                    }
+                    self.1.update(v);
+                }
             }
+            _ => {}
         }
     }
 }
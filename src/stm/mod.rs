@@ -1,8 +1,11 @@
 //! Software transactional memory APIs
 
 mod chaperon;
+mod group;
 mod journal;
 mod log;
+mod read_journal;
+mod retry;
 pub mod pspd;
 pub mod vspd;
 
@@ -12,8 +15,11 @@ use crate::{TxInSafe,TxOutSafe};
 use std::panic::UnwindSafe;
 
 pub use chaperon::*;
+pub use group::*;
 pub use journal::*;
 pub use log::*;
+pub use read_journal::*;
+pub use retry::*;
 
 /// Atomically executes commands
 /// 
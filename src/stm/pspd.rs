@@ -4,18 +4,15 @@
 //! `"use_pspd"` to the feature list.
 //! 
 
-use crate::cell::LazyCell;
 use crate::alloc::MemPool;
 use crate::ptr::Ptr;
 use crate::{utils, ll};
 use std::{mem, ptr};
 
-static SCRATCHPAD_SIZE: LazyCell<usize> = LazyCell::new(|| {
-    utils::nearest_pow2(std::env::var("SPD_SIZE")
-        .unwrap_or("1024".to_string())
-        .parse::<u64>()
-        .expect("RECOVERY_INFO should be an unsigned integer")) as usize
-});
+#[inline]
+fn scratchpad_size<A: MemPool>() -> usize {
+    utils::nearest_pow2(A::scratchpad_size() as u64) as usize
+}
 
 struct Page<A: MemPool> {
     len: usize,
@@ -36,7 +33,7 @@ impl<A: MemPool> Page<A> {
             if let Some(next) = self.next.as_option() {
                 next.write(val, org_off)
             } else {
-                let cap = *SCRATCHPAD_SIZE;
+                let cap = scratchpad_size::<A>();
                 let cap = utils::nearest_pow2(usize::max(cap, dist) as u64) as usize;
                 // FIXME: Memory leak
                 let (p, off, _, z) = A::pre_alloc(cap);
@@ -115,7 +112,7 @@ pub struct Scratchpad<A: MemPool> {
 impl<A: MemPool> Scratchpad<A> {
     pub(crate) fn new() -> Self {
         unsafe {
-            let cap = *SCRATCHPAD_SIZE - mem::size_of::<Page<A>>();
+            let cap = scratchpad_size::<A>() - mem::size_of::<Page<A>>();
             // FIXME: Memory leak
             let (p, _off, _) = A::alloc(mem::size_of::<Page<A>>() + cap);
             let pg = utils::read::<Page<A>>(p);
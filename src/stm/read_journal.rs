@@ -0,0 +1,112 @@
+//! The volatile journal of read-only transactions
+use crate::alloc::MemPool;
+use crate::*;
+use std::cell::RefCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+
+/// A volatile journal for read-only transactions
+///
+/// [`read_transaction()`] creates a `ReadJournal` and passes a reference to it
+/// to the body closure. Unlike [`Journal`], it does not reside in the pool, and
+/// it does not keep any logs. Therefore, it cannot be used for updating the
+/// persistent objects (e.g., [`PCell::set()`] or [`PRefCell::borrow_mut()`])
+/// or for allocating new objects, as they all require a `&Journal`.
+///
+/// A `ReadJournal` only keeps the [`PMutex`]es that are locked using
+/// [`PMutex::read_lock()`], so that they remain locked until the end of the
+/// read transaction, similar to the locks taken in regular transactions.
+///
+/// ```
+/// use corundum::default::*;
+///
+/// type P = Allocator;
+///
+/// let root = P::open::<PCell<i32>>("foo.pool", O_CF).unwrap();
+///
+/// let v = P::read_transaction(|_j| {
+///     // root.set(1, _j); // Error: expected `&Journal`
+///     root.get()
+/// }).unwrap();
+///
+/// assert_eq!(v, root.get());
+/// ```
+///
+/// [`read_transaction()`]: ../alloc/trait.MemPoolTraits.html#method.read_transaction
+/// [`Journal`]: ./struct.Journal.html
+/// [`PCell::set()`]: ../cell/struct.PCell.html#method.set
+/// [`PRefCell::borrow_mut()`]: ../cell/struct.PRefCell.html#method.borrow_mut
+/// [`PMutex`]: ../sync/struct.PMutex.html
+/// [`PMutex::read_lock()`]: ../sync/struct.PMutex.html#method.read_lock
+///
+pub struct ReadJournal<A: MemPool> {
+    locks: RefCell<Vec<u64>>,
+    phantom: PhantomData<A>,
+}
+
+impl<A: MemPool> !PSafe for ReadJournal<A> {}
+impl<A: MemPool> !Send for ReadJournal<A> {}
+impl<A: MemPool> !Sync for ReadJournal<A> {}
+impl<A: MemPool> !TxOutSafe for ReadJournal<A> {}
+impl<A: MemPool> !TxInSafe for ReadJournal<A> {}
+impl<A: MemPool> !LooseTxInUnsafe for ReadJournal<A> {}
+impl<A: MemPool> !std::panic::RefUnwindSafe for ReadJournal<A> {}
+impl<A: MemPool> !std::panic::UnwindSafe for ReadJournal<A> {}
+
+impl<A: MemPool> ReadJournal<A> {
+    pub(crate) fn new() -> Self {
+        Self {
+            locks: RefCell::new(vec![]),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Keeps the lock at `virt_addr` locked until the end of the read
+    /// transaction
+    pub(crate) fn unlock_on_finish(&self, virt_addr: u64) {
+        log!(A, Yellow, "READ LOCK", "FOR:         v@{:<18}", virt_addr);
+        self.locks.borrow_mut().push(virt_addr);
+    }
+
+    /// Returns the number of locks that are held by this journal
+    pub fn locks(&self) -> usize {
+        self.locks.borrow().len()
+    }
+}
+
+impl<A: MemPool> Drop for ReadJournal<A> {
+    fn drop(&mut self) {
+        for src in self.locks.borrow_mut().drain(..).rev() {
+            log!(A, Magenta, "UNLOCK", "FOR:          v@{}", src);
+            if A::deadlock_detection() {
+                crate::sync::deadlock::released(src);
+            }
+            unsafe {
+                #[cfg(not(any(feature = "no_pthread", windows)))] {
+                    let b = &mut *(src as *mut (bool, libc::pthread_mutex_t, libc::pthread_mutexattr_t));
+                    let lock = &mut b.1;
+                    let attr = &mut b.2;
+                    let result = libc::pthread_mutex_unlock(lock);
+                    if result != 0 {
+                        crate::sync::init_lock(lock, attr);
+                    }
+                }
+                #[cfg(any(feature = "no_pthread", windows))] {
+                    let b = &mut *(src as *mut (bool, u64));
+                    let lock = &mut b.1;
+                    std::intrinsics::atomic_store_rel(lock, 0);
+                }
+            }
+        }
+    }
+}
+
+impl<A: MemPool> Debug for ReadJournal<A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "READ JOURNAL")?;
+        for src in self.locks.borrow().iter() {
+            writeln!(f, "  UnlockOnFinish  (v@{})", src)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Retrying failed transactions
+use std::time::Duration;
+
+/// Default maximum number of attempts of a transaction
+pub const DEFAULT_RETRY_ATTEMPTS: usize = 10;
+
+/// The panic payload of a transaction which requests to be re-executed
+///
+/// It is raised by [`Journal::retry()`].
+///
+/// [`Journal::retry()`]: ./journal/struct.Journal.html#method.retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry;
+
+/// Determines how many times and how often a failed transaction is retried
+///
+/// It is used by [`transaction_with_retry()`]. The delay before the `n`-th
+/// retry starts with the initial backoff and doubles every time, up to the
+/// maximum backoff.
+///
+/// # Examples
+///
+/// ```
+/// use corundum::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let mut policy = RetryPolicy::new();
+/// policy.max_attempts(3)
+///     .backoff(Duration::from_micros(10), Duration::from_millis(1));
+/// ```
+///
+/// [`transaction_with_retry()`]: ../alloc/trait.MemPoolTraits.html#method.transaction_with_retry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub(crate) max_attempts: usize,
+    pub(crate) backoff: Duration,
+    pub(crate) max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RETRY_ATTEMPTS,
+            backoff: Duration::from_micros(100),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy with [`DEFAULT_RETRY_ATTEMPTS`] attempts, and a
+    /// backoff from 100us to 100ms
+    ///
+    /// [`DEFAULT_RETRY_ATTEMPTS`]: ./constant.DEFAULT_RETRY_ATTEMPTS.html
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of attempts, including the first one
+    ///
+    /// The transaction is executed at least once, even if it is zero.
+    pub fn max_attempts(&mut self, attempts: usize) -> &mut Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Sets the delay before the first retry, and the maximum delay between
+    /// the attempts
+    pub fn backoff(&mut self, initial: Duration, max: Duration) -> &mut Self {
+        self.backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Returns the delay after the failed `attempt`
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let shift = (attempt.max(1) - 1).min(31) as u32;
+        self.backoff.checked_mul(1 << shift)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
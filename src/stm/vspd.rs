@@ -4,18 +4,11 @@
 //! `"use_pspd"` to the feature list.
 //! 
 
-use crate::cell::{LazyCell,VCell};
+use crate::cell::VCell;
 use crate::alloc::MemPool;
 use crate::{utils, ll};
 use std::{mem, ptr, alloc::*};
 
-static SCRATCHPAD_SIZE: LazyCell<usize> = LazyCell::new(|| {
-    std::env::var("SPD_SIZE")
-        .unwrap_or("1024".to_string())
-        .parse::<usize>()
-        .expect("RECOVERY_INFO should be an unsigned integer")
-});
-
 struct RawPtr(*mut u8);
 
 impl Default for RawPtr {
@@ -33,7 +26,7 @@ pub struct Scratchpad<A: MemPool> {
 
 impl<A: MemPool> Scratchpad<A> {
     pub(crate) fn new() -> Self {
-        let size = *SCRATCHPAD_SIZE;
+        let size = A::scratchpad_size();
         Self {
             base: VCell::new(RawPtr(unsafe {
                 alloc(Layout::from_size_align_unchecked(size, 2))
@@ -53,7 +46,7 @@ impl<A: MemPool> Scratchpad<A> {
         //   * data                              (T)
         let len = 8 + 8 + size;
         if self.len + len > self.cap {
-            let new_cap = self.cap + *SCRATCHPAD_SIZE;
+            let new_cap = self.cap + A::scratchpad_size();
             self.base = VCell::new(RawPtr(realloc(self.base.0,
                 Layout::from_size_align_unchecked(self.cap, 2),
                 new_cap)));
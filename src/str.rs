@@ -4,6 +4,7 @@ use crate::RootObj;
 use crate::convert::PFrom;
 use crate::alloc::MemPool;
 use crate::clone::PClone;
+use crate::trace::{Trace, Tracer};
 use crate::stm::*;
 use crate::vec::Vec;
 use std::string::FromUtf8Error;
@@ -1216,6 +1217,12 @@ impl<A: MemPool> String<A> {
     // }
 }
 
+impl<A: MemPool> Trace<A> for String<A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        self.vec.trace(tracer)
+    }
+}
+
 impl<A: MemPool> PClone<A> for String<A> {
     fn pclone(&self, journal: &Journal<A>) -> Self {
         Self {
@@ -0,0 +1,107 @@
+//! Deadlock detection for transaction-wide mutexes
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, Once};
+
+/// The panic payload of a transaction which is aborted to break a deadlock
+///
+/// When the deadlock detection is enabled (see
+/// [`PoolOptions::deadlock_detection`]), a transaction which is about to wait
+/// for a [`PMutex`] held by a transaction that directly or indirectly waits
+/// for it is chosen as the victim. It unwinds with this payload, so that it
+/// rolls back and releases its locks. Nested transactions and savepoints do
+/// not stop it; the whole transaction aborts, and the caller may retry it.
+///
+/// [`PoolOptions::deadlock_detection`]: ../alloc/struct.PoolOptions.html#method.deadlock_detection
+/// [`PMutex`]: ./struct.PMutex.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadlock;
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadlock detected")
+    }
+}
+
+#[derive(Default)]
+struct WaitForGraph {
+    /// The owner threads of the held locks and the number of times they are
+    /// locked, mapped from the address of the locks
+    owners: HashMap<u64, (u64, usize)>,
+
+    /// The locks that the blocked threads are waiting for, mapped from the
+    /// thread ids
+    waits: HashMap<u64, u64>,
+}
+
+static INIT: Once = Once::new();
+static mut GRAPH: Option<Mutex<WaitForGraph>> = None;
+
+fn graph() -> MutexGuard<'static, WaitForGraph> {
+    INIT.call_once(|| unsafe { GRAPH = Some(Mutex::new(Default::default())) });
+    match unsafe { GRAPH.as_ref().unwrap() }.lock() {
+        Ok(g) => g,
+        Err(p) => p.into_inner()
+    }
+}
+
+#[inline]
+fn tid() -> u64 {
+    std::thread::current().id().as_u64().get()
+}
+
+/// Registers that the current thread waits for the lock at `lock`. It returns
+/// false without registering if waiting closes a cycle in the wait-for graph.
+pub(crate) fn wait(lock: u64) -> bool {
+    let me = tid();
+    let mut g = graph();
+    let mut curr = lock;
+    for _ in 0..=g.waits.len() {
+        let owner = match g.owners.get(&curr) {
+            Some((owner, _)) => *owner,
+            None => break
+        };
+        if owner == me {
+            return false;
+        }
+        match g.waits.get(&owner) {
+            Some(next) => curr = *next,
+            None => break
+        }
+    }
+    g.waits.insert(me, lock);
+    true
+}
+
+/// Unregisters the current thread from waiting
+pub(crate) fn done_waiting() {
+    graph().waits.remove(&tid());
+}
+
+/// Records that the current thread holds the lock at `lock` once more
+pub(crate) fn acquired(lock: u64) {
+    let me = tid();
+    graph().owners.entry(lock).or_insert((me, 0)).1 += 1;
+}
+
+/// Records that the lock at `lock` is released once
+pub(crate) fn released(lock: u64) {
+    let mut g = graph();
+    if let Some((_, cnt)) = g.owners.get_mut(&lock) {
+        *cnt -= 1;
+        if *cnt == 0 {
+            g.owners.remove(&lock);
+        }
+    }
+}
+
+/// Aborts the current transaction as the victim of a deadlock
+pub(crate) fn abort() -> ! {
+    std::panic::resume_unwind(Box::new(Deadlock))
+}
+
+/// Returns true if `payload` is the panic payload of a deadlock victim
+pub(crate) fn is_victim(payload: &Box<dyn Any + Send>) -> bool {
+    payload.is::<Deadlock>()
+}
@@ -1,7 +1,9 @@
 //! Useful synchronization primitives
 
+pub(crate) mod deadlock;
 mod mutex;
 mod parc;
 
+pub use deadlock::Deadlock;
 pub use mutex::*;
 pub use parc::*;
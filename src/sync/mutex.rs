@@ -1,17 +1,25 @@
 use crate::alloc::MemPool;
 use crate::cell::VCell;
 use crate::ptr::Ptr;
-use crate::stm::{Journal, Log, Notifier, Logger};
+use crate::stm::{Journal, ReadJournal, Log, Notifier, Logger};
 use crate::*;
 use std::cell::UnsafeCell;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::sync::{TryLockError, TryLockResult};
+use std::time::{Duration, Instant};
+use super::deadlock;
 
 #[allow(unused_imports)]
 use std::{fmt, intrinsics};
 
+/// The first and the longest pauses between the attempts of `lock_timeout()`
+#[cfg(not(any(feature = "no_pthread", windows)))]
+const MIN_PAUSE: Duration = Duration::from_micros(10);
+#[cfg(not(any(feature = "no_pthread", windows)))]
+const MAX_PAUSE: Duration = Duration::from_millis(1);
+
 /// A transaction-wide recursive mutual exclusion primitive useful for
 /// protecting shared data while transaction is open. Further locking in the
 /// same thread is non-blocking. Any access to data is serialized. Borrow rules
@@ -190,10 +198,66 @@ impl<T: PSafe, A: MemPool> PMutex<T, A> {
 }
 
 impl<T, A: MemPool> PMutex<T, A> {
+    /// Returns the address of the underlying lock
     #[inline]
-    fn raw_lock(&self, journal: &Journal<A>) {
-        unsafe {
-            // Log::unlock_on_failure(self.inner.get(), journal);
+    fn lock_addr(&self) -> u64 {
+        &self.inner.lock as *const _ as u64
+    }
+
+    /// Takes the underlying lock if it is free or held by the current thread
+    #[inline]
+    unsafe fn try_raw(&self) -> bool {
+        let lock = &self.inner.lock.1 as *const _ as *mut _;
+
+        #[cfg(not(any(feature = "no_pthread", windows)))]
+        let result = libc::pthread_mutex_trylock(lock) == 0;
+
+        #[cfg(any(feature = "no_pthread", windows))]
+        let result = {
+            let tid = std::thread::current().id().as_u64().get();
+            let old = intrinsics::atomic_cxchg_acqrel(lock, 0, tid).0;
+            old == 0 || old == tid
+        };
+
+        result
+    }
+
+    /// Blocks until the underlying lock is taken, or `timeout` expires. If
+    /// the deadlock detection is enabled and waiting for the lock closes a
+    /// cycle, the transaction is aborted as the victim.
+    unsafe fn wait_lock(&self, timeout: Option<Duration>) -> bool {
+        let detect = A::deadlock_detection();
+        if detect {
+            if self.try_raw() {
+                return true;
+            }
+            if !deadlock::wait(self.lock_addr()) {
+                log!(A, Red, "DEADLOCK", "FOR:          v@{}", self.lock_addr());
+                deadlock::abort();
+            }
+        }
+        let locked = if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            #[cfg(not(any(feature = "no_pthread", windows)))]
+            let mut pause = MIN_PAUSE;
+            loop {
+                if self.try_raw() {
+                    break true;
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break false;
+                }
+                // The thread sleeps with an exponential backoff rather than
+                // spinning until the deadline
+                #[cfg(not(any(feature = "no_pthread", windows)))] {
+                    std::thread::sleep(pause.min(deadline - now));
+                    pause = (pause * 2).min(MAX_PAUSE);
+                }
+                #[cfg(any(feature = "no_pthread", windows))]
+                std::thread::yield_now();
+            }
+        } else {
             let lock = &self.inner.lock.1 as *const _ as *mut _;
             #[cfg(not(any(feature = "no_pthread", windows)))] {
                 libc::pthread_mutex_lock(lock);
@@ -202,9 +266,30 @@ impl<T, A: MemPool> PMutex<T, A> {
                 let tid = std::thread::current().id().as_u64().get();
                 while intrinsics::atomic_cxchg_acqrel(lock, 0, tid).0 != tid {}
             }
+            true
+        };
+        if detect {
+            deadlock::done_waiting();
+        }
+        locked
+    }
+
+    #[inline]
+    fn raw_lock(&self, journal: &Journal<A>, timeout: Option<Duration>) -> bool {
+        unsafe {
+            // Log::unlock_on_failure(self.inner.get(), journal);
+            if !self.wait_lock(timeout) {
+                return false;
+            }
             if self.inner.acquire() {
-                Log::unlock_on_commit(&self.inner.lock as *const _ as u64, journal);
+                Log::unlock_on_commit(self.lock_addr(), journal);
+                if A::deadlock_detection() {
+                    deadlock::acquired(self.lock_addr());
+                }
+                true
             } else {
+                let lock = &self.inner.lock.1 as *const _ as *mut _;
+
                 #[cfg(not(any(feature = "no_pthread", windows)))]
                 libc::pthread_mutex_unlock(lock);
 
@@ -256,10 +341,53 @@ impl<T, A: MemPool> PMutex<T, A> {
     /// [`UnlockOnCommit`]: ../stm/enum.LogEnum.html#variant.UnlockOnCommit
     /// 
     pub fn lock<'a>(&'a self, journal: &'a Journal<A>) -> MutexGuard<'a, T, A> {
-        self.raw_lock(journal);
+        self.raw_lock(journal, None);
         unsafe { MutexGuard::new(self, journal) }
     }
 
+    /// Acquires a mutex, blocking the current thread until it is able to do
+    /// so, or until `timeout` expires.
+    ///
+    /// It is similar to [`lock()`](#method.lock), except that it returns
+    /// [`Err`] if the lock could not be acquired within `timeout`. Since the
+    /// locks are held until the end of the transactions, it may be used to
+    /// give up when the transactions lock mutexes in different orders. The
+    /// transaction may then be aborted (e.g., by panicking) to release its
+    /// locks and be retried.
+    ///
+    /// If the deadlock detection is enabled (see
+    /// [`PoolOptions::deadlock_detection`]) and waiting for the lock causes a
+    /// deadlock, the transaction aborts immediately with a [`Deadlock`] panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::time::Duration;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let obj = P::open::<Parc<PMutex<i32>>>("foo.pool", O_CF).unwrap();
+    ///
+    /// transaction(|j| {
+    ///     if let Ok(mut obj) = obj.lock_timeout(j, Duration::from_millis(10)) {
+    ///         *obj += 1;
+    ///     } else {
+    ///         panic!("timed out");
+    ///     }
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`PoolOptions::deadlock_detection`]: ../alloc/struct.PoolOptions.html#method.deadlock_detection
+    /// [`Deadlock`]: ./struct.Deadlock.html
+    pub fn lock_timeout<'a>(&'a self, journal: &'a Journal<A>, timeout: Duration) -> TryLockResult<MutexGuard<'a, T, A>> {
+        if self.raw_lock(journal, Some(timeout)) {
+            unsafe { Ok(MutexGuard::new(self, journal)) }
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
     #[inline]
     fn raw_trylock(&self, journal: &Journal<A>) -> bool {
         unsafe {
@@ -276,7 +404,10 @@ impl<T, A: MemPool> PMutex<T, A> {
 
             if result {
                 if self.inner.acquire() {
-                    Log::unlock_on_commit(&self.inner.lock as *const _ as u64, journal);
+                    Log::unlock_on_commit(self.lock_addr(), journal);
+                    if A::deadlock_detection() {
+                        deadlock::acquired(self.lock_addr());
+                    }
                     true
                 } else {
                     #[cfg(not(any(feature = "no_pthread", windows)))] 
@@ -346,12 +477,89 @@ impl<T, A: MemPool> PMutex<T, A> {
     }
 }
 
+impl<T, A: MemPool> PMutex<T, A> {
+    #[inline]
+    fn raw_read_lock(&self, journal: &ReadJournal<A>) {
+        unsafe {
+            let lock = &self.inner.lock.1 as *const _ as *mut _;
+            #[cfg(any(feature = "no_pthread", windows))]
+            let held = {
+                let tid = std::thread::current().id().as_u64().get();
+                intrinsics::atomic_load_acq(lock) == tid
+            };
+            self.wait_lock(None);
+            if self.inner.acquire() {
+                // The spin lock is not recursive; it is released by the owner
+                #[cfg(any(feature = "no_pthread", windows))]
+                if held { return; }
+
+                journal.unlock_on_finish(self.lock_addr());
+                if A::deadlock_detection() {
+                    deadlock::acquired(self.lock_addr());
+                }
+            } else {
+                #[cfg(not(any(feature = "no_pthread", windows)))]
+                libc::pthread_mutex_unlock(lock);
+
+                #[cfg(any(feature = "no_pthread", windows))]
+                if !held { intrinsics::atomic_store_rel(lock, 0); }
+
+                panic!("Cannot have multiple instances of MutexGuard");
+            }
+        }
+    }
+
+    /// Acquires a mutex in a read-only transaction, blocking the current
+    /// thread until it is able to do so.
+    ///
+    /// It is similar to [`lock()`](#method.lock), except that it takes a
+    /// [`ReadJournal`] and does not write any log. The returned guard only
+    /// gives shared access to the data. The mutex remains locked until the
+    /// read transaction finishes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use corundum::default::*;
+    /// use std::thread;
+    ///
+    /// type P = Allocator;
+    ///
+    /// let obj = P::open::<Parc<PMutex<i32>>>("foo.pool", O_CF).unwrap();
+    ///
+    /// let a = Parc::demote(&obj);
+    /// thread::spawn(move || {
+    ///     transaction(|j| {
+    ///         if let Some(obj) = a.promote(j) {
+    ///             *obj.lock(j) = 10;
+    ///         }
+    ///     }).unwrap();
+    /// }).join().expect("thread::spawn failed");
+    ///
+    /// P::read_transaction(|j| {
+    ///     assert_eq!(*obj.read_lock(j), 10);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`ReadJournal`]: ../stm/struct.ReadJournal.html
+    pub fn read_lock<'a>(&'a self, journal: &'a ReadJournal<A>) -> MutexReadGuard<'a, T, A> {
+        self.raw_read_lock(journal);
+        MutexReadGuard { lock: self }
+    }
+}
+
 impl<T: RootObj<A>, A: MemPool> RootObj<A> for PMutex<T, A> {
     fn init(journal: &Journal<A>) -> Self {
         PMutex::new(T::init(journal))
     }
 }
 
+impl<T: Trace<A>, A: MemPool> Trace<A> for PMutex<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        unsafe { (*self.data.get()).1.trace(tracer) }
+    }
+}
+
 impl<T: fmt::Debug, A: MemPool> fmt::Debug for PMutex<T, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.data.fmt(f)
@@ -409,6 +617,47 @@ impl<T, A: MemPool> Drop for MutexGuard<'_, T, A> {
     }
 }
 
+/// An RAII guard for shared access to the data of a [`PMutex`] in a read-only
+/// transaction
+///
+/// It is created by [`PMutex::read_lock()`].
+///
+/// [`PMutex`]: ./struct.PMutex.html
+/// [`PMutex::read_lock()`]: ./struct.PMutex.html#method.read_lock
+pub struct MutexReadGuard<'a, T: 'a, A: MemPool> {
+    lock: &'a PMutex<T, A>,
+}
+
+impl<T: ?Sized, A: MemPool> !TxOutSafe for MutexReadGuard<'_, T, A> {}
+impl<T: ?Sized, A: MemPool> !Send for MutexReadGuard<'_, T, A> {}
+unsafe impl<T: Sync, A: MemPool> Sync for MutexReadGuard<'_, T, A> {}
+
+impl<T: fmt::Debug, A: MemPool> fmt::Debug for MutexReadGuard<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display, A: MemPool> fmt::Display for MutexReadGuard<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T, A: MemPool> Deref for MutexReadGuard<'_, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &(*self.lock.data.get()).1 }
+    }
+}
+
+impl<T, A: MemPool> Drop for MutexReadGuard<'_, T, A> {
+    fn drop(&mut self) {
+        self.lock.inner.release()
+    }
+}
+
 #[cfg(not(any(feature = "no_pthread", windows)))]
 pub unsafe fn init_lock(mutex: *mut libc::pthread_mutex_t, attr: *mut libc::pthread_mutexattr_t) {
     *mutex = libc::PTHREAD_MUTEX_INITIALIZER;
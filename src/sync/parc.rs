@@ -631,12 +631,11 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
     /// ```
     ///
     pub fn initialize(arc: &Option<Parc<T, A>>, value: T) -> crate::result::Result<()> {
-        assert!(
-            !Journal::<A>::is_running(),
-            "Parc::initialize() cannot be used inside a transaction"
-        );
+        if Journal::<A>::is_running() {
+            return Err(crate::Error::InTransaction);
+        }
         match arc {
-            Some(_) => Err("already initialized".to_string()),
+            Some(_) => Err(crate::Error::AlreadyInitialized),
             None => if A::valid(arc) {
                 unsafe {
                     let new = A::atomic_new(
@@ -664,7 +663,7 @@ impl<T: PSafe, A: MemPool> Parc<T, A> {
                 }
                 Ok(())
             } else {
-                Err("The object is not in the PM".to_string())
+                Err(crate::Error::NotInPool)
             }
         }
     }
@@ -744,6 +743,15 @@ impl<T: PSafe + ?Sized, A: MemPool> PClone<A> for Parc<T, A> {
     }
 }
 
+impl<T: PSafe + Trace<A> + ?Sized, A: MemPool> Trace<A> for Parc<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        let inner = self.inner();
+        if tracer.mark_obj(&*inner) {
+            inner.value.trace(tracer);
+        }
+    }
+}
+
 impl<T: RootObj<A> + PSafe, A: MemPool> RootObj<A> for Parc<T, A> {
     #[inline]
     default fn init(journal: &Journal<A>) -> Parc<T, A> {
@@ -1087,6 +1095,17 @@ impl<T: PSafe + ?Sized, A: MemPool> PClone<A> for Weak<T, A> {
     }
 }
 
+impl<T: PSafe + Trace<A> + ?Sized, A: MemPool> Trace<A> for Weak<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        if let Some(inner) = self.inner() {
+            // The value is already dropped if there is no strong reference
+            if tracer.mark_obj(&*inner) && inner.counter.strong > 0 {
+                inner.value.trace(tracer);
+            }
+        }
+    }
+}
+
 impl<T: PSafe + ?Sized + fmt::Debug, A: MemPool> fmt::Debug for Weak<T, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "(Weak)")
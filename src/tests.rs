@@ -597,10 +597,35 @@ pub(crate) mod test {
 
     type A = Allocator;
 
+    /// A unique path in the temporary directory for the files which a test
+    /// produces. The file is removed when it goes out of scope.
+    pub(crate) struct TempPath(String);
+
+    impl TempPath {
+        pub(crate) fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("corundum-{}-{}", std::process::id(), name));
+            let path = path.to_string_lossy().to_string();
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+
+        pub(crate) fn as_str(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
     #[test]
     #[ignore]
     fn test_mutex_deadlock() {
-        // Race condition problem (cyclic locks) is still possible
+        // Cyclic locks block forever without deadlock detection (see
+        // `mutex_deadlock_detection`)
 
         struct Root {
             a: PMutex<u32, A>,
@@ -742,6 +767,1140 @@ pub(crate) mod test {
         }
     }
 
+    #[test]
+    fn multiple_instances() {
+        crate::pool!(shards, Allocator, BuddyAlg, 2);
+
+        type S0 = shards::Instance<0>;
+        type S1 = shards::Instance<1>;
+
+        let r0 = S0::open::<crate::Pbox<crate::PCell<i32, S0>, S0>>("shard0.pool", O_CF).unwrap();
+        let r1 = S1::open::<crate::Pbox<crate::PCell<i32, S1>, S1>>("shard1.pool", O_CF).unwrap();
+
+        assert_ne!(S0::start(), S1::start());
+
+        S0::transaction(|j| r0.set(10, j)).unwrap();
+        S1::transaction(|j| r1.set(20, j)).unwrap();
+
+        assert_eq!(r0.get(), 10);
+        assert_eq!(r1.get(), 20);
+    }
+
+    #[test]
+    fn locked_pool_file() {
+        crate::pool!(locked, Allocator, BuddyAlg, 2);
+
+        type L0 = locked::Instance<0>;
+        type L1 = locked::Instance<1>;
+
+        let _p0 = L0::open_no_root("locked.pool", O_CF).unwrap();
+
+        // The file is owned by the first instance
+        assert!(matches!(L1::open_no_root("locked.pool", 0).err(),
+            Some(crate::Error::PoolBusy)));
+        let len = std::fs::metadata("locked.pool").unwrap().len();
+        assert!(matches!(L1::open_no_root("locked.pool", O_CF).err(),
+            Some(crate::Error::PoolBusy)));
+        assert_eq!(std::fs::metadata("locked.pool").unwrap().len(), len);
+        assert!(L0::available() > 0);
+
+        drop(_p0);
+        assert!(L1::open_no_root("locked.pool", 0).is_ok());
+    }
+
+    #[test]
+    fn read_only_pool() {
+        crate::pool!(readers, Allocator, BuddyAlg, 2);
+
+        type R0 = readers::Instance<0>;
+        type R1 = readers::Instance<1>;
+
+        {
+            let root = R0::open::<crate::PCell<i32, R0>>("readers.pool", O_CF).unwrap();
+            R0::transaction(|j| root.set(10, j)).unwrap();
+        }
+
+        // Several readers can share the pool
+        let r0 = R0::open::<crate::PCell<i32, R0>>("readers.pool", O_RDONLY).unwrap();
+        let r1 = R1::open::<crate::PCell<i32, R1>>("readers.pool", O_RDONLY).unwrap();
+        assert!(R0::is_read_only());
+        assert_eq!(r0.get(), 10);
+        assert_eq!(r1.get(), 10);
+        assert!(R0::transaction(|j| r0.set(20, j)).is_err());
+        assert_eq!(r0.get(), 10);
+        assert!(matches!(R0::grow(2 * R0::size()), Err(crate::Error::ReadOnly)));
+        assert!(std::panic::catch_unwind(|| unsafe { R1::alloc(8) }).is_err());
+    }
+
+    #[test]
+    fn read_only_with_writer() {
+        crate::pool!(analytics, Allocator, BuddyAlg, 3);
+
+        type W = analytics::Instance<0>;
+        type R = analytics::Instance<1>;
+        type N = analytics::Instance<2>;
+
+        // The readers do not conflict with the lock of the writer
+        let w = W::open::<crate::PCell<i32, W>>("analytics.pool", O_CF).unwrap();
+        W::transaction(|j| w.set(10, j)).unwrap();
+        let len = std::fs::metadata("analytics.pool").unwrap().len();
+        let image = std::fs::read("analytics.pool").unwrap();
+
+        let r = R::open::<crate::PCell<i32, R>>("analytics.pool", O_RDONLY).unwrap();
+        assert_eq!(r.get(), 10);
+        assert!(R::transaction(|j| r.set(20, j)).is_err());
+        let n = N::open_no_root("analytics.pool", O_READINFO).unwrap();
+        assert_eq!(N::allocator_stats().unwrap().used, W::allocator_stats().unwrap().used);
+        drop(n);
+
+        // Neither of them writes the file
+        assert_eq!(std::fs::read("analytics.pool").unwrap(), image);
+        assert_eq!(std::fs::metadata("analytics.pool").unwrap().len(), len);
+
+        // The writer keeps owning the pool
+        W::transaction(|j| w.set(30, j)).unwrap();
+        assert_eq!(w.get(), 30);
+        assert!(matches!(N::open_no_root("analytics.pool", 0).err(),
+            Some(crate::Error::PoolBusy)));
+    }
+
+    #[test]
+    fn root_migration() {
+        type P = Allocator;
+
+        #[derive(Default)]
+        struct V1 {
+            value: crate::PCell<i32, P>,
+        }
+
+        #[derive(Default)]
+        struct V2 {
+            value: crate::PCell<i64, P>,
+            count: crate::PCell<usize, P>,
+        }
+
+        assert_ne!(crate::fingerprint::<V1, P>(), crate::fingerprint::<V2, P>());
+
+        {
+            let root = P::open::<V1>("migration.pool", O_CF).unwrap();
+            P::transaction(|j| root.value.set(10, j)).unwrap();
+        }
+
+        assert!(matches!(P::open::<V2>("migration.pool", 0).err(),
+            Some(crate::Error::IncompatibleRoot)));
+
+        let mut migrations = crate::Migrations::new();
+        migrations.register(|old: &V1, _| V2 {
+            value: crate::PCell::new(old.value.get() as i64),
+            count: crate::PCell::new(1),
+        });
+        {
+            let root = P::open_with_migrations::<V2>("migration.pool",
+                &crate::PoolOptions::new(), &migrations).unwrap();
+            assert_eq!(root.value.get(), 10);
+            assert_eq!(root.count.get(), 1);
+        }
+
+        // The upgraded root object is persistent
+        let root = P::open::<V2>("migration.pool", 0).unwrap();
+        assert_eq!(root.value.get(), 10);
+        drop(root);
+
+        // The root objects stored with the fingerprints from before the
+        // schemas are accepted, and their fingerprints are updated
+        let mut image = std::fs::read("migration.pool").unwrap();
+        let id = crate::fingerprint::<V2, P>().to_ne_bytes();
+        let pos = image[..64].windows(8).position(|w| w == id).unwrap();
+        image[pos..pos + 8].copy_from_slice(&crate::legacy_fingerprint::<V2, P>().to_ne_bytes());
+        std::fs::write("migration.pool", &image).unwrap();
+        assert_eq!(P::open::<V2>("migration.pool", 0).unwrap().value.get(), 10);
+        let image = std::fs::read("migration.pool").unwrap();
+        assert_eq!(image[pos..pos + 8], id);
+    }
+
+    #[test]
+    fn root_migration_crash() {
+        use crate::sim::*;
+
+        crate::pool!(mig, Allocator);
+        type P = mig::Allocator;
+
+        #[derive(Default)]
+        struct V1 {
+            value: mig::PCell<i32>,
+        }
+
+        #[derive(Default)]
+        struct V2 {
+            value: mig::PCell<i64>,
+            count: mig::PCell<usize>,
+        }
+
+        impl crate::Trace<P> for V1 {
+            fn trace(&self, _: &mut crate::Tracer<P>) {}
+        }
+
+        impl crate::Trace<P> for V2 {
+            fn trace(&self, _: &mut crate::Tracer<P>) {}
+        }
+
+        {
+            let root = P::open::<V1>("migration_crash.pool", O_CF).unwrap();
+            P::transaction(|j| root.value.set(10, j)).unwrap();
+        }
+
+        // Either the old or the upgraded root object is in the pool, and
+        // none of them leaks
+        let check = || {
+            match P::open::<V2>("migration_crash.pool", 0) {
+                Ok(root) => {
+                    if (root.value.get(), root.count.get()) != (10, 1) {
+                        return Err("the new root object is not initialized".into());
+                    }
+                    let report = crate::find_leaks::<P, V2>()?;
+                    if report.is_empty() { Ok(()) } else { Err(report.to_string().into()) }
+                }
+                Err(crate::Error::IncompatibleRoot) => {
+                    let root = P::open::<V1>("migration_crash.pool", 0)?;
+                    if root.value.get() != 10 {
+                        return Err("the old root object is modified".into());
+                    }
+                    let report = crate::find_leaks::<P, V1>()?;
+                    if report.is_empty() { Ok(()) } else { Err(report.to_string().into()) }
+                }
+                Err(e) => Err(e),
+            }
+        };
+
+        let report = CrashHarness::new("migration_crash.pool").run(|| {
+            let mut migrations = crate::Migrations::new();
+            migrations.register(|old: &V1, _| V2 {
+                value: mig::PCell::new(old.value.get() as i64),
+                count: mig::PCell::new(1),
+            });
+            P::open_with_migrations::<V2>("migration_crash.pool",
+                &crate::PoolOptions::new(), &migrations).unwrap();
+        }, check).unwrap();
+        assert!(report.points > 0);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn check_and_repair() {
+        type P = Allocator;
+
+        {
+            let root = P::open::<crate::Pbox<crate::PCell<i32, P>, P>>("fsck.pool", O_CF).unwrap();
+            P::transaction(|j| root.set(10, j)).unwrap();
+        }
+
+        let report = crate::check::<P>("fsck.pool").unwrap();
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.free_blocks > 0);
+
+        let available = {
+            let _p = P::open_no_root("fsck.pool", 0).unwrap();
+            P::available()
+        };
+
+        let report = crate::repair::<P>("fsck.pool").unwrap();
+        assert!(report.is_ok() && report.repaired, "{}", report);
+
+        let root = P::open::<crate::Pbox<crate::PCell<i32, P>, P>>("fsck.pool", 0).unwrap();
+        assert_eq!(root.get(), 10);
+        assert_eq!(P::available(), available);
+        P::transaction(|j| root.set(20, j)).unwrap();
+        assert_eq!(root.get(), 20);
+    }
+
+    #[test]
+    fn snapshot() {
+        crate::pool!(snap, Allocator, BuddyAlg, 2);
+
+        type S0 = snap::Instance<0>;
+        type S1 = snap::Instance<1>;
+
+        let backup = TempPath::new("snap.backup");
+        let root = S0::open::<crate::PCell<i32, S0>>("snap.pool", O_CF).unwrap();
+        S0::transaction(|j| root.set(10, j)).unwrap();
+        assert!(S0::transaction(|_| S0::snapshot(backup.as_str())).unwrap().is_err());
+        S0::snapshot(backup.as_str()).unwrap();
+
+        S0::transaction(|j| root.set(20, j)).unwrap();
+        let pages = S0::snapshot_incremental(backup.as_str()).unwrap();
+        assert!(pages > 0 && pages < S0::size() / 4096);
+        S0::transaction(|j| root.set(30, j)).unwrap();
+
+        // The backup has the state of the last snapshot
+        let backup = S1::open::<crate::PCell<i32, S1>>(backup.as_str(), 0).unwrap();
+        assert_eq!(backup.get(), 20);
+        assert_eq!(root.get(), 30);
+    }
+
+    #[test]
+    fn leak_detection() {
+        crate::pool!(gc, Allocator);
+        type P = gc::Allocator;
+
+        struct Node {
+            next: gc::PRefCell<Option<gc::Prc<Node>>>,
+        }
+
+        impl crate::Trace<P> for Node {
+            fn trace(&self, tracer: &mut crate::Tracer<P>) {
+                self.next.trace(tracer)
+            }
+        }
+
+        impl RootObj<P> for Node {
+            fn init(_j: &gc::Journal) -> Self {
+                Node { next: gc::PRefCell::new(None) }
+            }
+        }
+
+        let root = P::open::<Node>("leak_detection.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let n = gc::Prc::new(Node { next: gc::PRefCell::new(None) }, j);
+            *root.next.borrow_mut(j) = Some(n);
+        }).unwrap();
+        let report = crate::find_leaks::<P, Node>().unwrap();
+        assert!(report.is_empty(), "{}", report);
+        assert!(report.reachable > 2);
+
+        let used = P::used();
+        P::transaction(|j| {
+            // A reference cycle which is not reachable from the root
+            let a = gc::Prc::new(Node { next: gc::PRefCell::new(None) }, j);
+            let b = gc::Prc::new(Node { next: gc::PRefCell::new(Some(a.pclone(j))) }, j);
+            *a.next.borrow_mut(j) = Some(b);
+        }).unwrap();
+        assert!(P::used() > used);
+        let report = crate::find_leaks::<P, Node>().unwrap();
+        assert!(!report.is_empty() && !report.collected);
+        assert_eq!(report.leaked_bytes(), P::used() - used);
+
+        let report = crate::collect_leaks::<P, Node>().unwrap();
+        assert!(report.collected);
+        assert_eq!(P::used(), used);
+        assert!(crate::find_leaks::<P, Node>().unwrap().is_empty());
+        assert!(root.next.borrow().is_some());
+    }
+
+    #[test]
+    fn aligned_vec() {
+        crate::pool!(aligned, Allocator);
+        type P = aligned::Allocator;
+
+        let root = P::open::<aligned::PRefCell<aligned::PVec<u64>>>("aligned_vec.pool", O_CF).unwrap();
+        P::transaction(|j| {
+            let mut vec = root.borrow_mut(j);
+            *vec = aligned::PVec::with_capacity_aligned(2, 1024, j);
+            for i in 0..100 {
+                vec.push(i, j);
+                assert_eq!((P::start() + vec.off()) % 1024, 0);
+            }
+            vec.truncate(3);
+            vec.shrink_to_fit(j);
+            assert_eq!((P::start() + vec.off()) % 1024, 0);
+        }).unwrap();
+
+        // The alignment is kept in the pool
+        P::transaction(|j| {
+            let mut vec = root.borrow_mut(j);
+            vec.extend_from_slice(&[0; 200], j);
+            assert_eq!((P::start() + vec.off()) % 1024, 0);
+            assert_eq!(vec[..3], [0, 1, 2]);
+        }).unwrap();
+    }
+
+    #[test]
+    fn heap_walk() {
+        crate::pool!(typed, Allocator);
+        type P = typed::Allocator;
+
+        let vec_type = std::any::type_name::<[u32]>();
+        let opts = crate::PoolOptions::new().create(true).format(true).type_info(true).clone();
+        {
+            let root = P::open_with::<typed::PRefCell<typed::PVec<u32>>>(
+                "heap_walk.pool", &opts).unwrap();
+            P::transaction(|j| {
+                let mut v = root.borrow_mut(j);
+                for i in 0..10 {
+                    v.push(i, j);
+                }
+                let b = typed::Pbox::new(1u64, j);
+                let items: Vec<_> = P::heap_iter().unwrap().collect();
+                assert!(items.contains(&(b.off(), 8, "u64".to_string())));
+            }).unwrap();
+
+            let items: Vec<_> = P::heap_iter().unwrap().collect();
+            assert!(items.windows(2).all(|w| w[0].0 < w[1].0));
+            assert!(items.iter().all(|(_, _, name)| name != "u64"));
+            assert_eq!(items.iter().filter(|(_, _, name)| name == vec_type).count(), 1);
+            assert!(crate::find_leaks::<P, typed::PRefCell<typed::PVec<u32>>>().unwrap().is_empty());
+        }
+
+        // The records are persistent
+        let _pool = P::open_no_root("heap_walk.pool", 0).unwrap();
+        let items: Vec<_> = P::heap_iter().unwrap().collect();
+        assert_eq!(items.iter().filter(|(_, _, name)| name == vec_type).count(), 1);
+    }
+
+    #[test]
+    fn simulated_crash() {
+        use crate::sim::*;
+
+        crate::pool!(simulated, Allocator);
+        type P = simulated::Allocator;
+        type Root = simulated::PRefCell<simulated::PVec<u64>>;
+
+        let mid = TempPath::new("simulated_crash.mid");
+        let end = TempPath::new("simulated_crash.end");
+        let modes = [CrashMode::Revert, CrashMode::Random(1), CrashMode::Random(2)];
+        for mode in modes.iter() {
+            {
+                let root = P::open::<Root>("simulated_crash.pool", O_CF).unwrap();
+                let sim = PmSim::<P>::start().unwrap();
+                P::transaction(|j| {
+                    root.borrow_mut(j).push(1, j);
+                }).unwrap();
+                P::transaction(|j| {
+                    let mut v = root.borrow_mut(j);
+                    for i in 2..100 {
+                        v.push(i, j);
+                    }
+                    assert!(!sim.unflushed_lines().is_empty());
+                    sim.crash(mid.as_str(), *mode).unwrap();
+                }).unwrap();
+                sim.crash(end.as_str(), *mode).unwrap();
+            }
+
+            // The second transaction is rolled back
+            PmSim::<P>::recover::<Root, _, _>(mid.as_str(), |root| {
+                assert_eq!(root.borrow().as_slice(), &[1]);
+            }).unwrap();
+
+            PmSim::<P>::recover::<Root, _, _>(end.as_str(), |root| {
+                assert_eq!(root.borrow().len(), 99);
+            }).unwrap();
+        }
+    }
+
+    #[test]
+    fn simulated_crash_logs() {
+        use crate::sim::*;
+
+        crate::pool!(simulated_logs, Allocator);
+        type P = simulated_logs::Allocator;
+
+        struct Root {
+            cells: [simulated_logs::PCell<u64>; 8],
+        }
+
+        impl RootObj<P> for Root {
+            fn init(_: &Journal<P>) -> Self {
+                Self { cells: [(); 8].map(|_| simulated_logs::PCell::new(0)) }
+            }
+        }
+
+        let _ = P::open::<Root>("simulated_crash_logs.pool", O_CF).unwrap();
+
+        // The logs spread over many cache lines of the journal page. They
+        // should be visible to the recovery procedure once the logged data
+        // is durable.
+        let check = || {
+            let root = P::open::<Root>("simulated_crash_logs.pool", 0)?;
+            let values: Vec<_> = root.cells.iter().map(|c| c.get()).collect();
+            if values.iter().all(|v| *v == values[0]) {
+                Ok(())
+            } else {
+                Err(format!("{:?}", values).into())
+            }
+        };
+        let report = CrashHarness::new("simulated_crash_logs.pool").run(|| {
+            let root = P::open::<Root>("simulated_crash_logs.pool", 0).unwrap();
+            let _sim = PmSim::<P>::start().unwrap();
+            P::transaction(|j| {
+                for c in &root.cells {
+                    c.set(1, j);
+                }
+            }).unwrap();
+        }, check).unwrap();
+        assert_eq!(report.tested, report.points);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn crash_harness() {
+        use crate::sim::*;
+
+        crate::pool!(harness, Allocator);
+        type P = harness::Allocator;
+        type Root = harness::PCell<[u64; 2]>;
+
+        let _ = P::open::<Root>("crash_harness.pool", O_CF).unwrap();
+        let check = || {
+            let root = P::open::<Root>("crash_harness.pool", 0)?;
+            let [a, b] = root.get();
+            if a == b { Ok(()) } else { Err(format!("{} != {}", a, b).into()) }
+        };
+
+        let report = CrashHarness::new("crash_harness.pool").run(|| {
+            let root = P::open::<Root>("crash_harness.pool", 0).unwrap();
+            P::transaction(|j| root.set([1, 1], j)).unwrap();
+        }, check).unwrap();
+        assert!(report.points > 0);
+        assert_eq!(report.tested, report.points);
+        assert!(report.is_ok(), "{}", report);
+
+        // Updates outside transactions are not failure-atomic
+        let report = CrashHarness::new("crash_harness.pool").run(|| {
+            let root = P::open::<Root>("crash_harness.pool", 0).unwrap();
+            let v = unsafe { root.as_mut() };
+            v[0] = 2;
+            crate::ll::persist_obj(&v[0], true);
+            v[1] = 2;
+            crate::ll::persist_obj(&v[1], true);
+        }, check).unwrap();
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn crash_harness_allocation() {
+        use crate::sim::*;
+
+        crate::pool!(harness_alloc, Allocator);
+        type P = harness_alloc::Allocator;
+        type Root = harness_alloc::PRefCell<Option<harness_alloc::Pbox<u64>>>;
+
+        {
+            let root = P::open::<Root>("crash_harness_alloc.pool", O_CF).unwrap();
+            P::transaction(|j| *root.borrow_mut(j) = Some(harness_alloc::Pbox::new(1, j))).unwrap();
+        }
+
+        // The journal pages, the log copies, and the new and the old boxes
+        // are neither leaked nor left in the free lists
+        let check = || {
+            let root = P::open::<Root>("crash_harness_alloc.pool", 0)?;
+            match root.borrow().as_ref().map(|b| **b) {
+                Some(1) | Some(2) => {}
+                v => return Err(format!("unexpected value {:?}", v).into()),
+            }
+            let report = crate::find_leaks::<P, Root>()?;
+            if report.is_empty() { Ok(()) } else { Err(report.to_string().into()) }
+        };
+
+        let report = CrashHarness::new("crash_harness_alloc.pool").run(|| {
+            let root = P::open::<Root>("crash_harness_alloc.pool", 0).unwrap();
+            P::transaction(|j| *root.borrow_mut(j) = Some(harness_alloc::Pbox::new(2, j))).unwrap();
+        }, check).unwrap();
+        assert_eq!(report.tested, report.points);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn redo_logging() {
+        use crate::sim::*;
+
+        crate::pool!(redo, Allocator);
+        type P = redo::Allocator;
+        type Root = redo::PRefCell<[u64; 2]>;
+
+        let opts = crate::PoolOptions::new().redo_log(true).clone();
+        let root = P::open_with::<Root>("redo.pool", crate::PoolOptions::new()
+            .create(true).format(true).redo_log(true)).unwrap();
+        assert!(P::redo_logging());
+
+        P::transaction(|j| {
+            *root.borrow_mut(j) = [1, 1];
+            assert_eq!(*root.borrow(), [1, 1]);
+        }).unwrap();
+        assert_eq!(*root.borrow(), [1, 1]);
+
+        assert!(P::transaction(|j| {
+            *root.borrow_mut(j) = [2, 2];
+            panic!("abort");
+        }).is_err());
+        assert_eq!(*root.borrow(), [1, 1]);
+        drop(root);
+
+        let check = || {
+            let root = P::open::<Root>("redo.pool", 0)?;
+            let [a, b] = *root.borrow();
+            if a == b { Ok(()) } else { Err(format!("{} != {}", a, b).into()) }
+        };
+        let report = CrashHarness::new("redo.pool").run(|| {
+            let root = P::open_with::<Root>("redo.pool", &opts).unwrap();
+            P::transaction(|j| {
+                let mut v = root.borrow_mut(j);
+                v[0] = 3;
+                v[1] = 3;
+            }).unwrap();
+        }, check).unwrap();
+        assert_eq!(report.tested, report.points);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn redo_logging_mixed() {
+        use crate::sim::*;
+
+        crate::pool!(redo_mixed, Allocator);
+        type P = redo_mixed::Allocator;
+
+        struct Root {
+            redo: redo_mixed::PRefCell<u64>,
+            undo: redo_mixed::PMutex<u64>,
+        }
+
+        impl RootObj<P> for Root {
+            fn init(_: &Journal<P>) -> Self {
+                Self {
+                    redo: redo_mixed::PRefCell::new(0),
+                    undo: redo_mixed::PMutex::new(0),
+                }
+            }
+        }
+
+        let opts = crate::PoolOptions::new().redo_log(true).clone();
+        let _ = P::open_with::<Root>("redo_mixed.pool", crate::PoolOptions::new()
+            .create(true).format(true).redo_log(true)).unwrap();
+
+        // The undo-logged data is not rolled back after the commit point of
+        // the redo logs, so it should be durable by then
+        let check = || {
+            let root = P::open::<Root>("redo_mixed.pool", 0)?;
+            let redo = *root.redo.borrow();
+            let undo = P::transaction(|j| *root.undo.lock(j))?;
+            if redo == undo { Ok(()) } else { Err(format!("{} != {}", redo, undo).into()) }
+        };
+        let report = CrashHarness::new("redo_mixed.pool").run(|| {
+            let root = P::open_with::<Root>("redo_mixed.pool", &opts).unwrap();
+            let _sim = PmSim::<P>::start().unwrap();
+            P::transaction(|j| {
+                *root.undo.lock(j) = 1;
+                *root.redo.borrow_mut(j) = 1;
+            }).unwrap();
+        }, check).unwrap();
+        assert_eq!(report.tested, report.points);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn savepoints() {
+        crate::pool!(sp, Allocator);
+        type P = sp::Allocator;
+        type Root = sp::PRefCell<Option<sp::Pbox<u64>>>;
+
+        for &redo in &[false, true] {
+            let root = P::open_with::<Root>("savepoints.pool", crate::PoolOptions::new()
+                .create(true).format(true).redo_log(redo)).unwrap();
+            let get = || root.borrow().as_ref().map(|b| **b);
+
+            P::transaction(|j| {
+                *root.borrow_mut(j) = Some(sp::Pbox::new(1, j));
+                let res = j.savepoint(|j| {
+                    *root.borrow_mut(j) = Some(sp::Pbox::new(2, j));
+                    assert_eq!(get(), Some(2));
+                    panic!("fall back");
+                });
+                assert!(res.is_err());
+                assert_eq!(get(), Some(1));
+
+                // A failed nested transaction does not abort the outer one
+                assert!(P::transaction(|j| {
+                    *root.borrow_mut(j) = Some(sp::Pbox::new(3, j));
+                    panic!("fall back");
+                }).is_err());
+                assert_eq!(get(), Some(1));
+
+                P::transaction(|j| {
+                    *root.borrow_mut(j) = Some(sp::Pbox::new(4, j));
+                }).unwrap();
+                assert_eq!(get(), Some(4));
+            }).unwrap();
+
+            assert_eq!(get(), Some(4));
+            assert!(crate::find_leaks::<P, Root>().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn read_transactions() {
+        crate::pool!(rtx, Allocator);
+        type P = rtx::Allocator;
+
+        {
+            let root = P::open::<rtx::Parc<rtx::PMutex<i32>>>("read_tx.pool", O_CF).unwrap();
+            P::transaction(|j| *root.lock(j) = 10).unwrap();
+
+            assert_eq!(P::read_transaction(|j| {
+                assert!(!Journal::<P>::is_running());
+                let v = root.read_lock(j);
+                assert_eq!(j.locks(), 1);
+                *v
+            }).unwrap(), 10);
+
+            // The locks are released if the body panics
+            assert!(P::read_transaction(|j| {
+                let _v = root.read_lock(j);
+                panic!("fall back");
+            }).is_err());
+
+            let a = rtx::Parc::demote(&root);
+            std::thread::spawn(move || {
+                P::transaction(|j| {
+                    if let Some(obj) = a.promote(j) {
+                        *obj.lock(j) += 1;
+                    }
+                }).unwrap();
+            }).join().unwrap();
+        }
+
+        // Read transactions are allowed in read-only pools
+        let root = P::open::<rtx::Parc<rtx::PMutex<i32>>>("read_tx.pool", O_RDONLY).unwrap();
+        assert!(P::transaction(|j| *root.lock(j) = 20).is_err());
+        assert_eq!(P::read_transaction(|j| *root.read_lock(j)).unwrap(), 11);
+    }
+
+    #[test]
+    fn commit_abort_callbacks() {
+        use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+        crate::pool!(cb, Allocator);
+        type P = cb::Allocator;
+
+        static COMMITS: AtomicUsize = AtomicUsize::new(0);
+        static ABORTS: AtomicUsize = AtomicUsize::new(0);
+
+        let chaperon = TempPath::new("callbacks.chaperon");
+        let root = P::open::<cb::PCell<i32>>("callbacks.pool", O_CF).unwrap();
+
+        P::transaction(|j| {
+            root.set(1, j);
+            j.on_commit(|| { COMMITS.fetch_add(1, SeqCst); });
+            j.on_abort(|| { ABORTS.fetch_add(1, SeqCst); });
+
+            // The callbacks of a failed nested scope are resolved early
+            assert!(j.savepoint(|j| {
+                j.on_commit(|| { COMMITS.fetch_add(10, SeqCst); });
+                j.on_abort(|| { ABORTS.fetch_add(10, SeqCst); });
+                panic!("fall back");
+            }).is_err());
+            assert_eq!(ABORTS.load(SeqCst), 10);
+            assert_eq!(COMMITS.load(SeqCst), 0);
+        }).unwrap();
+        assert_eq!(COMMITS.load(SeqCst), 1);
+        assert_eq!(ABORTS.load(SeqCst), 10);
+
+        assert!(P::transaction(|j| {
+            root.set(2, j);
+            j.on_commit(|| { COMMITS.fetch_add(1, SeqCst); });
+            j.on_abort(|| { ABORTS.fetch_add(1, SeqCst); });
+            panic!("abort");
+        }).is_err());
+        assert_eq!(root.get(), 1);
+        assert_eq!(COMMITS.load(SeqCst), 1);
+        assert_eq!(ABORTS.load(SeqCst), 11);
+
+        // Chaperoned transactions run their callbacks when the session ends
+        Chaperon::session(chaperon.as_str(), || {
+            P::transaction(|j| {
+                root.set(3, j);
+                j.on_commit(|| { COMMITS.fetch_add(1, SeqCst); });
+            }).unwrap();
+            assert_eq!(COMMITS.load(SeqCst), 1);
+        }).unwrap();
+        assert_eq!(root.get(), 3);
+        assert_eq!(COMMITS.load(SeqCst), 2);
+    }
+
+    #[test]
+    fn group_commit() {
+        crate::pool!(gc, Allocator);
+        type P = gc::Allocator;
+        type Root = gc::Parc<gc::PMutex<u64>>;
+
+        let root = P::open_with::<Root>("group_commit.pool", crate::PoolOptions::new()
+            .create(true).format(true).group_commit(true)).unwrap();
+        assert!(P::commit_group().is_some());
+
+        let mut threads = vec![];
+        for _ in 0..4 {
+            let root = gc::Parc::demote(&root);
+            threads.push(std::thread::spawn(move || {
+                for _ in 0..100 {
+                    P::transaction(|j| {
+                        if let Some(root) = root.promote(j) {
+                            *root.lock(j) += 1;
+                        }
+                        // Deallocations are performed after the group commits
+                        let _ = gc::Pbox::new(0u64, j);
+                    }).unwrap();
+                }
+            }));
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(P::transaction(|j| *root.lock(j)).unwrap(), 400);
+        assert!(crate::find_leaks::<P, Root>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn group_commit_crash() {
+        use crate::sim::*;
+
+        crate::pool!(gcc, Allocator);
+        type P = gcc::Allocator;
+        type Root = gcc::PRefCell<Option<gcc::Pbox<u64>>>;
+
+        let opts = crate::PoolOptions::new().group_commit(true).clone();
+        {
+            let root = P::open_with::<Root>("group_commit_crash.pool", crate::PoolOptions::new()
+                .create(true).format(true).group_commit(true)).unwrap();
+            P::transaction(|j| *root.borrow_mut(j) = Some(gcc::Pbox::new(1, j))).unwrap();
+        }
+
+        // The old box is dropped on commit by the owner of the journal after
+        // the leader marks the group committed. If it crashes in between, the
+        // recovery procedure drops it.
+        let check = || {
+            let root = P::open::<Root>("group_commit_crash.pool", 0)?;
+            match root.borrow().as_ref().map(|b| **b) {
+                Some(1) | Some(2) => {}
+                v => return Err(format!("unexpected value {:?}", v).into()),
+            }
+            let report = crate::find_leaks::<P, Root>()?;
+            if report.is_empty() { Ok(()) } else { Err(report.to_string().into()) }
+        };
+
+        let report = CrashHarness::new("group_commit_crash.pool").run(|| {
+            let root = P::open_with::<Root>("group_commit_crash.pool", &opts).unwrap();
+            P::transaction(|j| *root.borrow_mut(j) = Some(gcc::Pbox::new(2, j))).unwrap();
+        }, check).unwrap();
+        assert_eq!(report.tested, report.points);
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    #[test]
+    fn journal_page_cache() {
+        crate::pool!(jc, Allocator);
+        type P = jc::Allocator;
+        type Root = jc::PCell<u64>;
+
+        let _root = P::open_with::<Root>("journal_cache.pool", crate::PoolOptions::new()
+            .create(true).format(true).journal_page_slots(4).journal_cache(8)).unwrap();
+        assert_eq!(P::journal_page_slots(), 4);
+
+        let cached_pages = || unsafe {
+            Journal::<P>::try_current().map(|j| (*j.0).page_blocks().len())
+        };
+
+        // 10 allocations and 10 deallocations take 5 pages
+        P::transaction(|j| {
+            let _v: Vec<_> = (0..10).map(|_| jc::Pbox::new(0u64, j)).collect();
+        }).unwrap();
+        assert_eq!(cached_pages(), Some(5));
+
+        // The unused pages are trimmed when the thread stays with small
+        // transactions
+        for _ in 0..100 {
+            P::transaction(|j| {
+                let _ = jc::Pbox::new(0u64, j);
+            }).unwrap();
+        }
+        assert_eq!(cached_pages(), Some(1));
+        assert!(crate::find_leaks::<P, Root>().unwrap().is_empty());
+
+        P::trim_journal_cache();
+        assert_eq!(cached_pages(), None);
+        assert!(crate::find_leaks::<P, Root>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn coalesced_data_logs() {
+        crate::pool!(cl, Allocator);
+        type P = cl::Allocator;
+
+        struct Root {
+            cells: cl::PRefCell<(cl::PCell<u64>, cl::PCell<u64>)>,
+            arr: cl::PRefCell<[u64; 8]>,
+        }
+
+        impl crate::Trace<P> for Root {
+            fn trace(&self, _: &mut crate::Tracer<P>) {}
+        }
+        impl RootObj<P> for Root {
+            fn init(_: &cl::Journal) -> Self {
+                Root {
+                    cells: cl::PRefCell::new((cl::PCell::new(0), cl::PCell::new(0))),
+                    arr: cl::PRefCell::new([0; 8]),
+                }
+            }
+        }
+
+        let root = P::open::<Root>("coalesced_logs.pool", O_CF).unwrap();
+
+        // The cells are covered by the log of the outer cell
+        assert!(P::transaction(|j| {
+            let cells = root.cells.borrow_mut(j);
+            cells.0.set(1, j);
+            cells.1.set(2, j);
+            panic!("abort");
+        }).is_err());
+        let cells = root.cells.borrow();
+        assert_eq!((cells.0.get(), cells.1.get()), (0, 0));
+        drop(cells);
+
+        P::transaction(|j| {
+            let cells = root.cells.borrow_mut(j);
+            cells.0.set(3, j);
+            cells.1.set(4, j);
+        }).unwrap();
+        let cells = root.cells.borrow();
+        assert_eq!((cells.0.get(), cells.1.get()), (3, 4));
+        drop(cells);
+
+        // The owners are notified to take new logs in the next transactions
+        assert!(P::transaction(|j| {
+            root.cells.borrow().0.set(5, j);
+            panic!("abort");
+        }).is_err());
+        assert_eq!(root.cells.borrow().0.get(), 3);
+
+        // Only the part of the second slice which is not logged is copied
+        assert!(P::transaction(|j| {
+            let arr = root.arr.borrow();
+            Log::create_slice(&arr[0..4], j, Notifier::None);
+            Log::create_slice(&arr[2..8], j, Notifier::None);
+            let p = arr.as_ptr() as *mut u64;
+            for i in 0..8 {
+                unsafe { *p.add(i) = i as u64 + 1; }
+            }
+            panic!("abort");
+        }).is_err());
+        assert_eq!(*root.arr.borrow(), [0; 8]);
+
+        assert!(crate::find_leaks::<P, Root>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn mutex_deadlock_detection() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+        use std::time::Duration;
+
+        crate::pool!(dl, Allocator);
+        type P = dl::Allocator;
+
+        struct Root {
+            a: dl::PMutex<u32>,
+            b: dl::PMutex<u32>,
+        }
+        impl RootObj<P> for Root {
+            fn init(_: &dl::Journal) -> Self {
+                Root {
+                    a: dl::PMutex::new(0),
+                    b: dl::PMutex::new(0),
+                }
+            }
+        }
+
+        static READY: AtomicUsize = AtomicUsize::new(0);
+        static ABORTS: AtomicUsize = AtomicUsize::new(0);
+        static HELD: AtomicBool = AtomicBool::new(false);
+        static RELEASE: AtomicBool = AtomicBool::new(false);
+
+        let root = P::open_with::<Root>("deadlock.pool", crate::PoolOptions::new()
+            .create(true).format(true).deadlock_detection(true)).unwrap();
+        assert!(P::deadlock_detection());
+
+        // The transactions lock the mutexes in different orders; one of them
+        // is aborted, and it succeeds on retry
+        let mut threads = vec![];
+        for i in 0..2 {
+            let root = root.clone();
+            threads.push(std::thread::spawn(move || {
+                while let Err(e) = P::transaction(|j| {
+                    let (first, second) = if i == 0 {
+                        (&root.a, &root.b)
+                    } else {
+                        (&root.b, &root.a)
+                    };
+                    *first.lock(j) += 1;
+                    READY.fetch_add(1, SeqCst);
+                    while READY.load(SeqCst) < 2 {}
+                    *second.lock(j) += 1;
+                }) {
+                    assert!(matches!(e, crate::Error::Deadlock));
+                    ABORTS.fetch_add(1, SeqCst);
+                }
+            }));
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(ABORTS.load(SeqCst), 1);
+        assert_eq!(P::transaction(|j| (*root.a.lock(j), *root.b.lock(j))).unwrap(), (2, 2));
+
+        // A lock held by another transaction times out
+        let t = {
+            let root = root.clone();
+            std::thread::spawn(move || {
+                P::transaction(|j| {
+                    *root.a.lock(j) += 1;
+                    HELD.store(true, SeqCst);
+                    while !RELEASE.load(SeqCst) {}
+                }).unwrap();
+            })
+        };
+        while !HELD.load(SeqCst) {}
+        assert!(P::transaction(|j| {
+            root.a.lock_timeout(j, Duration::from_millis(10)).is_err()
+        }).unwrap());
+        RELEASE.store(true, SeqCst);
+        t.join().unwrap();
+        assert_eq!(P::transaction(|j| {
+            *root.a.lock_timeout(j, Duration::from_secs(10)).unwrap()
+        }).unwrap(), 3);
+    }
+
+    #[test]
+    fn transaction_retry() {
+        use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+        use std::time::Duration;
+
+        crate::pool!(rt, Allocator);
+        type P = rt::Allocator;
+
+        static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+        let root = P::open::<rt::PCell<i32>>("retry.pool", O_CF).unwrap();
+
+        let mut policy = crate::RetryPolicy::new();
+        policy.max_attempts(4).backoff(Duration::from_micros(1), Duration::from_micros(10));
+        assert_eq!(policy.delay(1), Duration::from_micros(1));
+        assert_eq!(policy.delay(3), Duration::from_micros(4));
+        assert_eq!(policy.delay(10), Duration::from_micros(10));
+
+        // The body runs again until it succeeds
+        P::transaction_with_retry(&policy, |j| {
+            root.set(root.get() + 1, j);
+            if ATTEMPTS.fetch_add(1, SeqCst) < 2 {
+                j.retry();
+            }
+        }).unwrap();
+        assert_eq!(ATTEMPTS.swap(0, SeqCst), 3);
+        assert_eq!(root.get(), 1);
+
+        // It gives up after the maximum number of attempts
+        assert!(P::transaction_with_retry(&policy, |j| {
+            ATTEMPTS.fetch_add(1, SeqCst);
+            root.set(10, j);
+            j.retry();
+        }).is_err());
+        assert_eq!(ATTEMPTS.swap(0, SeqCst), 4);
+        assert_eq!(root.get(), 1);
+
+        // Other failures are not retried
+        assert!(P::transaction_with_retry(&policy, |j| {
+            ATTEMPTS.fetch_add(1, SeqCst);
+            root.set(10, j);
+            panic!("not retryable");
+        }).is_err());
+        assert_eq!(ATTEMPTS.swap(0, SeqCst), 1);
+        assert_eq!(root.get(), 1);
+    }
+
+    #[test]
+    fn typed_errors() {
+        use crate::Error;
+
+        crate::pool!(te, Allocator);
+        type P = te::Allocator;
+
+        let _ = std::fs::remove_file("typed_errors.pool");
+        assert!(matches!(P::open_no_root("typed_errors.pool", 0),
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound));
+        assert!(matches!(P::snapshot("typed_errors.backup"), Err(Error::PoolNotOpen)));
+        assert!(matches!(P::allocator_stats(), Err(Error::PoolNotOpen)));
+        assert!(matches!(crate::alloc::heap::Heap::allocator_stats(), Err(Error::Unsupported)));
+
+        // A file which is not a pool image is rejected without panicking
+        std::fs::write("typed_errors.pool", vec![0xab; 4096]).unwrap();
+        assert!(matches!(P::open_no_root("typed_errors.pool", 0), Err(Error::InvalidMagic)));
+        assert!(matches!(P::open_no_root_with("typed_errors.pool",
+            PoolOptions::new().create(true).zones(0)), Err(Error::InvalidOptions(_))));
+
+        {
+            let root = P::open::<te::PCell<i32>>("typed_errors.pool", O_CF).unwrap();
+
+            let e = P::transaction(|j| {
+                root.set(10, j);
+                panic!("abort");
+            }).unwrap_err();
+            assert_eq!(e.panic_message(), Some("abort"));
+            assert_eq!(e.to_string(), "Unsuccessful transaction: abort");
+            assert!(!e.is_retryable());
+            assert_eq!(root.get(), 0);
+
+            // The failure of a nested transaction is returned to the outer one
+            P::transaction(|_| {
+                let e = P::transaction(|_| panic!("nested")).unwrap_err();
+                assert_eq!(e.panic_message(), Some("nested"));
+            }).unwrap();
+
+            let e = P::transaction(|j| { j.retry(); }).unwrap_err();
+            assert!(matches!(e, Error::TxAborted { .. }));
+            assert!(e.is_retryable());
+
+            // An allocation which does not fit aborts with a typed error
+            let e = P::transaction(|j| {
+                te::PVec::<u8>::with_capacity(2 * P::size(), j);
+            }).unwrap_err();
+            assert!(matches!(e, Error::OutOfMemory));
+            assert!(e.is_retryable());
+        }
+
+        assert!(matches!(P::open::<te::PCell<u64>>("typed_errors.pool", 0),
+            Err(Error::IncompatibleRoot)));
+
+        let root = P::open_with::<te::PCell<i32>>("typed_errors.pool",
+            PoolOptions::new().read_only(true)).unwrap();
+        assert!(matches!(P::transaction(|j| root.set(1, j)), Err(Error::ReadOnly)));
+        let used = P::allocator_stats().unwrap().used;
+        drop(root);
+
+        // The statistics of a pool opened for its information are read
+        // without the allocator lock
+        let _p = P::open_no_root("typed_errors.pool", O_READINFO).unwrap();
+        assert_eq!(P::allocator_stats().unwrap().used, used);
+    }
+
+    #[test]
+    fn format_version() {
+        use crate::Error;
+
+        crate::pool!(fv, Allocator);
+        type P = fv::Allocator;
+
+        drop(P::open_no_root("format_version.pool", O_CF).unwrap());
+        let mut image = std::fs::read("format_version.pool").unwrap();
+        assert_eq!(image[8..12], crate::FORMAT_VERSION.to_ne_bytes());
+
+        // An image of another version is refused rather than misread
+        image[8..12].copy_from_slice(&(crate::FORMAT_VERSION + 1).to_ne_bytes());
+        std::fs::write("format_version.pool", &image).unwrap();
+        assert!(matches!(P::open_no_root("format_version.pool", 0),
+            Err(Error::UnsupportedVersion(v)) if v == crate::FORMAT_VERSION + 1));
+        let report = crate::check::<P>("format_version.pool").unwrap();
+        assert!(!report.is_ok());
+
+        // Only its information can be read
+        assert!(P::open_no_root("format_version.pool", O_READINFO).is_ok());
+    }
+
     #[test]
     fn test_maybe_null_drop() {
         struct D {
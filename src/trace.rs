@@ -0,0 +1,272 @@
+//! The `Trace` trait for enumerating persistent pointers, and a
+//! reachability-based leak detector
+
+use crate::alloc::MemPool;
+use crate::result::Result;
+use crate::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A trait for types that can enumerate the persistent allocations they own
+///
+/// It is used to walk the object graph starting from the root object and find
+/// the allocations which are not reachable from it. Such allocations are
+/// leaked, e.g. because of cyclic [`Prc`] references. The implementation
+/// passes every persistent pointer to the [`Tracer`], and traces the pointee
+/// only if the [`Tracer`] has not visited it before.
+///
+/// ## Derivable
+///
+/// This trait can be used with `#[derive]` if all fields are `Trace`. The
+/// `derive`d implementation of `Trace` calls [`trace`] on each field. It uses
+/// [`default::Allocator`] by default. It is possible to change the pool
+/// type(s) by using `pools()` attribute.
+///
+/// ```
+/// # use corundum::default::*;
+/// # type P = Allocator;
+/// #[derive(Trace)]
+/// struct Node {
+///     value: i32,
+///     next: PRefCell<Option<Prc<Node>>>,
+/// }
+/// ```
+///
+/// ## How can I implement `Trace`?
+///
+/// Types that do not hold any persistent pointer should have an empty
+/// implementation. Otherwise, a pointer type should mark its allocation using
+/// [`Tracer::mark`], and trace its content only if `mark` returns `true`.
+/// There is no blanket implementation, so that a type which is not traced
+/// does not hide leaks.
+///
+/// [`Prc`]: ../prc/struct.Prc.html
+/// [`Tracer`]: ./struct.Tracer.html
+/// [`Tracer::mark`]: ./struct.Tracer.html#method.mark
+/// [`trace`]: #tymethod.trace
+/// [`default::Allocator`]: ../default/struct.Allocator.html
+pub trait Trace<A: MemPool> {
+    /// Passes the persistent pointers of `self` to `tracer`
+    fn trace(&self, tracer: &mut Tracer<A>);
+}
+
+/// A collection of the reachable allocations of a pool
+///
+/// It is used by [`Trace`] implementations to mark the visited blocks.
+///
+/// [`Trace`]: ./trait.Trace.html
+pub struct Tracer<A: MemPool> {
+    blocks: BTreeMap<u64, usize>,
+    phantom: PhantomData<A>,
+}
+
+impl<A: MemPool> Tracer<A> {
+    /// Creates an empty tracer
+    pub fn new() -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Marks the allocation at offset `off` with the size of `len` as reachable
+    ///
+    /// It returns `true` if the block is not marked before, in which case its
+    /// content should be traced. Null pointers and zero-sized blocks are
+    /// ignored.
+    pub fn mark(&mut self, off: u64, len: usize) -> bool {
+        if off == u64::MAX || len == 0 || self.blocks.contains_key(&off) {
+            false
+        } else {
+            self.blocks.insert(off, len);
+            true
+        }
+    }
+
+    /// Marks the allocation of the persistent object `obj` as reachable
+    ///
+    /// See [`mark`](#method.mark) for more details.
+    pub fn mark_obj<T: ?Sized>(&mut self, obj: &T) -> bool {
+        match A::off(obj) {
+            Ok(off) => self.mark(off, std::mem::size_of_val(obj)),
+            Err(_) => false,
+        }
+    }
+
+    /// Traces `obj`
+    #[inline]
+    pub fn trace<T: Trace<A> + ?Sized>(&mut self, obj: &T) {
+        obj.trace(self)
+    }
+
+    /// Returns the reachable blocks as `(offset, len)` pairs sorted by offset
+    pub fn blocks(&self) -> Vec<(u64, usize)> {
+        self.blocks.iter().map(|(off, len)| (*off, *len)).collect()
+    }
+}
+
+impl<A: MemPool> Default for Tracer<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Trace<A>, A: MemPool> Trace<A> for Option<T> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        if let Some(x) = self {
+            x.trace(tracer)
+        }
+    }
+}
+
+impl<T: Trace<A>, A: MemPool> Trace<A> for [T] {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        for x in self {
+            x.trace(tracer)
+        }
+    }
+}
+
+impl<T: Trace<A>, A: MemPool, const N: usize> Trace<A> for [T; N] {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        for x in self {
+            x.trace(tracer)
+        }
+    }
+}
+
+impl<T: ?Sized, A: MemPool> Trace<A> for PhantomData<T> {
+    #[inline]
+    fn trace(&self, _tracer: &mut Tracer<A>) {}
+}
+
+use impl_trait_for_tuples::*;
+
+#[impl_for_tuples(32)]
+impl<A: MemPool> Trace<A> for Tuple {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        for_tuples!( #( Tuple.trace(tracer); )* );
+    }
+}
+
+/// Implementations of `Trace` for primitive types.
+mod impls {
+
+    use super::{Trace, Tracer};
+    use crate::alloc::MemPool;
+
+    macro_rules! impl_trace {
+        ($($t:ty),*) => {
+            $(
+                impl<A: MemPool> Trace<A> for $t {
+                    #[inline]
+                    fn trace(&self, _tracer: &mut Tracer<A>) {}
+                }
+            )*
+        }
+    }
+
+    impl_trace! {
+        usize, u8, u16, u32, u64, u128,
+        isize, i8, i16, i32, i64, i128,
+        f32, f64,
+        bool, char
+    }
+}
+
+/// The result of looking for the unreachable allocations of a pool
+///
+/// It is returned by [`find_leaks()`] and [`collect_leaks()`].
+///
+/// [`find_leaks()`]: ./fn.find_leaks.html
+/// [`collect_leaks()`]: ./fn.collect_leaks.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LeakReport {
+    /// The leaked blocks as `(offset, len)` pairs sorted by offset
+    pub leaks: Vec<(u64, usize)>,
+
+    /// Number of the reachable blocks
+    pub reachable: usize,
+
+    /// Shows that the leaked blocks are freed
+    pub collected: bool,
+}
+
+impl LeakReport {
+    /// Returns true if no leak is found
+    pub fn is_empty(&self) -> bool {
+        self.leaks.is_empty()
+    }
+
+    /// Returns the total size of the leaked blocks in bytes
+    pub fn leaked_bytes(&self) -> usize {
+        self.leaks.iter().map(|(_, len)| len).sum()
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Reachable blocks: {}", self.reachable)?;
+        for (off, len) in &self.leaks {
+            writeln!(f, "leak: 0x{:x} ({} bytes)", off, len)?;
+        }
+        write!(f, "Leaked: {} bytes in {} blocks{}", self.leaked_bytes(),
+            self.leaks.len(), if self.collected { " (collected)" } else { "" })
+    }
+}
+
+/// Finds the allocations of an open pool which are not reachable from its
+/// root object of type `U`
+///
+/// The object graph is traced from the root object, and the allocator
+/// metadata and the journals are considered reachable. It waits until no
+/// transaction is running, and keeps new transactions from starting until
+/// the tracing is done. The root type should be the same as the one used to
+/// open the pool.
+///
+/// # Examples
+///
+/// ```
+/// # use corundum::default::*;
+/// # type P = Allocator;
+/// #[derive(Trace)]
+/// struct Node {
+///     next: PRefCell<Option<Prc<Node>>>,
+/// }
+///
+/// impl RootObj<P> for Node {
+///     fn init(_j: &Journal) -> Self {
+///         Node { next: PRefCell::new(None) }
+///     }
+/// }
+///
+/// let _root = P::open::<Node>("foo.pool", O_CF).unwrap();
+/// P::transaction(|j| {
+///     // A reference cycle which is not reachable from the root
+///     let a = Prc::new(Node { next: PRefCell::new(None) }, j);
+///     let b = Prc::new(Node { next: PRefCell::new(Some(a.pclone(j))) }, j);
+///     *a.next.borrow_mut(j) = Some(b);
+/// }).unwrap();
+///
+/// let report = corundum::find_leaks::<P, Node>().unwrap();
+/// assert!(!report.is_empty(), "{}", report);
+///
+/// corundum::collect_leaks::<P, Node>().unwrap();
+/// assert!(corundum::find_leaks::<P, Node>().unwrap().is_empty());
+/// ```
+pub fn find_leaks<P: MemPool, U: PSafe + RootObj<P> + Trace<P>>() -> Result<LeakReport> {
+    P::leaks::<U>(false)
+}
+
+/// Finds the unreachable allocations of an open pool and frees them in a
+/// transaction
+///
+/// See [`find_leaks()`](./fn.find_leaks.html) for more details. The leaked
+/// objects are not dropped. Objects which are only referenced by volatile
+/// pointers, such as the ones from [`Pbox::into_raw`], are also freed.
+///
+/// [`Pbox::into_raw`]: ./struct.Pbox.html#method.into_raw
+pub fn collect_leaks<P: MemPool, U: PSafe + RootObj<P> + Trace<P>>() -> Result<LeakReport> {
+    P::leaks::<U>(true)
+}
@@ -1,6 +1,10 @@
 use std::fmt::{Debug, Error, Formatter};
 use std::fs::File;
 use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "verbose")]
+use std::sync::atomic::AtomicBool;
 
 #[cfg(not(feature = "no_flush_alloc"))]
 use crate::ll::*;
@@ -12,7 +16,8 @@ pub fn rand() -> i64 {
     i64::from_be_bytes(buf)
 }
 
-static mut CRASH_PROB: Option<u64> = None;
+/// The probability of crashing at `may_crash!()` in 1/10000
+static CRASH_PROB: AtomicU64 = AtomicU64::new(0);
 
 #[macro_export]
 macro_rules! may_crash {
@@ -26,25 +31,22 @@ macro_rules! may_crash {
 
 #[inline]
 pub fn can_crash() -> bool {
-    unsafe {
-        if let Some(p) = CRASH_PROB {
-            if p == 0 {
-                return false;
-            } else {
-                let r: u64 = rand::random();
-                return r % 10000 == 0;
-            }
-        } else {
-            let p = std::env::var("CRASH_PROB")
-                .unwrap_or("0".to_string())
-                .parse::<u64>()
-                .expect("CRASH_PROB should be a non-negative integer");
-            CRASH_PROB = Some(p);
-        }
-        can_crash()
+    let p = CRASH_PROB.load(Ordering::Relaxed);
+    if p == 0 {
+        false
+    } else {
+        let r: u64 = rand::random();
+        r % 10000 < p
     }
 }
 
+/// Sets the probability of crashing at `may_crash!()` in 1/10000
+///
+/// See [`PoolOptions::crash_prob`](../alloc/struct.PoolOptions.html#method.crash_prob).
+pub fn set_crash_prob(prob: u64) {
+    CRASH_PROB.store(prob, Ordering::Relaxed);
+}
+
 #[inline]
 #[doc(hidden)]
 pub unsafe fn as_mut<'a, T: ?Sized>(v: *const T) -> &'a mut T {
@@ -292,12 +294,22 @@ impl Drop for SpinLock {
 }
 
 #[cfg(feature = "verbose")]
-pub static VERBOSE: crate::cell::LazyCell<bool> = crate::cell::LazyCell::new(||
-    if let Ok(val) = std::env::var("VERBOSE") {
-        val == "1"
-    } else {
-        false
-    });
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the logs should be printed
+#[cfg(feature = "verbose")]
+#[inline]
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// Enables printing the logs, if the `verbose` feature is enabled
+///
+/// See [`PoolOptions::verbose`](../alloc/struct.PoolOptions.html#method.verbose).
+pub fn set_verbose(_verbose: bool) {
+    #[cfg(feature = "verbose")]
+    VERBOSE.store(_verbose, Ordering::Relaxed);
+}
 
 #[macro_export]
 macro_rules! log {
@@ -306,7 +318,7 @@ macro_rules! log {
             use term_painter::Color::*;
             use term_painter::ToStyle;
 
-            if *$crate::utils::VERBOSE {
+            if $crate::utils::verbose() {
                 println!("{:<8} {}", $p::name().to_owned() + ":",
                     $c.paint(format!("{:>10}  {}", $tag, format!($msg, $($args)*))));
             }
@@ -317,7 +329,7 @@ macro_rules! log {
             use term_painter::Color::*;
             use term_painter::ToStyle;
 
-            if *$crate::utils::VERBOSE {
+            if $crate::utils::verbose() {
                 println!("{:<8} {}", "",
                     $c.paint(format!("{:>10}  {}", $tag, format!($msg, $($args)*))));
             }
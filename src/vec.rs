@@ -52,6 +52,7 @@ pub struct Vec<T: PSafe, A: MemPool> {
     buf: Slice<T, A>,
     len: usize,
     has_log: u8,
+    align: u8,
     marker: PhantomData<[T]>,
 }
 
@@ -146,12 +147,54 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         } else {
             let layout = Layout::array::<T>(cap).unwrap();
             unsafe {
-                let buf = A::new_uninit_for_layout(layout.size(), j);
+                let buf = A::new_uninit_typed(layout, std::any::type_name::<[T]>(), j);
                 Self::from_off_len(A::off_unchecked(buf), cap, 0)
             }
         }
     }
 
+    /// Creates an empty `Vec` with the specified capacity whose buffer is
+    /// aligned to `align`
+    ///
+    /// `align` should be a power of two not larger than [`MAX_ALIGN`]. The
+    /// alignment is kept when the vector is reallocated to grow or shrink.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use corundum::default::*;
+    /// # type P = Allocator;
+    /// # let _p = P::open_no_root("foo.pool", O_CF).unwrap();
+    /// P::transaction(|j| {
+    ///     let mut vec = PVec::<f32>::with_capacity_aligned(64, 256, j);
+    ///     assert_eq!((P::start() + vec.off()) % 256, 0);
+    ///
+    ///     // The reallocated buffer is aligned too
+    ///     vec.extend_from_slice(&[0.0; 100], j);
+    ///     assert_eq!((P::start() + vec.off()) % 256, 0);
+    /// }).unwrap();
+    /// ```
+    ///
+    /// [`MAX_ALIGN`]: ../alloc/constant.MAX_ALIGN.html
+    pub fn with_capacity_aligned(cap: usize, align: usize, j: &Journal<A>) -> Self {
+        assert!(align.is_power_of_two(), "`align` must be a power of two");
+        let mut vec = Self::empty();
+        vec.align = align.trailing_zeros() as u8;
+        if cap != 0 {
+            unsafe {
+                let buf = A::new_uninit_typed(vec.layout(cap), std::any::type_name::<[T]>(), j);
+                vec.buf = Slice::from_off_cap(A::off_unchecked(buf), cap);
+            }
+        }
+        vec
+    }
+
+    /// Returns the layout of a buffer for `cap` elements with the alignment
+    /// which the vector is created with
+    fn layout(&self, cap: usize) -> Layout {
+        Layout::array::<T>(cap).unwrap().align_to(1 << self.align).unwrap()
+    }
+
     /// Creates a `PVec<T>` directly from the raw components of another vector.
     ///
     /// # Safety
@@ -209,6 +252,7 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
             buf: Slice::<T,A>::from_off_cap(off, capacity),
             len: length,
             has_log: 0,
+            align: 0,
             marker: PhantomData
         }
     }
@@ -287,6 +331,7 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
             buf: Slice::from_off_cap(offset, capacity),
             len: length,
             has_log: 0,
+            align: 0,
             marker: PhantomData,
         }
     }
@@ -463,6 +508,7 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
         let new_cap = new_cap.max(self.len);
         if get_idx(new_cap * mem::size_of::<T>()) != get_idx(cap * mem::size_of::<T>()) {
             unsafe {
+                let layout = self.layout(new_cap);
                 let buf = self.to_slice_mut();
                 let (rem, left) = buf.split_at_mut(buf.len().min(new_cap));
                 if !left.is_empty() {
@@ -473,8 +519,10 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
                 if rem.is_empty() {
                     self.buf = Slice::null();
                 } else {
-                    let new = A::new_slice(rem, j);
-                    self.buf = Slice::from_off_cap(A::off_unchecked(new), new.len());
+                    let new: *mut T = A::new_uninit_typed(layout,
+                        std::any::type_name::<[T]>(), j).cast();
+                    ptr::copy_nonoverlapping(rem.as_ptr(), new, rem.len());
+                    self.buf = Slice::from_off_cap(A::off_unchecked(new), rem.len());
                 }
             }
         }
@@ -536,9 +584,9 @@ impl<T: PSafe, A: MemPool> Vec<T, A> {
             self.buf.set_cap(new_cap);
         } else {
             unsafe {
+                let layout = self.layout(new_cap);
                 let old = self.to_slice_mut();
-                let layout = Layout::array::<T>(new_cap).unwrap();
-                let new = A::new_uninit_for_layout(layout.size(), j).cast();
+                let new = A::new_uninit_typed(layout, std::any::type_name::<[T]>(), j).cast();
                 ptr::copy(old.as_ptr(), new, len);
                 A::free_slice(Self::__to_slice_mut(self.off(), self.capacity()));
                 self.buf = Slice::new(slice::from_raw_parts(new, new_cap));
@@ -1113,6 +1161,14 @@ impl<A: MemPool, T: PSafe + PClone<A>> PClone<A> for Vec<T, A> {
     }
 }
 
+impl<T: PSafe + Trace<A>, A: MemPool> Trace<A> for Vec<T, A> {
+    fn trace(&self, tracer: &mut Tracer<A>) {
+        if tracer.mark(self.off(), self.capacity() * mem::size_of::<T>()) {
+            self.as_slice().trace(tracer);
+        }
+    }
+}
+
 impl<A: MemPool, T: PSafe + Eq> Eq for Vec<T, A> {}
 
 /// Implements ordering of vectors, lexicographically.